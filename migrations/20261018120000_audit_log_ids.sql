@@ -0,0 +1,12 @@
+-- Add migration script here
+-- /xp add and /xp remove used to record `previous` as the new total plus the delta,
+-- rather than minus it, so correct those rows before reverts start relying on them
+UPDATE audit_logs SET previous = previous - 2 * delta WHERE NOT set AND NOT reset;
+
+ALTER TABLE audit_logs
+    ADD COLUMN id INT8 GENERATED ALWAYS AS IDENTITY PRIMARY KEY;
+
+ALTER TABLE audit_logs
+    ADD COLUMN reverts INT8;
+
+CREATE UNIQUE INDEX audit_logs_reverts_unique ON audit_logs (reverts) WHERE reverts IS NOT NULL;
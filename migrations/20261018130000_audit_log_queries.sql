@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE audit_logs
+    ADD COLUMN import BOOLEAN NOT NULL DEFAULT false;
+
+DROP INDEX IF EXISTS audit_logs_guild_id_idx;
+CREATE INDEX ON audit_logs(guild_id, timestamp);
+CREATE INDEX ON audit_logs(guild_id, moderator, timestamp);
+CREATE INDEX ON audit_logs(guild_id, user_id, timestamp);
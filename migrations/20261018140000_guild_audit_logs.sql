@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE guild_audit_logs (
+    id INT8 GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    guild_id INT8 NOT NULL,
+    actor INT8 NOT NULL,
+    timestamp INT8 NOT NULL,
+    kind TEXT NOT NULL,
+    before JSONB,
+    after JSONB
+);
+
+CREATE INDEX ON guild_audit_logs(guild_id, timestamp);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE custom_card ADD COLUMN animated_avatar BOOLEAN;
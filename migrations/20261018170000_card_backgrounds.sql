@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE card_backgrounds (
+    id INT8 PRIMARY KEY REFERENCES custom_card(id) ON DELETE CASCADE,
+    image BYTEA NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE custom_card
+    ALTER COLUMN username TYPE VARCHAR(32),
+    ALTER COLUMN rank TYPE VARCHAR(32),
+    ALTER COLUMN level TYPE VARCHAR(32),
+    ALTER COLUMN border TYPE VARCHAR(32),
+    ALTER COLUMN background TYPE VARCHAR(32),
+    ALTER COLUMN progress_foreground TYPE VARCHAR(32),
+    ALTER COLUMN progress_background TYPE VARCHAR(32),
+    ALTER COLUMN foreground_xp_count TYPE VARCHAR(32),
+    ALTER COLUMN background_xp_count TYPE VARCHAR(32);
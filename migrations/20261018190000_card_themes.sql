@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE TABLE card_themes (
+    code CHAR(8) PRIMARY KEY DEFAULT substr(md5(random()::text), 1, 8),
+    owner INT8 NOT NULL,
+    name VARCHAR(32) NOT NULL,
+    username VARCHAR(32) NOT NULL,
+    rank VARCHAR(32) NOT NULL,
+    level VARCHAR(32) NOT NULL,
+    border VARCHAR(32) NOT NULL,
+    background VARCHAR(32) NOT NULL,
+    progress_foreground VARCHAR(32) NOT NULL,
+    progress_background VARCHAR(32) NOT NULL,
+    foreground_xp_count VARCHAR(32) NOT NULL,
+    background_xp_count VARCHAR(32) NOT NULL,
+    font VARCHAR(64) NOT NULL,
+    card_layout VARCHAR(32) NOT NULL,
+    UNIQUE (owner, name)
+);
@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE guild_card_layouts (
+    id INT8 PRIMARY KEY,
+    template TEXT NOT NULL
+);
@@ -60,7 +60,10 @@ impl Interpolation {
     /// interpolation values from. Said values *must* be strings.
     /// # Errors
     /// If an interpolation value is not found, it is added to the [`RenderError`].
-    pub fn try_render(&self, args: &HashMap<Cow<str>, Cow<str>>) -> Result<String, RenderError> {
+    pub fn try_render(
+        &self,
+        args: &HashMap<Cow<str>, Cow<str>>,
+    ) -> Result<String, RenderError<'_>> {
         let mut output = self.output_string();
         for (raw, interpolation_key) in &self.parts {
             output.push_str(raw);
@@ -278,6 +281,7 @@ impl std::fmt::Display for RenderError<'_> {
 impl std::error::Error for RenderError<'_> {}
 
 #[cfg(test)]
+#[allow(clippy::literal_string_with_formatting_args)]
 mod tests {
     use std::collections::HashMap;
 
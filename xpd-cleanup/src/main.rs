@@ -115,6 +115,10 @@ async fn cleanup_guild(
     xpd_database::delete_guild_config(db.as_mut(), guild).await?;
     debug!(%guild, "Deleting guild card customizations");
     xpd_database::delete_card_customizations(db.as_mut(), guild.cast()).await?;
+    debug!(%guild, "Deleting guild saved card themes");
+    xpd_database::delete_card_themes(db.as_mut(), guild.cast()).await?;
+    debug!(%guild, "Deleting guild card layout");
+    xpd_database::delete_guild_card_layout(db.as_mut(), guild).await?;
     debug!(%guild, "Deleting guild rewards");
     let rewards = xpd_database::guild_rewards(db.as_mut(), guild).await?;
     debug!(%guild, count = rewards.len(), "Deleting guild rewards");
@@ -124,6 +128,8 @@ async fn cleanup_guild(
     }
     debug!(%guild, "Deleting guild levels");
     xpd_database::delete_levels_guild(db.as_mut(), guild).await?;
+    debug!(%guild, "Deleting guild settings audit logs");
+    xpd_database::delete_guild_audit_events_guild(db.as_mut(), guild).await?;
     debug!(%guild, "Acknowledging guild has been cleaned up");
     xpd_database::delete_guild_cleanup(db.as_mut(), guild).await?;
     Ok(())
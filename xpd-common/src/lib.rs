@@ -131,15 +131,41 @@ pub const DEFAULT_MIN_XP_PER_MESSAGE: i16 = 15;
 pub const DEFAULT_MESSAGE_COOLDOWN: i16 = 60;
 pub const MAX_MESSAGE_COOLDOWN: i16 = 28800;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct GuildConfig {
     pub one_at_a_time: Option<bool>,
+    #[serde(
+        serialize_with = "serialize_interpolation",
+        deserialize_with = "deserialize_interpolation"
+    )]
     pub level_up_message: Option<Interpolation>,
     pub level_up_channel: Option<Id<ChannelMarker>>,
     pub ping_on_level_up: Option<bool>,
     pub min_xp_per_message: Option<i16>,
     pub max_xp_per_message: Option<i16>,
     pub cooldown: Option<i16>,
+    pub log_channel: Option<Id<ChannelMarker>>,
+}
+
+#[allow(clippy::ref_option)]
+fn serialize_interpolation<S: serde::Serializer>(
+    interpolation: &Option<Interpolation>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(
+        &interpolation.as_ref().map(Interpolation::input_value),
+        serializer,
+    )
+}
+
+fn deserialize_interpolation<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Interpolation>, D::Error> {
+    let input: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    input
+        .map(Interpolation::new)
+        .transpose()
+        .map_err(serde::de::Error::custom)
 }
 
 impl Display for GuildConfig {
@@ -180,11 +206,18 @@ impl Display for GuildConfig {
             self.min_xp_per_message
                 .unwrap_or(DEFAULT_MIN_XP_PER_MESSAGE)
         )?;
-        write!(
+        writeln!(
             f,
             "Cooldown (seconds): {}",
             self.cooldown.unwrap_or(DEFAULT_MESSAGE_COOLDOWN)
         )?;
+        write!(
+            f,
+            "Audit log channel: {}",
+            self.log_channel.map_or(Cow::Borrowed("unset"), |v| {
+                Cow::Owned(format!("`<#{v}>`"))
+            })
+        )?;
         Ok(())
     }
 }
@@ -199,6 +232,194 @@ pub struct AuditLogEvent {
     pub delta: i64,
     pub reset: bool,
     pub set: bool,
+    /// The ID of the audit log entry this event undid, if it was created by `/audit revert`.
+    pub reverts: Option<i64>,
+    /// Whether this event was created by `/manage import`
+    pub import: bool,
+}
+
+impl AuditLogEvent {
+    #[must_use]
+    pub const fn action(&self) -> AuditLogAction {
+        if self.reverts.is_some() {
+            AuditLogAction::Revert
+        } else if self.import {
+            AuditLogAction::Import
+        } else if self.reset {
+            AuditLogAction::Reset
+        } else if self.set {
+            AuditLogAction::Set
+        } else if self.delta.is_negative() {
+            AuditLogAction::Remove
+        } else {
+            AuditLogAction::Add
+        }
+    }
+}
+
+/// The kind of action an [`AuditLogEvent`] records.
+/// [`AuditLogAction::name`] must match the `CASE` expression used to filter audit logs in `xpd-database`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AuditLogAction {
+    Add,
+    Remove,
+    Set,
+    Reset,
+    Import,
+    Revert,
+}
+
+impl AuditLogAction {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Remove => "remove",
+            Self::Set => "set",
+            Self::Reset => "reset",
+            Self::Import => "import",
+            Self::Revert => "revert",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let action = match name {
+            "add" => Self::Add,
+            "remove" => Self::Remove,
+            "set" => Self::Set,
+            "reset" => Self::Reset,
+            "import" => Self::Import,
+            "revert" => Self::Revert,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// An [`AuditLogEvent`] as stored in the database, along with its ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub guild_id: Id<GuildMarker>,
+    pub user_id: Id<UserMarker>,
+    pub moderator: Id<UserMarker>,
+    pub timestamp: i64,
+    pub previous: i64,
+    pub delta: i64,
+    pub reset: bool,
+    pub set: bool,
+    pub reverts: Option<i64>,
+    pub import: bool,
+}
+
+impl AuditLogEntry {
+    #[must_use]
+    pub const fn event(&self) -> AuditLogEvent {
+        AuditLogEvent {
+            guild_id: self.guild_id,
+            user_id: self.user_id,
+            moderator: self.moderator,
+            timestamp: self.timestamp,
+            previous: self.previous,
+            delta: self.delta,
+            reset: self.reset,
+            set: self.set,
+            reverts: self.reverts,
+            import: self.import,
+        }
+    }
+
+    #[must_use]
+    pub const fn action(&self) -> AuditLogAction {
+        self.event().action()
+    }
+}
+
+/// A change to server-wide settings or data, rather than to one user's XP.
+/// `before` and `after` are JSON snapshots of whatever was changed, and are `None` if it did not exist.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GuildAuditEvent {
+    pub guild_id: Id<GuildMarker>,
+    pub actor: Id<UserMarker>,
+    pub timestamp: i64,
+    pub kind: GuildAuditKind,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// A [`GuildAuditEvent`] as stored in the database, along with its ID.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GuildAuditEntry {
+    pub id: i64,
+    pub guild_id: Id<GuildMarker>,
+    pub actor: Id<UserMarker>,
+    pub timestamp: i64,
+    pub kind: GuildAuditKind,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl GuildAuditEntry {
+    #[must_use]
+    pub fn event(&self) -> GuildAuditEvent {
+        GuildAuditEvent {
+            guild_id: self.guild_id,
+            actor: self.actor,
+            timestamp: self.timestamp,
+            kind: self.kind,
+            before: self.before.clone(),
+            after: self.after.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuildAuditKind {
+    ConfigUpdate,
+    ConfigReset,
+    RewardAdd,
+    RewardRemove,
+    GuildCardUpdate,
+    GuildCardReset,
+    Import,
+    GuildReset,
+    Restore,
+}
+
+impl GuildAuditKind {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ConfigUpdate => "config_update",
+            Self::ConfigReset => "config_reset",
+            Self::RewardAdd => "reward_add",
+            Self::RewardRemove => "reward_remove",
+            Self::GuildCardUpdate => "guild_card_update",
+            Self::GuildCardReset => "guild_card_reset",
+            Self::Import => "import",
+            Self::GuildReset => "guild_reset",
+            Self::Restore => "restore",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let kind = match name {
+            "config_update" => Self::ConfigUpdate,
+            "config_reset" => Self::ConfigReset,
+            "reward_add" => Self::RewardAdd,
+            "reward_remove" => Self::RewardRemove,
+            "guild_card_update" => Self::GuildCardUpdate,
+            "guild_card_reset" => Self::GuildCardReset,
+            "import" => Self::Import,
+            "guild_reset" => Self::GuildReset,
+            "restore" => Self::Restore,
+            _ => return None,
+        };
+        Some(kind)
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -208,13 +429,20 @@ pub struct UserStatus {
     pub xp: i64,
 }
 
+/// When a user last earned XP from a message in a guild.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct UserCooldown {
+    pub user: Id<UserMarker>,
+    pub last_message: i64,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct UserInGuild {
     pub guild: Id<GuildMarker>,
     pub user: Id<UserMarker>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct RoleReward {
     pub id: Id<RoleMarker>,
     pub requirement: i64,
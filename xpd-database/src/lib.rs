@@ -21,7 +21,10 @@ use twilight_model::id::{
     Id,
 };
 use util::{db_to_id, id_to_db};
-use xpd_common::{AuditLogEvent, GuildConfig, RoleReward, UserInGuild, UserStatus};
+use xpd_common::{
+    AuditLogAction, AuditLogEntry, AuditLogEvent, GuildAuditEntry, GuildAuditEvent, GuildAuditKind,
+    GuildConfig, RoleReward, UserCooldown, UserInGuild, UserStatus,
+};
 pub async fn guild_rewards<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -58,7 +61,7 @@ pub async fn guild_config<
     let config = query_as!(
         RawGuildConfig,
         "SELECT one_at_a_time, level_up_message, level_up_channel, ping_on_level_up,\
-                 max_xp_per_message, min_xp_per_message, message_cooldown \
+                 max_xp_per_message, min_xp_per_message, message_cooldown, log_channel \
                  FROM guild_configs WHERE id = $1",
         id_to_db(guild)
     )
@@ -173,6 +176,8 @@ pub async fn set_cooldown<
     Ok(output)
 }
 
+/// Record an XP audit log event, returning its ID. Fails with [`Error::AlreadyReverted`]
+/// if `event.reverts` names an entry that another event has already reverted.
 pub async fn add_audit_log_event<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -180,13 +185,13 @@ pub async fn add_audit_log_event<
 >(
     conn: A,
     event: AuditLogEvent,
-) -> Result<(), Error> {
+) -> Result<i64, Error> {
     let mut conn = conn.acquire().await?;
-    query!(
+    let id = query!(
         "INSERT INTO audit_logs \
             (guild_id, user_id, moderator,
-                timestamp, previous, delta, reset, set)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                timestamp, previous, delta, reset, set, reverts, import)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id",
         id_to_db(event.guild_id),
         id_to_db(event.user_id),
         id_to_db(event.moderator),
@@ -194,13 +199,26 @@ pub async fn add_audit_log_event<
         event.previous,
         event.delta,
         event.reset,
-        event.set
+        event.set,
+        event.reverts,
+        event.import
     )
-    .execute(conn.as_mut())
-    .await?;
-    Ok(())
+    .fetch_one(conn.as_mut())
+    .await
+    .map_err(|source| match &source {
+        sqlx::Error::Database(db_error)
+            if db_error.constraint() == Some("audit_logs_reverts_unique") =>
+        {
+            Error::AlreadyReverted
+        }
+        _ => Error::Database(source),
+    })?
+    .id;
+    Ok(id)
 }
 
+/// Fetch audit log entries matching `filter`, newest first.
+/// A `limit` of `None` fetches every matching entry.
 pub async fn get_audit_log_events<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -208,41 +226,271 @@ pub async fn get_audit_log_events<
 >(
     conn: A,
     guild_id: Id<GuildMarker>,
-    actions_on_user: Option<Id<UserMarker>>,
-    actions_by_moderator: Option<Id<UserMarker>>,
-) -> Result<Vec<AuditLogEvent>, Error> {
-    let mut conn = conn.acquire().await?;
-    let mut stream = query!(
-        "SELECT user_id, moderator,
-        timestamp, previous, delta, reset, set
-        FROM audit_logs WHERE guild_id = $1",
-        id_to_db(guild_id)
+    filter: AuditLogFilter,
+    limit: Option<i64>,
+    offset: i64,
+) -> Result<Vec<AuditLogEntry>, Error> {
+    let mut conn = conn.acquire().await?;
+    let logs = query!(
+        "SELECT id, user_id, moderator,
+        timestamp, previous, delta, reset, set, reverts, import
+        FROM audit_logs WHERE guild_id = $1
+            AND ($2::INT8 IS NULL OR user_id = $2)
+            AND ($3::INT8 IS NULL OR moderator = $3)
+            AND ($4::INT8 IS NULL OR timestamp >= $4)
+            AND ($5::INT8 IS NULL OR timestamp <= $5)
+            AND ($6::TEXT IS NULL OR $6 = CASE
+                WHEN reverts IS NOT NULL THEN 'revert'
+                WHEN import THEN 'import'
+                WHEN reset THEN 'reset'
+                WHEN set THEN 'set'
+                WHEN delta < 0 THEN 'remove'
+                ELSE 'add' END)
+        ORDER BY timestamp DESC, id DESC
+        LIMIT $7 OFFSET $8",
+        id_to_db(guild_id),
+        filter.user.map(id_to_db),
+        filter.moderator.map(id_to_db),
+        filter.since,
+        filter.until,
+        filter.action.map(AuditLogAction::name),
+        limit,
+        offset
     )
-    .fetch(conn.as_mut());
-    let mut logs = Vec::new();
-    while let Some(row) = stream.next().await.transpose()? {
-        let user_id = db_to_id(row.user_id);
-        let moderator = db_to_id(row.moderator);
-        if actions_by_moderator.is_some_and(|requested_moderator| requested_moderator != moderator)
-            || actions_on_user.is_some_and(|requested_user| requested_user != user_id)
-        {
-            continue;
-        }
-        let log = AuditLogEvent {
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| AuditLogEntry {
+        id: row.id,
+        guild_id,
+        user_id: db_to_id(row.user_id),
+        moderator: db_to_id(row.moderator),
+        timestamp: row.timestamp,
+        previous: row.previous,
+        delta: row.delta,
+        reset: row.reset,
+        set: row.set,
+        reverts: row.reverts,
+        import: row.import,
+    })
+    .collect();
+    Ok(logs)
+}
+
+pub async fn count_audit_log_events<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild_id: Id<GuildMarker>,
+    filter: AuditLogFilter,
+) -> Result<i64, Error> {
+    let mut conn = conn.acquire().await?;
+    let count = query!(
+        "SELECT COUNT(*) AS \"count!\" FROM audit_logs WHERE guild_id = $1
+            AND ($2::INT8 IS NULL OR user_id = $2)
+            AND ($3::INT8 IS NULL OR moderator = $3)
+            AND ($4::INT8 IS NULL OR timestamp >= $4)
+            AND ($5::INT8 IS NULL OR timestamp <= $5)
+            AND ($6::TEXT IS NULL OR $6 = CASE
+                WHEN reverts IS NOT NULL THEN 'revert'
+                WHEN import THEN 'import'
+                WHEN reset THEN 'reset'
+                WHEN set THEN 'set'
+                WHEN delta < 0 THEN 'remove'
+                ELSE 'add' END)",
+        id_to_db(guild_id),
+        filter.user.map(id_to_db),
+        filter.moderator.map(id_to_db),
+        filter.since,
+        filter.until,
+        filter.action.map(AuditLogAction::name),
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .count;
+    Ok(count)
+}
+
+pub async fn add_guild_audit_event<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    event: GuildAuditEvent,
+) -> Result<i64, Error> {
+    let mut conn = conn.acquire().await?;
+    let id = query!(
+        "INSERT INTO guild_audit_logs (guild_id, actor, timestamp, kind, before, after) \
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+        id_to_db(event.guild_id),
+        id_to_db(event.actor),
+        event.timestamp,
+        event.kind.name(),
+        event.before,
+        event.after
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .id;
+    Ok(id)
+}
+
+/// Fetch guild audit log entries matching `filter`, newest first.
+/// A `limit` of `None` fetches every matching entry.
+pub async fn get_guild_audit_events<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild_id: Id<GuildMarker>,
+    filter: GuildAuditFilter,
+    limit: Option<i64>,
+    offset: i64,
+) -> Result<Vec<GuildAuditEntry>, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "SELECT id, actor, timestamp, kind, before, after
+        FROM guild_audit_logs WHERE guild_id = $1
+            AND ($2::INT8 IS NULL OR actor = $2)
+            AND ($3::INT8 IS NULL OR timestamp >= $3)
+            AND ($4::INT8 IS NULL OR timestamp <= $4)
+            AND ($5::TEXT IS NULL OR kind = $5)
+        ORDER BY timestamp DESC, id DESC
+        LIMIT $6 OFFSET $7",
+        id_to_db(guild_id),
+        filter.actor.map(id_to_db),
+        filter.since,
+        filter.until,
+        filter.kind.map(GuildAuditKind::name),
+        limit,
+        offset
+    )
+    .fetch_all(conn.as_mut())
+    .await?;
+    let mut logs = Vec::with_capacity(rows.len());
+    for row in rows {
+        let kind = GuildAuditKind::from_name(&row.kind).ok_or(Error::UnknownAuditKind(row.kind))?;
+        logs.push(GuildAuditEntry {
+            id: row.id,
             guild_id,
-            user_id,
-            moderator: db_to_id(row.moderator),
+            actor: db_to_id(row.actor),
             timestamp: row.timestamp,
-            previous: row.previous,
-            delta: row.delta,
-            reset: row.reset,
-            set: row.set,
-        };
-        logs.push(log);
+            kind,
+            before: row.before,
+            after: row.after,
+        });
     }
     Ok(logs)
 }
 
+pub async fn count_guild_audit_events<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild_id: Id<GuildMarker>,
+    filter: GuildAuditFilter,
+) -> Result<i64, Error> {
+    let mut conn = conn.acquire().await?;
+    let count = query!(
+        "SELECT COUNT(*) AS \"count!\" FROM guild_audit_logs WHERE guild_id = $1
+            AND ($2::INT8 IS NULL OR actor = $2)
+            AND ($3::INT8 IS NULL OR timestamp >= $3)
+            AND ($4::INT8 IS NULL OR timestamp <= $4)
+            AND ($5::TEXT IS NULL OR kind = $5)",
+        id_to_db(guild_id),
+        filter.actor.map(id_to_db),
+        filter.since,
+        filter.until,
+        filter.kind.map(GuildAuditKind::name),
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .count;
+    Ok(count)
+}
+
+pub async fn delete_guild_audit_events_guild<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild_id: Id<GuildMarker>,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "DELETE FROM guild_audit_logs WHERE guild_id = $1",
+        id_to_db(guild_id)
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+pub async fn get_audit_log_entry<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild_id: Id<GuildMarker>,
+    id: i64,
+) -> Result<Option<AuditLogEntry>, Error> {
+    let mut conn = conn.acquire().await?;
+    let entry = query!(
+        "SELECT id, user_id, moderator,
+        timestamp, previous, delta, reset, set, reverts, import
+        FROM audit_logs WHERE guild_id = $1 AND id = $2",
+        id_to_db(guild_id),
+        id
+    )
+    .fetch_optional(conn.as_mut())
+    .await?
+    .map(|row| AuditLogEntry {
+        id: row.id,
+        guild_id,
+        user_id: db_to_id(row.user_id),
+        moderator: db_to_id(row.moderator),
+        timestamp: row.timestamp,
+        previous: row.previous,
+        delta: row.delta,
+        reset: row.reset,
+        set: row.set,
+        reverts: row.reverts,
+        import: row.import,
+    });
+    Ok(entry)
+}
+
+/// Get the IDs of every audit log entry in this guild which has already been reverted.
+pub async fn reverted_audit_log_entries<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild_id: Id<GuildMarker>,
+) -> Result<Vec<i64>, Error> {
+    let mut conn = conn.acquire().await?;
+    let reverted = query!(
+        "SELECT reverts AS \"reverts!\" FROM audit_logs \
+            WHERE guild_id = $1 AND reverts IS NOT NULL",
+        id_to_db(guild_id)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| row.reverts)
+    .collect();
+    Ok(reverted)
+}
+
 pub async fn delete_audit_log_events_guild<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -376,6 +624,31 @@ pub async fn count_with_higher_xp<
     Ok(count)
 }
 
+/// Like [`count_with_higher_xp`], but only counting `users`
+pub async fn count_among_with_higher_xp<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    users: &[Id<UserMarker>],
+    xp: i64,
+) -> Result<Option<i64>, Error> {
+    let mut conn = conn.acquire().await?;
+    let ids: Vec<i64> = users.iter().map(|id| id_to_db(*id)).collect();
+    let count = query!(
+        "SELECT COUNT(*) as count FROM levels WHERE xp > $1 AND guild = $2 AND id = ANY($3)",
+        xp,
+        id_to_db(guild),
+        &ids
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .count;
+    Ok(count)
+}
+
 pub async fn levels_in_guild<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -498,6 +771,305 @@ pub async fn delete_card_customizations<
     Ok(())
 }
 
+pub async fn card_background<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    targets: &[Id<GenericMarker>],
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut conn = conn.acquire().await?;
+    let targets: Vec<i64> = targets.iter().copied().map(id_to_db).collect();
+    // The background belongs to whichever card customizations win, so a user card
+    // without a background hides the guild's background.
+    let data = query!(
+        "SELECT card_backgrounds.image AS \"image?\" FROM UNNEST($1::INT8[]) WITH ORDINALITY \
+                AS ordering_ids(ord_id, ordinality) \
+                INNER JOIN custom_card ON ordering_ids.ord_id = custom_card.id \
+                LEFT JOIN card_backgrounds ON card_backgrounds.id = custom_card.id \
+                ORDER BY ordering_ids.ordinality \
+                LIMIT 1",
+        &targets
+    )
+    .fetch_optional(conn.as_mut())
+    .await?;
+    Ok(data.and_then(|row| row.image))
+}
+
+pub async fn set_card_background<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    target: Id<GenericMarker>,
+    image: &[u8],
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "INSERT INTO card_backgrounds (id, image) VALUES ($1, $2) \
+                ON CONFLICT (id) DO UPDATE SET image = excluded.image",
+        id_to_db(target),
+        image
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_card_background<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    target: Id<GenericMarker>,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "DELETE FROM card_backgrounds WHERE id = $1",
+        id_to_db(target)
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+pub async fn save_card_theme<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    owner: Id<GenericMarker>,
+    theme: &CardTheme,
+) -> Result<String, Error> {
+    let mut conn = conn.acquire().await?;
+    let mut attempt = 1;
+    loop {
+        // A savepoint, so a clashing code doesn't abort the caller's transaction
+        let mut txn = conn.begin().await?;
+        match insert_card_theme(txn.as_mut(), owner, theme).await {
+            Ok(code) => {
+                txn.commit().await?;
+                return Ok(code);
+            }
+            Err(sqlx::Error::Database(db_error))
+                if db_error.constraint() == Some("card_themes_pkey")
+                    && attempt < CARD_THEME_CODE_ATTEMPTS =>
+            {
+                attempt += 1;
+            }
+            Err(source) => return Err(source.into()),
+        }
+    }
+}
+
+/// Share codes are 8 random hex digits, so they can clash once there are enough themes
+const CARD_THEME_CODE_ATTEMPTS: usize = 5;
+
+async fn insert_card_theme(
+    conn: &mut PgConnection,
+    owner: Id<GenericMarker>,
+    theme: &CardTheme,
+) -> Result<String, sqlx::Error> {
+    // Saving over a theme with the same name keeps its code, so shared codes stay valid
+    let code = query!(
+        "INSERT INTO card_themes (
+                owner,
+                name,
+                username,
+                rank,
+                level,
+                border,
+                background,
+                progress_foreground,
+                progress_background,
+                foreground_xp_count,
+                background_xp_count,
+                font,
+                card_layout
+            ) VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13
+            ) ON CONFLICT (owner, name) DO UPDATE SET
+                username = excluded.username,
+                rank = excluded.rank,
+                level = excluded.level,
+                border = excluded.border,
+                background = excluded.background,
+                progress_foreground = excluded.progress_foreground,
+                progress_background = excluded.progress_background,
+                foreground_xp_count = excluded.foreground_xp_count,
+                background_xp_count = excluded.background_xp_count,
+                font = excluded.font,
+                card_layout = excluded.card_layout
+            RETURNING code",
+        id_to_db(owner),
+        theme.name,
+        theme.username,
+        theme.rank,
+        theme.level,
+        theme.border,
+        theme.background,
+        theme.progress_foreground,
+        theme.progress_background,
+        theme.foreground_xp_count,
+        theme.background_xp_count,
+        theme.font,
+        theme.card_layout
+    )
+    .fetch_one(conn)
+    .await?
+    .code;
+    Ok(code)
+}
+
+pub async fn card_themes<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    owner: Id<GenericMarker>,
+) -> Result<Vec<SavedCardTheme>, Error> {
+    let mut conn = conn.acquire().await?;
+    let themes = query_as!(
+        RawSavedCardTheme,
+        "SELECT code, name, username, rank, level, border, background, \
+                progress_foreground, progress_background, foreground_xp_count, \
+                background_xp_count, font, card_layout \
+                FROM card_themes WHERE owner = $1 ORDER BY name",
+        id_to_db(owner)
+    )
+    .fetch_all(conn.as_mut())
+    .await?;
+    Ok(themes.into_iter().map(RawSavedCardTheme::cook).collect())
+}
+
+/// Find one of `owner`'s themes by name, or anyone's theme by its code.
+/// The owner's own themes win if a name looks like a code.
+pub async fn find_card_theme<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    owner: Id<GenericMarker>,
+    name_or_code: &str,
+) -> Result<Option<SavedCardTheme>, Error> {
+    let mut conn = conn.acquire().await?;
+    let theme = query_as!(
+        RawSavedCardTheme,
+        "SELECT code, name, username, rank, level, border, background, \
+                progress_foreground, progress_background, foreground_xp_count, \
+                background_xp_count, font, card_layout \
+                FROM card_themes WHERE (owner = $1 AND name = $2) OR code = LOWER($2) \
+                ORDER BY owner = $1 DESC LIMIT 1",
+        id_to_db(owner),
+        name_or_code
+    )
+    .fetch_optional(conn.as_mut())
+    .await?;
+    Ok(theme.map(RawSavedCardTheme::cook))
+}
+
+pub async fn delete_card_theme<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    owner: Id<GenericMarker>,
+    name: &str,
+) -> Result<bool, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM card_themes WHERE owner = $1 AND name = $2",
+        id_to_db(owner),
+        name
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows > 0)
+}
+
+pub async fn delete_card_themes<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    owner: Id<GenericMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!("DELETE FROM card_themes WHERE owner = $1", id_to_db(owner))
+        .execute(conn.as_mut())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn guild_card_layout<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<Option<String>, Error> {
+    let mut conn = conn.acquire().await?;
+    let template = query!(
+        "SELECT template FROM guild_card_layouts WHERE id = $1",
+        id_to_db(guild)
+    )
+    .fetch_optional(conn.as_mut())
+    .await?
+    .map(|row| row.template);
+    Ok(template)
+}
+
+pub async fn set_guild_card_layout<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    template: &str,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "INSERT INTO guild_card_layouts (id, template) VALUES ($1, $2) \
+                ON CONFLICT (id) DO UPDATE SET template = excluded.template",
+        id_to_db(guild),
+        template
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_guild_card_layout<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<bool, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM guild_card_layouts WHERE id = $1",
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows > 0)
+}
+
 pub async fn delete_levels_user<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -615,9 +1187,10 @@ pub async fn update_card<
                 font,
                 toy_image,
                 card_layout,
+                animated_avatar,
                 id
             ) VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, COALESCE($12, $13), $14
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, COALESCE($12, $13), $14, $15
             ) ON CONFLICT (id) DO UPDATE SET
                 username = COALESCE($1, custom_card.username),
                 rank = COALESCE($2, custom_card.rank),
@@ -630,7 +1203,8 @@ pub async fn update_card<
                 background_xp_count = COALESCE($9, custom_card.background_xp_count),
                 font = COALESCE($10, custom_card.font),
                 toy_image = COALESCE($11, custom_card.toy_image),
-                card_layout = COALESCE($12, custom_card.card_layout, $13)",
+                card_layout = COALESCE($12, custom_card.card_layout, $13),
+                animated_avatar = COALESCE($14, custom_card.animated_avatar)",
         update.username,
         update.rank,
         update.level,
@@ -644,6 +1218,7 @@ pub async fn update_card<
         update.toy_image,
         update.card_layout,
         update.card_layout_default,
+        update.animated_avatar,
         id_to_db(id)
     )
     .execute(conn.as_mut())
@@ -663,8 +1238,8 @@ pub async fn update_guild_config<
     let mut conn = conn.acquire().await?;
     let config = query_as!(
                 RawGuildConfig,
-                "INSERT INTO guild_configs (id, level_up_message, level_up_channel, ping_on_level_up, max_xp_per_message, min_xp_per_message, message_cooldown, one_at_a_time) \
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+                "INSERT INTO guild_configs (id, level_up_message, level_up_channel, ping_on_level_up, max_xp_per_message, min_xp_per_message, message_cooldown, one_at_a_time, log_channel) \
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
                 ON CONFLICT (id) DO UPDATE SET \
                 level_up_message = COALESCE($2, guild_configs.level_up_message), \
                 level_up_channel = COALESCE($3, guild_configs.level_up_channel), \
@@ -672,9 +1247,10 @@ pub async fn update_guild_config<
                 max_xp_per_message = COALESCE($5, guild_configs.max_xp_per_message), \
                 min_xp_per_message = COALESCE($6, guild_configs.min_xp_per_message), \
                 message_cooldown = COALESCE($7, guild_configs.message_cooldown), \
-                one_at_a_time = COALESCE($8, guild_configs.one_at_a_time) \
+                one_at_a_time = COALESCE($8, guild_configs.one_at_a_time), \
+                log_channel = COALESCE($9, guild_configs.log_channel) \
                 RETURNING one_at_a_time, level_up_message, level_up_channel, ping_on_level_up, \
-                max_xp_per_message, min_xp_per_message, message_cooldown",
+                max_xp_per_message, min_xp_per_message, message_cooldown, log_channel",
                 id_to_db(guild),
                 cfg.level_up_message.map(|v| v),
                 cfg.level_up_channel.as_ref().map(|id| id_to_db(*id)),
@@ -682,7 +1258,8 @@ pub async fn update_guild_config<
                 cfg.max_xp_per_message,
                 cfg.min_xp_per_message,
                 cfg.message_cooldown,
-                cfg.one_at_a_time
+                cfg.one_at_a_time,
+                cfg.log_channel.map(id_to_db)
             )
         .fetch_one(conn.as_mut())
         .await?
@@ -690,6 +1267,31 @@ pub async fn update_guild_config<
     Ok(config)
 }
 
+/// Stop mirroring audit events to a log channel.
+/// Returns the updated config, or `None` if the guild had no config to update.
+pub async fn clear_guild_log_channel<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<Option<GuildConfig>, Error> {
+    let mut conn = conn.acquire().await?;
+    let config = query_as!(
+        RawGuildConfig,
+        "UPDATE guild_configs SET log_channel = NULL WHERE id = $1 \
+            RETURNING one_at_a_time, level_up_message, level_up_channel, ping_on_level_up, \
+            max_xp_per_message, min_xp_per_message, message_cooldown, log_channel",
+        id_to_db(guild)
+    )
+    .fetch_optional(conn.as_mut())
+    .await?
+    .map(RawGuildConfig::cook)
+    .transpose()?;
+    Ok(config)
+}
+
 pub async fn delete_guild_config<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -868,6 +1470,40 @@ pub async fn get_leaderboard_page<
     Ok(output)
 }
 
+/// Like [`get_leaderboard_page`], but only ranking `users`
+pub async fn get_leaderboard_page_among<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    users: &[Id<UserMarker>],
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<UserStatus>, Error> {
+    let mut conn = conn.acquire().await?;
+    let ids: Vec<i64> = users.iter().map(|id| id_to_db(*id)).collect();
+    let output = query!(
+        "SELECT id, xp FROM levels WHERE guild = $1 AND id = ANY($2) \
+        ORDER BY (xp, id) DESC LIMIT $3 OFFSET $4",
+        id_to_db(guild),
+        &ids,
+        limit,
+        offset
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|rec| UserStatus {
+        id: db_to_id(rec.id),
+        guild,
+        xp: rec.xp,
+    })
+    .collect();
+    Ok(output)
+}
+
 pub async fn add_reward_role<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -946,6 +1582,230 @@ pub async fn export_bulk_users<
     Ok(out)
 }
 
+/// Set the XP of many users in a guild at once, overwriting any XP they already had.
+/// Returns the number of rows written.
+pub async fn bulk_set_xp<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    users: &[(Id<UserMarker>, i64)],
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let ids: Vec<i64> = users.iter().map(|(id, _)| id_to_db(*id)).collect();
+    let xps: Vec<i64> = users.iter().map(|(_, xp)| *xp).collect();
+    let rows = query!(
+        "INSERT INTO levels (id, guild, xp) \
+            SELECT id, $1, xp FROM UNNEST($2::INT8[], $3::INT8[]) AS new_levels(id, xp) \
+            ON CONFLICT (id, guild) DO UPDATE SET xp = excluded.xp",
+        id_to_db(guild),
+        &ids,
+        &xps
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Add XP to many users in a guild at once. Each user may only appear once.
+/// Returns each user's XP after the addition, in no particular order.
+pub async fn bulk_add_xp<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    users: &[(Id<UserMarker>, i64)],
+) -> Result<Vec<(Id<UserMarker>, i64)>, Error> {
+    let mut conn = conn.acquire().await?;
+    let ids: Vec<i64> = users.iter().map(|(id, _)| id_to_db(*id)).collect();
+    let xps: Vec<i64> = users.iter().map(|(_, xp)| *xp).collect();
+    let rows = query!(
+        "INSERT INTO levels (id, guild, xp) \
+            SELECT id, $1, xp FROM UNNEST($2::INT8[], $3::INT8[]) AS new_levels(id, xp) \
+            ON CONFLICT (id, guild) DO UPDATE SET xp = levels.xp + excluded.xp \
+            RETURNING id, xp",
+        id_to_db(guild),
+        &ids,
+        &xps
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| (db_to_id(row.id), row.xp))
+    .collect();
+    Ok(rows)
+}
+
+/// Fetch the XP of many users in a guild, locking their rows until the transaction ends.
+/// Users without any XP are left out.
+pub async fn bulk_user_xp<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    users: &[Id<UserMarker>],
+) -> Result<Vec<(Id<UserMarker>, i64)>, Error> {
+    let mut conn = conn.acquire().await?;
+    let ids: Vec<i64> = users.iter().map(|id| id_to_db(*id)).collect();
+    let rows = query!(
+        "SELECT id, xp FROM levels WHERE guild = $1 AND id = ANY($2) FOR UPDATE",
+        id_to_db(guild),
+        &ids
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| (db_to_id(row.id), row.xp))
+    .collect();
+    Ok(rows)
+}
+
+/// Insert many audit log events at once. Returns the number of events written.
+pub async fn bulk_add_audit_log_events<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    events: &[AuditLogEvent],
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let column = |f: fn(&AuditLogEvent) -> i64| -> Vec<i64> { events.iter().map(f).collect() };
+    let flag = |f: fn(&AuditLogEvent) -> bool| -> Vec<bool> { events.iter().map(f).collect() };
+    let reverts: Vec<Option<i64>> = events.iter().map(|event| event.reverts).collect();
+    let rows = query!(
+        "INSERT INTO audit_logs \
+            (guild_id, user_id, moderator,
+                timestamp, previous, delta, reset, set, reverts, import)
+             SELECT * FROM UNNEST($1::INT8[], $2::INT8[], $3::INT8[], $4::INT8[], $5::INT8[], \
+                $6::INT8[], $7::BOOL[], $8::BOOL[], $9::INT8[], $10::BOOL[])",
+        &column(|event| id_to_db(event.guild_id)),
+        &column(|event| id_to_db(event.user_id)),
+        &column(|event| id_to_db(event.moderator)),
+        &column(|event| event.timestamp),
+        &column(|event| event.previous),
+        &column(|event| event.delta),
+        &flag(|event| event.reset),
+        &flag(|event| event.set),
+        &reverts as &[Option<i64>],
+        &flag(|event| event.import),
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+pub async fn guild_cooldowns<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<Vec<UserCooldown>, Error> {
+    let mut conn = conn.acquire().await?;
+    let cooldowns = query!(
+        "SELECT user_id, last_message FROM cooldowns WHERE guild_id = $1",
+        id_to_db(guild)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| UserCooldown {
+        user: db_to_id(row.user_id),
+        last_message: row.last_message,
+    })
+    .collect();
+    Ok(cooldowns)
+}
+
+/// Overwrite the cooldown state of many users in a guild at once.
+pub async fn bulk_set_cooldowns<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    cooldowns: &[UserCooldown],
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let users: Vec<i64> = cooldowns.iter().map(|v| id_to_db(v.user)).collect();
+    let last_messages: Vec<i64> = cooldowns.iter().map(|v| v.last_message).collect();
+    let rows = query!(
+        "INSERT INTO cooldowns (guild_id, user_id, last_message) \
+            SELECT $1, user_id, last_message \
+            FROM UNNEST($2::INT8[], $3::INT8[]) AS new_cooldowns(user_id, last_message) \
+            ON CONFLICT (guild_id, user_id) DO UPDATE SET last_message = excluded.last_message",
+        id_to_db(guild),
+        &users,
+        &last_messages
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+pub async fn delete_guild_cooldowns<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!("DELETE FROM cooldowns WHERE guild_id = $1", id_to_db(guild))
+        .execute(conn.as_mut())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn delete_guild_rewards<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!("DELETE FROM role_rewards WHERE guild = $1", id_to_db(guild))
+        .execute(conn.as_mut())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+/// Constraints on which guild audit log entries to fetch. Timestamps are inclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GuildAuditFilter {
+    pub actor: Option<Id<UserMarker>>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub kind: Option<GuildAuditKind>,
+}
+
+/// Constraints on which audit log entries to fetch. Timestamps are inclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AuditLogFilter {
+    pub user: Option<Id<UserMarker>>,
+    pub moderator: Option<Id<UserMarker>>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub action: Option<AuditLogAction>,
+}
+
 #[derive(Default)]
 pub struct UpdateGuildConfig {
     pub level_up_message: Option<String>,
@@ -955,6 +1815,7 @@ pub struct UpdateGuildConfig {
     pub min_xp_per_message: Option<i16>,
     pub message_cooldown: Option<i16>,
     pub one_at_a_time: Option<bool>,
+    pub log_channel: Option<Id<ChannelMarker>>,
 }
 
 macro_rules! setter {
@@ -985,6 +1846,8 @@ impl UpdateGuildConfig {
 
     setter!(one_at_a_time, bool);
 
+    setter!(log_channel, Id<ChannelMarker>);
+
     #[must_use]
     pub fn new() -> Self {
         Self::default()
@@ -1005,8 +1868,10 @@ pub struct CardUpdate {
     pub toy_image: Option<String>,
     pub card_layout: Option<String>,
     pub card_layout_default: String,
+    pub animated_avatar: Option<bool>,
 }
 
+#[derive(serde::Serialize)]
 pub struct RawCustomizations {
     pub username: Option<String>,
     pub rank: Option<String>,
@@ -1020,14 +1885,81 @@ pub struct RawCustomizations {
     pub font: Option<String>,
     pub toy_image: Option<String>,
     pub card_layout: String,
+    pub animated_avatar: Option<bool>,
     #[allow(dead_code)]
+    #[serde(skip)]
     id: I64Placeholder,
     #[allow(dead_code)]
+    #[serde(skip)]
     ord_id: I64Placeholder,
     #[allow(dead_code)]
+    #[serde(skip)]
     ordinality: I64Placeholder,
 }
 
+/// A card's colors, font and layout, saved under a name.
+/// Colors are stored in the same form as [`CardUpdate`] colors.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CardTheme {
+    pub name: String,
+    pub username: String,
+    pub rank: String,
+    pub level: String,
+    pub border: String,
+    pub background: String,
+    pub progress_foreground: String,
+    pub progress_background: String,
+    pub foreground_xp_count: String,
+    pub background_xp_count: String,
+    pub font: String,
+    pub card_layout: String,
+}
+
+/// A [`CardTheme`] and the code it can be shared with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedCardTheme {
+    pub code: String,
+    pub theme: CardTheme,
+}
+
+struct RawSavedCardTheme {
+    code: String,
+    name: String,
+    username: String,
+    rank: String,
+    level: String,
+    border: String,
+    background: String,
+    progress_foreground: String,
+    progress_background: String,
+    foreground_xp_count: String,
+    background_xp_count: String,
+    font: String,
+    card_layout: String,
+}
+
+impl RawSavedCardTheme {
+    fn cook(self) -> SavedCardTheme {
+        SavedCardTheme {
+            code: self.code,
+            theme: CardTheme {
+                name: self.name,
+                username: self.username,
+                rank: self.rank,
+                level: self.level,
+                border: self.border,
+                background: self.background,
+                progress_foreground: self.progress_foreground,
+                progress_background: self.progress_background,
+                foreground_xp_count: self.foreground_xp_count,
+                background_xp_count: self.background_xp_count,
+                font: self.font,
+                card_layout: self.card_layout,
+            },
+        }
+    }
+}
+
 struct I64Placeholder;
 
 impl From<i64> for I64Placeholder {
@@ -1050,6 +1982,7 @@ pub struct RawGuildConfig {
     pub min_xp_per_message: Option<i16>,
     pub max_xp_per_message: Option<i16>,
     pub message_cooldown: Option<i16>,
+    pub log_channel: Option<i64>,
 }
 
 impl RawGuildConfig {
@@ -1068,6 +2001,7 @@ impl RawGuildConfig {
             min_xp_per_message: self.min_xp_per_message,
             max_xp_per_message: self.max_xp_per_message,
             cooldown: self.message_cooldown,
+            log_channel: self.log_channel.map(db_to_id),
         };
         Ok(gc)
     }
@@ -1078,6 +2012,8 @@ pub enum Error {
     Database(sqlx::Error),
     Interpolation(simpleinterpolation::ParseError),
     UnspecifiedDelete,
+    AlreadyReverted,
+    UnknownAuditKind(String),
 }
 
 impl Display for Error {
@@ -1086,6 +2022,8 @@ impl Display for Error {
             Self::Database(de) => write!(f, "{de}"),
             Self::Interpolation(ie) => write!(f, "{ie}"),
             Self::UnspecifiedDelete => f.write_str("No constraints specified to delete by."),
+            Self::AlreadyReverted => f.write_str("That audit log entry has already been reverted."),
+            Self::UnknownAuditKind(kind) => write!(f, "Unknown guild audit log kind `{kind}`"),
         }
     }
 }
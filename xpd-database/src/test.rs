@@ -45,9 +45,14 @@ async fn audit_log_refetch(db: PgPool) -> Result<(), Box<dyn std::error::Error>>
         delta: -100,
         reset: true,
         set: false,
+        reverts: None,
+        import: false,
     };
     add_audit_log_event(&db, original_event).await?;
-    let roundtripped_event = get_audit_log_events(&db, Id::new(1), None, None).await?;
+    let roundtripped_event =
+        get_audit_log_events(&db, Id::new(1), AuditLogFilter::default(), None, 0)
+            .await?
+            .events();
     assert_eq!(roundtripped_event, &[original_event]);
     Ok(())
 }
@@ -64,6 +69,8 @@ async fn audit_log_multi(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
             delta: -100,
             reset: true,
             set: false,
+            reverts: None,
+            import: false,
         },
         AuditLogEvent {
             guild_id: Id::new(1),
@@ -74,12 +81,17 @@ async fn audit_log_multi(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
             delta: 50,
             reset: false,
             set: true,
+            reverts: None,
+            import: false,
         },
     ];
     for event in &original_events {
         add_audit_log_event(&db, *event).await?;
     }
-    let roundtripped_events = get_audit_log_events(&db, Id::new(1), None, None).await?;
+    let roundtripped_events =
+        get_audit_log_events(&db, Id::new(1), AuditLogFilter::default(), None, 0)
+            .await?
+            .events();
 
     assert_eq!(
         roundtripped_events.sorted_by_timestamp(),
@@ -100,6 +112,8 @@ async fn audit_logs_deleted_guild(db: PgPool) -> Result<(), Box<dyn std::error::
             delta: -100,
             reset: false,
             set: false,
+            reverts: None,
+            import: false,
         },
         AuditLogEvent {
             guild_id: Id::new(1),
@@ -110,6 +124,8 @@ async fn audit_logs_deleted_guild(db: PgPool) -> Result<(), Box<dyn std::error::
             delta: 50,
             reset: false,
             set: false,
+            reverts: None,
+            import: false,
         },
         AuditLogEvent {
             guild_id: Id::new(2),
@@ -120,6 +136,8 @@ async fn audit_logs_deleted_guild(db: PgPool) -> Result<(), Box<dyn std::error::
             delta: 50,
             reset: false,
             set: true,
+            reverts: None,
+            import: false,
         },
     ];
     for event in &original_events {
@@ -127,8 +145,14 @@ async fn audit_logs_deleted_guild(db: PgPool) -> Result<(), Box<dyn std::error::
     }
     delete_audit_log_events_guild(&db, Id::new(1)).await?;
 
-    let should_be_nonexistent = get_audit_log_events(&db, Id::new(1), None, None).await?;
-    let roundtripped_events = get_audit_log_events(&db, Id::new(2), None, None).await?;
+    let should_be_nonexistent =
+        get_audit_log_events(&db, Id::new(1), AuditLogFilter::default(), None, 0)
+            .await?
+            .events();
+    let roundtripped_events =
+        get_audit_log_events(&db, Id::new(2), AuditLogFilter::default(), None, 0)
+            .await?
+            .events();
 
     assert!(should_be_nonexistent.is_empty());
     assert_eq!(
@@ -138,13 +162,434 @@ async fn audit_logs_deleted_guild(db: PgPool) -> Result<(), Box<dyn std::error::
     Ok(())
 }
 
+#[sqlx::test(migrations = "../migrations/")]
+async fn audit_log_reverts(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let original_event = AuditLogEvent {
+        guild_id: Id::new(1),
+        user_id: Id::new(2),
+        moderator: Id::new(3),
+        timestamp: 50,
+        previous: 100,
+        delta: 900,
+        reset: false,
+        set: true,
+        reverts: None,
+        import: false,
+    };
+    let original_id = add_audit_log_event(&db, original_event).await?;
+    assert!(reverted_audit_log_entries(&db, Id::new(1))
+        .await?
+        .is_empty());
+
+    let revert_event = AuditLogEvent {
+        guild_id: Id::new(1),
+        user_id: Id::new(2),
+        moderator: Id::new(4),
+        timestamp: 60,
+        previous: 1000,
+        delta: -900,
+        reset: false,
+        set: true,
+        reverts: Some(original_id),
+        import: false,
+    };
+    let revert_id = add_audit_log_event(&db, revert_event).await?;
+
+    let fetched = get_audit_log_entry(&db, Id::new(1), revert_id).await?;
+    assert_eq!(fetched.map(|entry| entry.event()), Some(revert_event));
+    assert!(get_audit_log_entry(&db, Id::new(2), revert_id)
+        .await?
+        .is_none());
+    assert_eq!(
+        reverted_audit_log_entries(&db, Id::new(1)).await?,
+        vec![original_id]
+    );
+    assert!(matches!(
+        add_audit_log_event(&db, revert_event).await,
+        Err(Error::AlreadyReverted)
+    ));
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn audit_log_filters(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let base = AuditLogEvent {
+        guild_id: Id::new(1),
+        user_id: Id::new(2),
+        moderator: Id::new(3),
+        timestamp: 0,
+        previous: 0,
+        delta: 10,
+        reset: false,
+        set: false,
+        reverts: None,
+        import: false,
+    };
+    let events = [
+        base,
+        AuditLogEvent {
+            timestamp: 10,
+            delta: -5,
+            ..base
+        },
+        AuditLogEvent {
+            timestamp: 20,
+            moderator: Id::new(4),
+            set: true,
+            ..base
+        },
+        AuditLogEvent {
+            timestamp: 30,
+            user_id: Id::new(5),
+            import: true,
+            ..base
+        },
+    ];
+    for event in events {
+        add_audit_log_event(&db, event).await?;
+    }
+
+    let removes = AuditLogFilter {
+        action: Some(AuditLogAction::Remove),
+        ..Default::default()
+    };
+    let fetched = get_audit_log_events(&db, Id::new(1), removes, None, 0).await?;
+    assert_eq!(fetched.events(), vec![events[1]]);
+
+    let imports = AuditLogFilter {
+        action: Some(AuditLogAction::Import),
+        ..Default::default()
+    };
+    let fetched = get_audit_log_events(&db, Id::new(1), imports, None, 0).await?;
+    assert_eq!(fetched.events(), vec![events[3]]);
+
+    let range = AuditLogFilter {
+        since: Some(10),
+        until: Some(20),
+        ..Default::default()
+    };
+    let fetched = get_audit_log_events(&db, Id::new(1), range, None, 0).await?;
+    assert_eq!(fetched.events(), vec![events[2], events[1]]);
+    assert_eq!(count_audit_log_events(&db, Id::new(1), range).await?, 2);
+
+    let by_moderator = AuditLogFilter {
+        moderator: Some(Id::new(3)),
+        user: Some(Id::new(2)),
+        ..Default::default()
+    };
+    let page = get_audit_log_events(&db, Id::new(1), by_moderator, Some(1), 1).await?;
+    assert_eq!(page.events(), vec![events[0]]);
+    assert_eq!(
+        count_audit_log_events(&db, Id::new(1), by_moderator).await?,
+        2
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn guild_audit_log_refetch(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let config_event = GuildAuditEvent {
+        guild_id: Id::new(1),
+        actor: Id::new(2),
+        timestamp: 50,
+        kind: GuildAuditKind::ConfigUpdate,
+        before: None,
+        after: Some(serde_json::json!({ "level_up_message": "gg {user_mention}" })),
+    };
+    let reward_event = GuildAuditEvent {
+        guild_id: Id::new(1),
+        actor: Id::new(3),
+        timestamp: 60,
+        kind: GuildAuditKind::RewardAdd,
+        before: Some(serde_json::json!([])),
+        after: Some(serde_json::json!([{ "id": "4", "requirement": 5 }])),
+    };
+    add_guild_audit_event(&db, config_event.clone()).await?;
+    add_guild_audit_event(&db, reward_event.clone()).await?;
+
+    let all = get_guild_audit_events(&db, Id::new(1), GuildAuditFilter::default(), None, 0).await?;
+    let all: Vec<GuildAuditEvent> = all.iter().map(GuildAuditEntry::event).collect();
+    assert_eq!(all, vec![reward_event, config_event.clone()]);
+
+    let configs = GuildAuditFilter {
+        kind: Some(GuildAuditKind::ConfigUpdate),
+        ..Default::default()
+    };
+    let fetched = get_guild_audit_events(&db, Id::new(1), configs, None, 0).await?;
+    assert_eq!(fetched.len(), 1);
+    assert_eq!(fetched[0].event(), config_event);
+    assert_eq!(count_guild_audit_events(&db, Id::new(1), configs).await?, 1);
+
+    delete_guild_audit_events_guild(&db, Id::new(1)).await?;
+    assert_eq!(
+        count_guild_audit_events(&db, Id::new(1), GuildAuditFilter::default()).await?,
+        0
+    );
+    Ok(())
+}
+
+trait AuditLogEntries {
+    fn events(self) -> Vec<AuditLogEvent>;
+}
+
+impl AuditLogEntries for Vec<AuditLogEntry> {
+    fn events(self) -> Vec<AuditLogEvent> {
+        self.iter().map(AuditLogEntry::event).collect()
+    }
+}
+
 trait SortedByTimestamp {
     fn sorted_by_timestamp(self) -> Self;
 }
 
 impl SortedByTimestamp for Vec<AuditLogEvent> {
     fn sorted_by_timestamp(mut self) -> Self {
-        self.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        self.sort_by_key(|a| a.timestamp);
         self
     }
 }
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn bulk_restore_writes(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    set_xp(&db, Id::new(2), guild, 10).await?;
+    let written = bulk_set_xp(&db, guild, &[(Id::new(2), 500), (Id::new(3), 40)]).await?;
+    assert_eq!(written, 2);
+    assert_eq!(user_xp(&db, guild, Id::new(2)).await?, Some(500));
+    assert_eq!(user_xp(&db, guild, Id::new(3)).await?, Some(40));
+
+    let cooldowns = [
+        UserCooldown {
+            user: Id::new(2),
+            last_message: 100,
+        },
+        UserCooldown {
+            user: Id::new(3),
+            last_message: 200,
+        },
+    ];
+    bulk_set_cooldowns(&db, guild, &cooldowns).await?;
+    let mut fetched = guild_cooldowns(&db, guild).await?;
+    fetched.sort_by_key(|v| v.last_message);
+    assert_eq!(fetched, cooldowns);
+    assert_eq!(delete_guild_cooldowns(&db, guild).await?, 2);
+    assert!(guild_cooldowns(&db, guild).await?.is_empty());
+
+    add_reward_role(&db, guild, 5, Id::new(10)).await?;
+    add_reward_role(&db, guild, 10, Id::new(11)).await?;
+    add_reward_role(&db, Id::new(2), 10, Id::new(12)).await?;
+    assert_eq!(delete_guild_rewards(&db, guild).await?, 2);
+    assert!(guild_rewards(&db, guild).await?.is_empty());
+    assert_eq!(guild_rewards(&db, Id::new(2)).await?.len(), 1);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn bulk_import_writes(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    set_xp(&db, Id::new(2), guild, 10).await?;
+    let mut added = bulk_add_xp(&db, guild, &[(Id::new(2), 5), (Id::new(3), 40)]).await?;
+    added.sort_unstable();
+    assert_eq!(added, vec![(Id::new(2), 15), (Id::new(3), 40)]);
+
+    let mut txn = db.begin().await?;
+    let existing = bulk_user_xp(txn.as_mut(), guild, &[Id::new(3), Id::new(4)]).await?;
+    assert_eq!(existing, vec![(Id::new(3), 40)]);
+    txn.commit().await?;
+
+    let event = AuditLogEvent {
+        guild_id: guild,
+        user_id: Id::new(2),
+        moderator: Id::new(9),
+        timestamp: 50,
+        previous: 10,
+        delta: 5,
+        reset: false,
+        set: false,
+        reverts: None,
+        import: true,
+    };
+    let events = [
+        event,
+        AuditLogEvent {
+            user_id: Id::new(3),
+            previous: 0,
+            delta: 40,
+            reverts: Some(1),
+            ..event
+        },
+    ];
+    assert_eq!(bulk_add_audit_log_events(&db, &events).await?, 2);
+    let mut logged: Vec<AuditLogEvent> =
+        get_audit_log_events(&db, guild, AuditLogFilter::default(), None, 0)
+            .await?
+            .iter()
+            .map(AuditLogEntry::event)
+            .collect();
+    logged.sort_by_key(|event| event.user_id);
+    assert_eq!(logged, events);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn leaderboards_among_members(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    for (user, xp) in [(2, 100), (3, 300), (4, 200), (5, 400)] {
+        set_xp(&db, Id::new(user), guild, xp).await?;
+    }
+    set_xp(&db, Id::new(3), Id::new(6), 1000).await?;
+    let house = [Id::new(2), Id::new(3), Id::new(4), Id::new(7)];
+
+    let page = get_leaderboard_page_among(&db, guild, &house, 2, 0).await?;
+    let ids: Vec<_> = page.iter().map(|user| user.id.get()).collect();
+    assert_eq!(ids, [3, 4]);
+    let page = get_leaderboard_page_among(&db, guild, &house, 2, 2).await?;
+    let ids: Vec<_> = page.iter().map(|user| user.id.get()).collect();
+    assert_eq!(ids, [2]);
+
+    // User 5 has more XP, but isn't in the house
+    assert_eq!(
+        count_among_with_higher_xp(&db, guild, &house, 200).await?,
+        Some(1)
+    );
+    assert_eq!(count_with_higher_xp(&db, guild, 200).await?, Some(2));
+    assert!(get_leaderboard_page_among(&db, guild, &[], 10, 0)
+        .await?
+        .is_empty());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn card_backgrounds_follow_customizations(
+    db: PgPool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (user, guild) = (Id::new(1), Id::new(2));
+    let update = CardUpdate {
+        username: None,
+        rank: None,
+        level: None,
+        border: None,
+        background: None,
+        progress_background: None,
+        progress_foreground: None,
+        foreground_xp_count: None,
+        background_xp_count: None,
+        font: None,
+        toy_image: None,
+        card_layout: None,
+        card_layout_default: "classic.svg".to_string(),
+        animated_avatar: None,
+    };
+    update_card(&db, guild, &update).await?;
+    set_card_background(&db, guild, &[1, 2, 3]).await?;
+    set_card_background(&db, guild, &[4, 5]).await?;
+    assert_eq!(
+        card_background(&db, &[user, guild]).await?,
+        Some(vec![4, 5])
+    );
+
+    // A user card without a background wins over the guild's background
+    update_card(&db, user, &update).await?;
+    assert_eq!(card_background(&db, &[user, guild]).await?, None);
+
+    delete_card_customizations(&db, guild).await?;
+    assert_eq!(card_background(&db, &[guild]).await?, None);
+    delete_card_background(&db, user).await?;
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn guild_card_layouts_replace(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    assert_eq!(guild_card_layout(&db, guild).await?, None);
+    set_guild_card_layout(&db, guild, "<svg></svg>").await?;
+    set_guild_card_layout(&db, guild, "<svg><rect /></svg>").await?;
+    assert_eq!(
+        guild_card_layout(&db, guild).await?.as_deref(),
+        Some("<svg><rect /></svg>")
+    );
+    assert!(delete_guild_card_layout(&db, guild).await?);
+    assert!(!delete_guild_card_layout(&db, guild).await?);
+    assert_eq!(guild_card_layout(&db, guild).await?, None);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn card_themes_share_by_code(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let (alice, bob) = (Id::new(1), Id::new(2));
+    let mut theme = CardTheme {
+        name: "dusk".to_string(),
+        username: "#FFFFFF".to_string(),
+        rank: "#FFFFFF".to_string(),
+        level: "#FF8000".to_string(),
+        border: "#000000".to_string(),
+        background: "#101020,#302040,90deg".to_string(),
+        progress_foreground: "#FF8000".to_string(),
+        progress_background: "#FFFFFF40".to_string(),
+        foreground_xp_count: "#FFFFFF".to_string(),
+        background_xp_count: "#000000".to_string(),
+        font: "Roboto".to_string(),
+        card_layout: "classic.svg".to_string(),
+    };
+    let code = save_card_theme(&db, alice, &theme).await?;
+    // Saving again under the same name updates the theme and keeps the code
+    theme.level = "#00FF00".to_string();
+    assert_eq!(save_card_theme(&db, alice, &theme).await?, code);
+    assert_eq!(card_themes(&db, alice).await?.len(), 1);
+
+    let found = find_card_theme(&db, alice, "dusk").await?.unwrap();
+    assert_eq!((found.code.as_str(), &found.theme), (code.as_str(), &theme));
+    // Others can only find it by code
+    assert!(find_card_theme(&db, bob, "dusk").await?.is_none());
+    let shared = find_card_theme(&db, bob, &code.to_uppercase())
+        .await?
+        .unwrap();
+    assert_eq!(shared.theme, theme);
+
+    assert!(!delete_card_theme(&db, bob, "dusk").await?);
+    assert!(delete_card_theme(&db, alice, "dusk").await?);
+    assert!(find_card_theme(&db, bob, &code).await?.is_none());
+    save_card_theme(&db, alice, &theme).await?;
+    assert_eq!(delete_card_themes(&db, alice).await?, 1);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn card_theme_codes_retry_on_clash(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    // The first two codes handed out clash, and the third doesn't
+    sqlx::query("CREATE SEQUENCE theme_codes")
+        .execute(&db)
+        .await?;
+    sqlx::query(
+        "ALTER TABLE card_themes ALTER COLUMN code SET DEFAULT \
+            CASE WHEN nextval('theme_codes') <= 2 THEN 'aaaaaaaa' ELSE 'bbbbbbbb' END",
+    )
+    .execute(&db)
+    .await?;
+    let theme = CardTheme {
+        name: "dusk".to_string(),
+        username: "#FFFFFF".to_string(),
+        rank: "#FFFFFF".to_string(),
+        level: "#FF8000".to_string(),
+        border: "#000000".to_string(),
+        background: "#000000".to_string(),
+        progress_foreground: "#FF8000".to_string(),
+        progress_background: "#FFFFFF".to_string(),
+        foreground_xp_count: "#FFFFFF".to_string(),
+        background_xp_count: "#000000".to_string(),
+        font: "Roboto".to_string(),
+        card_layout: "classic.svg".to_string(),
+    };
+    let mut txn = db.begin().await?;
+    assert_eq!(
+        save_card_theme(txn.as_mut(), Id::new(1), &theme).await?,
+        "aaaaaaaa"
+    );
+    assert_eq!(
+        save_card_theme(txn.as_mut(), Id::new(2), &theme).await?,
+        "bbbbbbbb"
+    );
+    txn.commit().await?;
+    assert_eq!(card_themes(&db, Id::new(2)).await?.len(), 1);
+    Ok(())
+}
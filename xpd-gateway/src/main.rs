@@ -1,4 +1,5 @@
 #![deny(clippy::all)]
+#![allow(clippy::result_large_err)]
 
 #[macro_use]
 extern crate tracing;
@@ -6,6 +7,7 @@ extern crate tracing;
 use std::{
     collections::HashMap,
     env::VarError,
+    path::PathBuf,
     process::{ExitCode, Termination},
     str::FromStr,
     sync::Arc,
@@ -40,9 +42,15 @@ use twilight_model::{
 };
 use xpd_common::RequiredDiscordResources;
 use xpd_listener::XpdListener;
-use xpd_slash::XpdSlash;
+use xpd_slash::{CardFormats, XpdSlash};
 use xpd_util::LogError;
 
+/// Largest `/manage import` file, in bytes, if `MAX_IMPORT_SIZE` isn't set.
+/// Imports are streamed, so this is about upload time rather than memory.
+const DEFAULT_MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+/// Directory holding the card manifest, fonts, toys and templates
+const DEFAULT_CARD_RESOURCES: &str = "xpd-card-resources";
+
 #[tokio::main]
 async fn main() -> Result<(), SetupError> {
     let tracer_shutdown = init_tracing()?;
@@ -54,6 +62,15 @@ async fn main() -> Result<(), SetupError> {
     let token = get_var("DISCORD_TOKEN")?;
     let pg = get_var("DATABASE_URL")?;
     let control_guild: Id<GuildMarker> = parse_var("CONTROL_GUILD")?;
+    let max_import_size: usize =
+        parse_var_opt("MAX_IMPORT_SIZE")?.unwrap_or(DEFAULT_MAX_IMPORT_SIZE);
+    let card_resources: PathBuf =
+        parse_var_opt("CARD_RESOURCES")?.unwrap_or_else(|| DEFAULT_CARD_RESOURCES.into());
+    let default_formats = CardFormats::default();
+    let card_formats = CardFormats {
+        text: parse_var_opt("TEXT_CARD_FORMAT")?.unwrap_or(default_formats.text),
+        photo: parse_var_opt("PHOTO_CARD_FORMAT")?.unwrap_or(default_formats.photo),
+    };
 
     let db = sqlx::postgres::PgPoolOptions::new()
         .max_connections(50)
@@ -137,6 +154,9 @@ async fn main() -> Result<(), SetupError> {
         control_guild,
         owners,
         event_bus_tx,
+        max_import_size,
+        &card_resources,
+        card_formats,
     );
     let config = Config::new(token.clone(), intents);
     let shards: Vec<Shard> =
@@ -366,6 +386,17 @@ fn get_var(name: &str) -> Result<String, SetupError> {
     get_var_opt(name)?.ok_or_else(|| SetupError::MissingEnv(name.to_owned()))
 }
 
+fn parse_var_opt<T>(name: &str) -> Result<Option<T>, SetupError>
+where
+    T: FromStr,
+    T::Err: std::error::Error + 'static,
+{
+    get_var_opt(name)?
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|e| SetupError::FromStr(name.to_string(), Box::new(e)))
+}
+
 fn parse_var<T>(name: &str) -> Result<T, SetupError>
 where
     T: FromStr,
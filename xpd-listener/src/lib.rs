@@ -100,6 +100,7 @@ impl XpdListenerInner {
         }
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn update_config(&self, guild: Id<GuildMarker>, config: GuildConfig) -> Result<(), Error> {
         self.configs.insert(guild, Arc::new(config));
         Ok(())
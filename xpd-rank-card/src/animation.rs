@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use gif::{ColorOutput, DecodeOptions, DisposalMethod, Encoder, Frame, Repeat};
+use rayon::prelude::*;
+use resvg::tiny_skia::Pixmap;
+
+use crate::Error;
+
+/// Animated avatars with more frames than this are sampled down to this many frames,
+/// so that render time stays bounded no matter how long the source animation is.
+pub const MAX_ANIMATION_FRAMES: usize = 24;
+/// Avatars larger than this in either dimension are not animated
+pub const MAX_AVATAR_DIMENSION: u16 = 512;
+/// Animated cards larger than this are discarded. Callers should fall back to a still card.
+pub const MAX_ANIMATED_CARD_BYTES: usize = 8 * 1024 * 1024;
+/// Largest area of all frames of an animated card together. Every frame is held in memory
+/// until the GIF is encoded.
+pub const MAX_ANIMATION_PIXELS: u64 = 32 * 1024 * 1024;
+/// Stop decoding the source animation after this many frames
+const MAX_SOURCE_FRAMES: usize = 512;
+/// Browsers (and discord) play GIF frames with a delay under 2 centiseconds at 10 centiseconds
+const MIN_FRAME_DELAY: u16 = 2;
+const SLOW_FRAME_DELAY: u16 = 10;
+/// Quantization speed, from 1 (best) to 30 (fastest)
+const QUANTIZE_SPEED: i32 = 10;
+
+/// A single frame of a decoded avatar, ready to be handed to resvg
+pub struct AvatarFrame {
+    pub png: Arc<Vec<u8>>,
+    /// Frame delay in centiseconds
+    pub delay: u16,
+}
+
+struct SourceFrame {
+    rgba: Vec<u8>,
+    delay: u16,
+}
+
+/// Decode an animated GIF avatar into full frames, sampled down to [`MAX_ANIMATION_FRAMES`].
+pub fn decode_avatar(gif: &[u8]) -> Result<Vec<AvatarFrame>, Error> {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options.read_info(gif)?;
+    let (width, height) = (decoder.width(), decoder.height());
+    if width == 0 || height == 0 || width > MAX_AVATAR_DIMENSION || height > MAX_AVATAR_DIMENSION {
+        return Err(Error::AvatarTooLarge);
+    }
+    let (width, height) = (usize::from(width), usize::from(height));
+
+    // GIF frames only cover the part of the image which changed, so we composite them
+    // onto a canvas as the GIF disposal methods describe.
+    let mut canvas = vec![0; width * height * 4];
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        if frames.len() >= MAX_SOURCE_FRAMES {
+            break;
+        }
+        let previous = (frame.dispose == DisposalMethod::Previous).then(|| canvas.clone());
+        let left = usize::from(frame.left);
+        let top = usize::from(frame.top);
+        let frame_width = usize::from(frame.width);
+        for (row, line) in frame.buffer.chunks_exact(frame_width * 4).enumerate() {
+            let y = top + row;
+            if y >= height {
+                break;
+            }
+            for (col, pixel) in line.chunks_exact(4).enumerate() {
+                let x = left + col;
+                if x >= width {
+                    break;
+                }
+                if pixel[3] != 0 {
+                    let idx = (y * width + x) * 4;
+                    canvas[idx..idx + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+        frames.push(SourceFrame {
+            rgba: canvas.clone(),
+            delay: frame.delay,
+        });
+        match (frame.dispose, previous) {
+            (DisposalMethod::Previous, Some(previous)) => canvas = previous,
+            (DisposalMethod::Background, _) => {
+                for y in top..(top + usize::from(frame.height)).min(height) {
+                    let start = (y * width + left.min(width)) * 4;
+                    let end = (y * width + (left + frame_width).min(width)) * 4;
+                    canvas[start..end].fill(0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let step = frames.len().div_ceil(MAX_ANIMATION_FRAMES).max(1);
+    frames
+        .chunks(step)
+        .map(|chunk| {
+            let delay = chunk.iter().fold(0u16, |acc, frame| {
+                let delay = if frame.delay < MIN_FRAME_DELAY {
+                    SLOW_FRAME_DELAY
+                } else {
+                    frame.delay
+                };
+                acc.saturating_add(delay)
+            });
+            #[allow(clippy::cast_possible_truncation)]
+            let png = encode_png(&chunk[0].rgba, width as u32, height as u32)?;
+            Ok(AvatarFrame {
+                png: Arc::new(png),
+                delay,
+            })
+        })
+        .collect()
+}
+
+fn encode_png(rgba: &[u8], width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)?;
+    Ok(png)
+}
+
+/// Encode rendered card frames into a looping GIF.
+pub fn encode_animation(frames: &[(Pixmap, u16)]) -> Result<Vec<u8>, Error> {
+    let Some((first, _)) = frames.first() else {
+        return Err(Error::PixmapCreation);
+    };
+    let width = u16::try_from(first.width()).map_err(|_| Error::AnimationTooLarge)?;
+    let height = u16::try_from(first.height()).map_err(|_| Error::AnimationTooLarge)?;
+    // Quantizing is the slow part, so do it for every frame at once
+    let gif_frames: Vec<Frame> = frames
+        .par_iter()
+        .map(|(pixmap, delay)| {
+            let mut rgba: Vec<u8> = pixmap
+                .pixels()
+                .iter()
+                .flat_map(|pixel| {
+                    let pixel = pixel.demultiply();
+                    [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+                })
+                .collect();
+            let mut frame = Frame::from_rgba_speed(width, height, &mut rgba, QUANTIZE_SPEED);
+            frame.delay = *delay;
+            frame
+        })
+        .collect();
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut output, width, height, &[])?;
+        encoder.set_repeat(Repeat::Infinite)?;
+        for frame in &gif_frames {
+            encoder.write_frame(frame)?;
+            if encoder.get_ref().len() > MAX_ANIMATED_CARD_BYTES {
+                return Err(Error::AnimationTooLarge);
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_avatar, MAX_ANIMATION_FRAMES};
+    use crate::Error;
+
+    fn gif(frames: u16, size: u16, delay: u16) -> Vec<u8> {
+        let mut gif = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut gif, size, size, &[]).unwrap();
+        for i in 0..frames {
+            #[allow(clippy::cast_possible_truncation)]
+            let mut rgba = [i as u8, 0, 0, 255].repeat(usize::from(size) * usize::from(size));
+            let mut frame = gif::Frame::from_rgba_speed(size, size, &mut rgba, 30);
+            frame.delay = delay;
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+        gif
+    }
+
+    #[test]
+    fn long_animations_are_sampled() {
+        let frames = decode_avatar(&gif(100, 8, 3)).unwrap();
+        assert!(frames.len() <= MAX_ANIMATION_FRAMES);
+        // Sampling keeps the animation's total length
+        let total: u32 = frames.iter().map(|f| u32::from(f.delay)).sum();
+        assert_eq!(total, 300);
+    }
+
+    #[test]
+    fn zero_delays_play_slowly() {
+        let frames = decode_avatar(&gif(3, 8, 0)).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| f.delay == 10));
+    }
+
+    #[test]
+    fn huge_avatars_are_refused() {
+        assert!(matches!(
+            decode_avatar(&gif(1, 1024, 3)),
+            Err(Error::AvatarTooLarge)
+        ));
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use resvg::usvg::ImageKind;
+
+use crate::Error;
+
+/// Smallest width and height of an uploaded background image
+pub const MIN_DIMENSION: usize = 64;
+/// Largest width and height of an uploaded background image
+pub const MAX_DIMENSION: usize = 4096;
+
+/// Check that an uploaded background is an image we can decode, and that it is a sensible size.
+/// The file contents decide the format, not the name or content type it was uploaded with.
+pub fn validate(image: Vec<u8>) -> Result<ImageKind, Error> {
+    let size = imagesize::blob_size(&image).map_err(|_| Error::UnsupportedBackground)?;
+    let kind = match imagesize::image_type(&image) {
+        Ok(imagesize::ImageType::Png) => ImageKind::PNG(Arc::new(image)),
+        Ok(imagesize::ImageType::Jpeg) => ImageKind::JPEG(Arc::new(image)),
+        Ok(imagesize::ImageType::Gif) => ImageKind::GIF(Arc::new(image)),
+        Ok(imagesize::ImageType::Webp) => ImageKind::WEBP(Arc::new(image)),
+        _ => return Err(Error::UnsupportedBackground),
+    };
+    let dimensions = MIN_DIMENSION..=MAX_DIMENSION;
+    if !dimensions.contains(&size.width) || !dimensions.contains(&size.height) {
+        return Err(Error::BackgroundSize {
+            width: size.width,
+            height: size.height,
+        });
+    }
+    Ok(kind)
+}
+
+/// An SVG which scales the background to cover the whole `width` by `height` area,
+/// cropping whatever sticks out on either side.
+pub fn fit_svg(width: u32, height: u32) -> String {
+    format!(
+        r#"<svg version="1.1" width="{width}" height="{height}" xmlns="http://www.w3.org/2000/svg">
+  <image width="{width}" height="{height}" preserveAspectRatio="xMidYMid slice" href="{}" />
+</svg>"#,
+        crate::BACKGROUND_IMAGE_HREF
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::Error;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        let pixels = vec![0; width as usize * height as usize * 4];
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&pixels)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn accepts_png() {
+        assert!(validate(png(200, 100)).is_ok());
+    }
+
+    #[test]
+    fn rejects_tiny_images() {
+        assert!(matches!(
+            validate(png(10, 100)),
+            Err(Error::BackgroundSize {
+                width: 10,
+                height: 100
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(
+            validate(b"<svg></svg>".to_vec()),
+            Err(Error::UnsupportedBackground)
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 use std::time::Instant;
 
-use xpd_rank_card::{Context, SvgState};
+use xpd_rank_card::{Context, ImageFormat, PngCompression, SvgState, DEFAULT_WEBP_QUALITY};
 
 const VALK_PFP: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAQAAAAEABAMAAACuXLVVAAAAIGNIUk0AAHomAACAhAAA+gAAAIDoAAB1MAAA6mAAADqYAAAXcJy6UTwAAAAYUExURXG0zgAAAFdXV6ampoaGhr6zpHxfQ2VPOt35dJcAAAABYktHRAH/Ai3eAAAAB3RJTUUH5wMDFSE5W/eo1AAAAQtJREFUeNrt1NENgjAUQFFXYAVWYAVXcAVXYH0hoQlpSqGY2Dae82WE9971x8cDAAAAAAAAAAAAAAAAAADgR4aNAAEC/jNgPTwuBAgQ8J8B69FpI0CAgL4DhozczLgjQICAPgPCkSkjtXg/I0CAgD4Dzg4PJ8YEAQIE9BEQLyg5cEWYFyBAQHsBVxcPN8U7BAgQ0FbAlcNhcLohjkn+egECBFQPKPE8cXpQgAABzQXkwsIfUElwblaAAAF9BeyP3Z396rgAAQJ+EvCqTIAAAfUD3pUJECCgvYB5kfp89N28yR3J7RQgQED9gPjhfmG8/Oh56r1UYOpdAQIEtBFwtLBUyY7wrgABAqoHfABW2cbX3ElRgQAAACV0RVh0ZGF0ZTpjcmVhdGUAMjAyMy0wMy0wM1QyMTozMzo1NiswMDowMNpnAp0AAAAldEVYdGRhdGU6bW9kaWZ5ADIwMjMtMDMtMDNUMjE6MzM6NTYrMDA6MDCrOrohAAAAKHRFWHRkYXRlOnRpbWVzdGFtcAAyMDIzLTAzLTAzVDIxOjMzOjU3KzAwOjAwWliQSgAAAABJRU5ErkJggg==";
 
@@ -13,17 +13,43 @@ fn main() {
         percentage: 30,
         current: 124,
         needed: 213,
-        customizations: state.default_customizations().clone(),
+        customizations: state.resources().default_customizations().clone(),
         avatar: VALK_PFP.to_string(),
+        reward: None,
+        next_reward: None,
+        background_image: None,
+        layout_owner: None,
     };
-    let mut total = 0.0;
-    let times = 10000;
-    for _ in 0..times {
-        let start = Instant::now();
-        let data = state.sync_render(&context).unwrap();
-        total += start.elapsed().as_secs_f64();
-        std::fs::write("/dev/null", data).unwrap();
+    let formats = [
+        ImageFormat::Png(PngCompression::Fast),
+        ImageFormat::Png(PngCompression::Balanced),
+        ImageFormat::Png(PngCompression::Best),
+        ImageFormat::WebpLossless,
+        ImageFormat::WebpLossy { quality: 60 },
+        ImageFormat::WebpLossy {
+            quality: DEFAULT_WEBP_QUALITY,
+        },
+    ];
+    let times = 200;
+    for format in formats {
+        let mut total = 0.0;
+        let mut bytes = 0;
+        for i in 0..times {
+            // Every card is different, so none of them come from the render cache
+            let context = Context {
+                current: i,
+                ..context.clone()
+            };
+            let start = Instant::now();
+            let data = state.sync_render(&context, format).unwrap();
+            total += start.elapsed().as_secs_f64();
+            bytes += data.len();
+        }
+        let time_per_card = total / times as f64;
+        let bytes_per_card = bytes / times as usize;
+        println!(
+            "{format:?}: took {total} seconds to render the card {times} times \
+             ({time_per_card}s / card, {bytes_per_card} bytes / card)"
+        );
     }
-    let time_per_card = total / times as f64;
-    println!("Took {total} seconds to render the card {times} times ({time_per_card}s / card)");
 }
@@ -1,6 +1,9 @@
 use std::thread::JoinHandle;
 
-use xpd_rank_card::{customizations::Customizations, *};
+use xpd_rank_card::{
+    customizations::{Color, Customizations},
+    *,
+};
 
 const VALK_PFP: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAQAAAAEABAMAAACuXLVVAAAAIGNIUk0AAHomAACAhAAA+gAAAIDoAAB1MAAA6mAAADqYAAAXcJy6UTwAAAAYUExURXG0zgAAAFdXV6ampoaGhr6zpHxfQ2VPOt35dJcAAAABYktHRAH/Ai3eAAAAB3RJTUUH5wMDFSE5W/eo1AAAAQtJREFUeNrt1NENgjAUQFFXYAVWYAVXcAVXYH0hoQlpSqGY2Dae82WE9971x8cDAAAAAAAAAAAAAAAAAADgR4aNAAEC/jNgPTwuBAgQ8J8B69FpI0CAgL4DhozczLgjQICAPgPCkSkjtXg/I0CAgD4Dzg4PJ8YEAQIE9BEQLyg5cEWYFyBAQHsBVxcPN8U7BAgQ0FbAlcNhcLohjkn+egECBFQPKPE8cXpQgAABzQXkwsIfUElwblaAAAF9BeyP3Z396rgAAQJ+EvCqTIAAAfUD3pUJECCgvYB5kfp89N28yR3J7RQgQED9gPjhfmG8/Oh56r1UYOpdAQIEtBFwtLBUyY7wrgABAqoHfABW2cbX3ElRgQAAACV0RVh0ZGF0ZTpjcmVhdGUAMjAyMy0wMy0wM1QyMTozMzo1NiswMDowMNpnAp0AAAAldEVYdGRhdGU6bW9kaWZ5ADIwMjMtMDMtMDNUMjE6MzM6NTYrMDA6MDCrOrohAAAAKHRFWHRkYXRlOnRpbWVzdGFtcAAyMDIzLTAzLTAzVDIxOjMzOjU3KzAwOjAwWliQSgAAAABJRU5ErkJggg==";
 
@@ -11,6 +14,14 @@ fn main() {
     render_classic_r().unwrap();
     render_vertical().unwrap();
     render_vertical_procedural();
+    render_leaderboards().unwrap();
+    render_animated().unwrap();
+    render_backgrounds().unwrap();
+    render_gradients().unwrap();
+    render_themes().unwrap();
+    render_rewards().unwrap();
+    render_scripts().unwrap();
+    render_custom_layout().unwrap();
 }
 
 fn new_state() -> SvgState {
@@ -22,7 +33,11 @@ fn render_classic_l() -> Result<(), Error> {
     let xp = 49;
     let customizations = Customizations {
         toy: Some("bee.png".to_string()),
-        ..state.customizations_for("classic.svg").unwrap().clone()
+        ..state
+            .resources()
+            .customizations_for("classic.svg")
+            .unwrap()
+            .clone()
     };
     let context = Context {
         level: 1,
@@ -33,8 +48,12 @@ fn render_classic_l() -> Result<(), Error> {
         needed: 100 - xp,
         customizations,
         avatar: VALK_PFP.to_string(),
+        reward: None,
+        next_reward: None,
+        background_image: None,
+        layout_owner: None,
     };
-    let output = state.sync_render(&context)?;
+    let output = state.sync_render(&context, ImageFormat::default())?;
     std::fs::write("rendered-cards/renderer_test_classic_l.png", output).unwrap();
     Ok(())
 }
@@ -44,7 +63,11 @@ fn render_classic_r() -> Result<(), Error> {
     let xp = 51;
     let customizations = Customizations {
         toy: Some("cow.png".to_string()),
-        ..state.customizations_for("classic.svg").unwrap().clone()
+        ..state
+            .resources()
+            .customizations_for("classic.svg")
+            .unwrap()
+            .clone()
     };
     let context = Context {
         level: 1,
@@ -55,8 +78,12 @@ fn render_classic_r() -> Result<(), Error> {
         needed: 100 - xp,
         customizations,
         avatar: VALK_PFP.to_string(),
+        reward: None,
+        next_reward: None,
+        background_image: None,
+        layout_owner: None,
     };
-    let output = state.sync_render(&context)?;
+    let output = state.sync_render(&context, ImageFormat::default())?;
     std::fs::write("rendered-cards/renderer_test_classic_r.png", output).unwrap();
     Ok(())
 }
@@ -66,7 +93,11 @@ fn render_vertical() -> Result<(), Error> {
     let xp = 99;
     let customizations = Customizations {
         font: "Montserrat-Alt1".to_string(),
-        ..state.customizations_for("vertical.svg").unwrap().clone()
+        ..state
+            .resources()
+            .customizations_for("vertical.svg")
+            .unwrap()
+            .clone()
     };
     let context = Context {
         level: 420,
@@ -77,14 +108,375 @@ fn render_vertical() -> Result<(), Error> {
         needed: 100 - xp,
         customizations,
         avatar: VALK_PFP.to_string(),
+        reward: None,
+        next_reward: None,
+        background_image: None,
+        layout_owner: None,
     };
     let svg = state.render_svg(&context)?;
-    let png = state.sync_render(&context)?;
+    let png = state.sync_render(&context, ImageFormat::default())?;
     std::fs::write("rendered-cards/renderer_test_vertical.svg", svg).unwrap();
     std::fs::write("rendered-cards/renderer_test_vertical.png", png).unwrap();
     Ok(())
 }
 
+fn render_leaderboards() -> Result<(), Error> {
+    let state = new_state();
+    let rows: Vec<LeaderboardRow> = (1..=10)
+        .map(|rank| LeaderboardRow {
+            rank,
+            name: format!("Testy McTestington {rank}"),
+            level: 110 - rank.unsigned_abs() * 10,
+            percentage: rank.unsigned_abs() * 9,
+            current: rank.unsigned_abs() * 90,
+            needed: 1000,
+            avatar: if rank % 3 == 0 {
+                String::new()
+            } else {
+                VALK_PFP.to_string()
+            },
+        })
+        .collect();
+    for card in ["classic.svg", "vertical.svg"] {
+        let context = LeaderboardContext {
+            rows: rows.clone(),
+            customizations: state.resources().customizations_for(card).unwrap().clone(),
+        };
+        let output = state.sync_render_leaderboard(&context, ImageFormat::default())?;
+        std::fs::write(
+            format!("rendered-cards/renderer_test_leaderboard_{card}.png"),
+            output,
+        )
+        .unwrap();
+    }
+    Ok(())
+}
+
+/// A 60-frame GIF of a bar sweeping across a 128x128 square
+fn test_animated_avatar() -> Vec<u8> {
+    let mut gif = Vec::new();
+    let mut encoder = gif::Encoder::new(&mut gif, 128, 128, &[]).unwrap();
+    for step in 0..60 {
+        let mut rgba: Vec<u8> = (0..128 * 128)
+            .flat_map(|i| {
+                let x = i % 128;
+                if (x * 60 / 128) == step {
+                    [255, 255, 255, 255]
+                } else {
+                    [40, 90, 200, 255]
+                }
+            })
+            .collect();
+        let mut frame = gif::Frame::from_rgba_speed(128, 128, &mut rgba, 30);
+        frame.delay = 3;
+        encoder.write_frame(&frame).unwrap();
+    }
+    drop(encoder);
+    gif
+}
+
+fn render_animated() -> Result<(), Error> {
+    let state = new_state();
+    let avatar = test_animated_avatar();
+    for card in ["classic.svg", "vertical.svg"] {
+        let context = Context {
+            level: 12,
+            rank: 3,
+            name: "Testy McTestington".to_string(),
+            percentage: 60,
+            current: 60,
+            needed: 100,
+            customizations: state.resources().customizations_for(card).unwrap().clone(),
+            avatar: String::new(),
+            reward: None,
+            next_reward: None,
+            background_image: None,
+            layout_owner: None,
+        };
+        let start = std::time::Instant::now();
+        let output = state.sync_render_animated(&context, &avatar)?;
+        println!(
+            "Rendered animated {card} in {:?} ({} bytes)",
+            start.elapsed(),
+            output.len()
+        );
+        std::fs::write(
+            format!("rendered-cards/renderer_test_animated_{card}.gif"),
+            output,
+        )
+        .unwrap();
+    }
+    Ok(())
+}
+
+/// A 640x480 PNG with a diagonal gradient, to show how backgrounds get cropped
+fn test_background() -> Vec<u8> {
+    let (width, height) = (640_u32, 480_u32);
+    let pixels: Vec<u8> = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            #[allow(clippy::cast_possible_truncation)]
+            [(x * 255 / width) as u8, (y * 255 / height) as u8, 160, 255]
+        })
+        .collect();
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&pixels)
+        .unwrap();
+    png
+}
+
+fn render_backgrounds() -> Result<(), Error> {
+    let state = new_state();
+    for card in ["classic.svg", "vertical.svg"] {
+        let background = state.sync_prepare_background(card, test_background())?;
+        let context = Context {
+            level: 12,
+            rank: 3,
+            name: "Testy McTestington".to_string(),
+            percentage: 60,
+            current: 60,
+            needed: 100,
+            customizations: state.resources().customizations_for(card).unwrap().clone(),
+            avatar: VALK_PFP.to_string(),
+            reward: None,
+            next_reward: None,
+            background_image: Some(std::sync::Arc::new(background)),
+            layout_owner: None,
+        };
+        let output = state.sync_render(&context, ImageFormat::default())?;
+        std::fs::write(
+            format!("rendered-cards/renderer_test_background_{card}.png"),
+            output,
+        )
+        .unwrap();
+    }
+    Ok(())
+}
+
+fn render_gradients() -> Result<(), Error> {
+    let state = new_state();
+    for card in ["classic.svg", "vertical.svg"] {
+        let customizations = Customizations {
+            background: Color::parse(&"#1E1E2E,#45475A,135deg")?,
+            progress_foreground: Color::parse(&"hotpink,orange")?,
+            progress_background: Color::parse(&"#FFFFFF40")?,
+            username: Color::parse(&"gold,white,180deg")?,
+            ..state.resources().customizations_for(card).unwrap().clone()
+        };
+        let context = Context {
+            level: 12,
+            rank: 3,
+            name: "Testy McTestington".to_string(),
+            percentage: 60,
+            current: 60,
+            needed: 100,
+            customizations: customizations.clone(),
+            avatar: VALK_PFP.to_string(),
+            reward: None,
+            next_reward: None,
+            background_image: None,
+            layout_owner: None,
+        };
+        let output = state.sync_render(&context, ImageFormat::default())?;
+        std::fs::write(
+            format!("rendered-cards/renderer_test_gradient_{card}.png"),
+            output,
+        )
+        .unwrap();
+        let leaderboard = LeaderboardContext {
+            rows: vec![LeaderboardRow {
+                rank: 1,
+                name: "Testy McTestington".to_string(),
+                level: 12,
+                percentage: 60,
+                current: 60,
+                needed: 100,
+                avatar: VALK_PFP.to_string(),
+            }],
+            customizations,
+        };
+        let output = state.sync_render_leaderboard(&leaderboard, ImageFormat::default())?;
+        std::fs::write(
+            format!("rendered-cards/leaderboard_test_gradient_{card}.png"),
+            output,
+        )
+        .unwrap();
+    }
+    Ok(())
+}
+
+fn render_themes() -> Result<(), Error> {
+    let state = new_state();
+    for theme in &state.resources().config().themes {
+        let customizations = state.resources().default_customizations().with_theme(
+            &theme.palette,
+            &theme.font,
+            &theme.card,
+        );
+        let context = Context {
+            level: 12,
+            rank: 3,
+            name: "Testy McTestington".to_string(),
+            percentage: 60,
+            current: 60,
+            needed: 100,
+            customizations,
+            avatar: VALK_PFP.to_string(),
+            reward: None,
+            next_reward: None,
+            background_image: None,
+            layout_owner: None,
+        };
+        let output = state.sync_render(&context, ImageFormat::default())?;
+        std::fs::write(
+            format!("rendered-cards/theme_{}.png", theme.internal_name),
+            output,
+        )
+        .unwrap();
+    }
+    Ok(())
+}
+
+fn render_rewards() -> Result<(), Error> {
+    let state = new_state();
+    let next_reward = NextReward {
+        role: RewardRole {
+            name: "Regular".to_string(),
+            color: Some(Color::new(88, 101, 242)),
+            emoji: None,
+            icon: None,
+        },
+        level: 20,
+        xp_needed: 12_345,
+    };
+    let rewards = [
+        RewardRole {
+            name: "Chatterbox".to_string(),
+            color: Some(Color::new(241, 196, 15)),
+            emoji: None,
+            icon: Some(VALK_PFP.to_string()),
+        },
+        RewardRole {
+            name: "Newcomer".to_string(),
+            color: None,
+            emoji: None,
+            icon: None,
+        },
+    ];
+    for card in ["classic.svg", "vertical.svg"] {
+        for (i, reward) in rewards.iter().enumerate() {
+            let context = Context {
+                level: 12,
+                rank: 3,
+                name: "Testy McTestington".to_string(),
+                percentage: 60,
+                current: 60,
+                needed: 100,
+                customizations: state.resources().customizations_for(card).unwrap().clone(),
+                avatar: VALK_PFP.to_string(),
+                reward: Some(reward.clone()),
+                next_reward: Some(next_reward.clone()),
+                background_image: None,
+                layout_owner: None,
+            };
+            let output = state.sync_render(&context, ImageFormat::default())?;
+            std::fs::write(
+                format!("rendered-cards/renderer_test_rewards_{i}_{card}.png"),
+                output,
+            )
+            .unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Names in scripts the card fonts don't cover, which need fallback fonts
+const SCRIPT_NAMES: [(&str, &str); 6] = [
+    ("cyrillic", "Дмитрий Иванов"),
+    ("greek", "Ελένη Παππά"),
+    ("arabic", "محمد الأحمد"),
+    ("hebrew", "שרה כהן 42"),
+    ("cjk", "山田太郎"),
+    ("emoji", "Party 🎉 Time ✨"),
+];
+
+fn render_scripts() -> Result<(), Error> {
+    let state = new_state();
+    for card in ["classic.svg", "vertical.svg"] {
+        for (script, name) in SCRIPT_NAMES {
+            let context = Context {
+                level: 5,
+                rank: 42,
+                name: name.to_string(),
+                percentage: 30,
+                current: 30,
+                needed: 100,
+                customizations: state.resources().customizations_for(card).unwrap().clone(),
+                avatar: VALK_PFP.to_string(),
+                reward: None,
+                next_reward: None,
+                background_image: None,
+                layout_owner: None,
+            };
+            let output = state.sync_render(&context, ImageFormat::default())?;
+            std::fs::write(
+                format!("rendered-cards/renderer_test_script_{script}_{card}.png"),
+                output,
+            )
+            .unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// An uploaded layout, like a guild would make for their own branding
+const CUSTOM_LAYOUT_TEMPLATE: &str = r##"<svg width="1200" height="300" xmlns="http://www.w3.org/2000/svg">
+  <defs>{% include "gradients.svg" %}</defs>
+  <rect width="1200" height="300" rx="40" fill="{{ customizations.background }}" />
+  <rect x="20" y="20" width="1160" height="260" rx="30" fill="none" stroke="{{ customizations.border }}" stroke-width="8" />
+  <image x="50" y="50" width="200" height="200" href="{{ avatar }}" />
+  <text x="290" y="120" font-size="64" fill="{{ customizations.username }}">{{ name | bidi_isolate }}</text>
+  <text x="290" y="200" font-size="48" fill="{{ customizations.level }}">Level {{ level }} · #{{ rank }}</text>
+  {% if reward %}<text x="1150" y="200" font-size="36" text-anchor="end" fill="{% if reward.color %}{{ reward.color }}{% else %}{{ customizations.rank }}{% endif %}">{{ reward.name | bidi_isolate }}</text>{% endif %}
+  <rect x="290" y="230" width="860" height="24" rx="12" fill="{{ customizations.progress_background }}" />
+  <rect x="290" y="230" width="{{ percentage * 8.6 }}" height="24" rx="12" fill="{{ customizations.progress_foreground }}" />
+</svg>"##;
+
+fn render_custom_layout() -> Result<(), Error> {
+    let state = new_state();
+    state.sync_register_layout(1, CUSTOM_LAYOUT_TEMPLATE)?;
+    let mut customizations = state.resources().default_customizations().clone();
+    customizations.internal_name = CUSTOM_LAYOUT.to_string();
+    customizations.background = Color::new(20, 20, 40);
+    let context = Context {
+        level: 12,
+        rank: 3,
+        name: "Testy McTestington".to_string(),
+        percentage: 60,
+        current: 60,
+        needed: 100,
+        customizations,
+        avatar: VALK_PFP.to_string(),
+        reward: Some(RewardRole {
+            name: "Artist".to_string(),
+            color: Some(Color::new(241, 196, 15)),
+            emoji: None,
+            icon: None,
+        }),
+        next_reward: None,
+        background_image: None,
+        layout_owner: Some(1),
+    };
+    let output = state.sync_render(&context, ImageFormat::default())?;
+    std::fs::write("rendered-cards/renderer_test_custom_layout.png", output).unwrap();
+    Ok(())
+}
+
 fn render_vertical_procedural() {
     let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(100);
     std::fs::create_dir_all("rendered-cards/test-procedural/").unwrap();
@@ -98,10 +490,18 @@ fn render_vertical_procedural() {
                 percentage: xp,
                 current: xp,
                 needed: 100 - xp,
-                customizations: state.customizations_for("vertical.svg").unwrap().clone(),
+                customizations: state
+                    .resources()
+                    .customizations_for("vertical.svg")
+                    .unwrap()
+                    .clone(),
                 avatar: VALK_PFP.to_string(),
+                reward: None,
+                next_reward: None,
+                background_image: None,
+                layout_owner: None,
             };
-            let output = state.sync_render(&context).unwrap();
+            let output = state.sync_render(&context, ImageFormat::default()).unwrap();
             std::fs::write(
                 format!("rendered-cards/test-procedural/renderer_test_vertical_{xp:0>3}xp.png"),
                 output,
@@ -0,0 +1,61 @@
+//! Check a card resource pack, and draw its cards with edge-case data.
+//!
+//! Usage: `xpd-card-lint [resource directory] [render directory]`
+//!
+//! Every problem found is printed to stdout as one line of JSON. Sample renders are
+//! written to the render directory. Exits with an error if any problem is an error.
+
+use std::{path::PathBuf, process::ExitCode};
+
+use xpd_rank_card::{lint, SampleRender, Severity};
+
+fn main() -> ExitCode {
+    let mut args = std::env::args_os().skip(1);
+    let data_dir = args
+        .next()
+        .map_or_else(|| PathBuf::from("xpd-card-resources"), PathBuf::from);
+    let render_dir = args
+        .next()
+        .map_or_else(|| PathBuf::from("lint-renders"), PathBuf::from);
+
+    let report = lint(&data_dir);
+    for diagnostic in &report.diagnostics {
+        let line = serde_json::to_string(diagnostic).expect("Diagnostics are always valid JSON");
+        println!("{line}");
+    }
+    if let Err(source) = write_renders(&render_dir, &report.renders) {
+        eprintln!(
+            "Failed to write sample renders to {}: {source}",
+            render_dir.display()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let count = |severity| {
+        report
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    };
+    eprintln!(
+        "{} errors, {} warnings. Wrote {} sample renders to {}",
+        count(Severity::Error),
+        count(Severity::Warning),
+        report.renders.len(),
+        render_dir.display()
+    );
+    if report.has_errors() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn write_renders(render_dir: &PathBuf, renders: &[SampleRender]) -> std::io::Result<()> {
+    std::fs::create_dir_all(render_dir)?;
+    for render in renders {
+        std::fs::write(render_dir.join(&render.name), &render.png)?;
+    }
+    Ok(())
+}
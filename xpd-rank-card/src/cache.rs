@@ -0,0 +1,223 @@
+use std::{
+    fmt::Display,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use hashlink::LinkedHashMap;
+
+/// A least-recently-used cache of byte buffers, bounded by the total size of its values
+/// and by how long each value may live.
+pub struct ByteCache<K> {
+    entries: Mutex<Entries<K>>,
+    max_bytes: usize,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct Entries<K> {
+    // front is least recently used
+    map: LinkedHashMap<K, Entry>,
+    bytes: usize,
+}
+
+struct Entry {
+    created: Instant,
+    value: Arc<Vec<u8>>,
+}
+
+impl<K: Hash + Eq> ByteCache<K> {
+    #[must_use]
+    pub fn new(max_bytes: usize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                map: LinkedHashMap::new(),
+                bytes: 0,
+            }),
+            max_bytes,
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Fetch a value, marking it as recently used. Expired values are dropped.
+    pub fn get(&self, key: &K) -> Option<Arc<Vec<u8>>> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let expired = match entries.map.to_back(key) {
+            Some(entry) if entry.created.elapsed() < self.ttl => {
+                let value = entry.value.clone();
+                drop(entries);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(value);
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            entries.remove(key);
+        }
+        drop(entries);
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Store a value, evicting the least recently used values until it fits.
+    /// Values larger than the whole cache are not stored.
+    pub fn insert(&self, key: K, value: Arc<Vec<u8>>) {
+        if value.len() > self.max_bytes {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.remove(&key);
+        while entries.bytes + value.len() > self.max_bytes {
+            let Some((_, evicted)) = entries.map.pop_front() else {
+                break;
+            };
+            entries.bytes -= evicted.value.len();
+        }
+        entries.bytes += value.len();
+        entries.map.insert(
+            key,
+            Entry {
+                created: Instant::now(),
+                value,
+            },
+        );
+    }
+
+    /// Drop every value whose key matches `evict`
+    pub fn remove_matching(&self, evict: impl Fn(&K) -> bool) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut removed = 0;
+        entries.map.retain(|key, entry| {
+            let keep = !evict(key);
+            if !keep {
+                removed += entry.value.len();
+            }
+            keep
+        });
+        entries.bytes -= removed;
+    }
+
+    /// Drop every value, keeping the hit and miss counts
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.map.clear();
+        entries.bytes = 0;
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let (len, bytes) = (entries.map.len(), entries.bytes);
+        drop(entries);
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: len,
+            bytes,
+        }
+    }
+}
+
+impl<K: Hash + Eq> Entries<K> {
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.map.remove(key) {
+            self.bytes -= entry.value.len();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl Display for CacheStats {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} entries ({:.1} MiB)",
+            self.hits,
+            self.misses,
+            self.entries,
+            self.bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::ByteCache;
+
+    fn value(len: usize) -> Arc<Vec<u8>> {
+        Arc::new(vec![0; len])
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = ByteCache::new(30, Duration::from_mins(1));
+        cache.insert(1, value(10));
+        cache.insert(2, value(10));
+        cache.insert(3, value(10));
+        // 1 is now the most recently used
+        assert!(cache.get(&1).is_some());
+        cache.insert(4, value(10));
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&3).is_some());
+        assert!(cache.get(&4).is_some());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (4, 1));
+        assert_eq!((stats.entries, stats.bytes), (3, 30));
+    }
+
+    #[test]
+    fn replacing_keeps_size() {
+        let cache = ByteCache::new(30, Duration::from_mins(1));
+        cache.insert(1, value(10));
+        cache.insert(1, value(20));
+        assert_eq!(cache.stats().bytes, 20);
+    }
+
+    #[test]
+    fn oversized_values_are_skipped() {
+        let cache = ByteCache::new(30, Duration::from_mins(1));
+        cache.insert(1, value(10));
+        cache.insert(2, value(31));
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&1).is_some());
+    }
+
+    #[test]
+    fn removes_matching_keys() {
+        let cache = ByteCache::new(30, Duration::from_mins(1));
+        cache.insert(1, value(10));
+        cache.insert(2, value(10));
+        cache.insert(3, value(10));
+        cache.remove_matching(|key| key % 2 == 1);
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&2).is_some());
+        assert_eq!(cache.stats().bytes, 10);
+    }
+
+    #[test]
+    fn entries_expire() {
+        let cache = ByteCache::new(30, Duration::ZERO);
+        cache.insert(1, value(10));
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.stats().bytes, 0);
+    }
+}
@@ -2,14 +2,23 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
-use crate::customizations::Customizations;
+use crate::customizations::{Customizations, Palette};
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Config {
     pub defaults: Defaults,
     pub fonts: Vec<ConfigItem>,
+    /// Fonts tried, in order, for characters the card's font doesn't have
+    #[serde(default)]
+    pub fallback_fonts: Vec<FallbackFontItem>,
     pub toys: Vec<ConfigItem>,
     pub cards: Vec<CardItem>,
+    #[serde(default)]
+    pub leaderboards: Vec<LeaderboardItem>,
+    #[serde(default)]
+    pub comparisons: Vec<ComparisonItem>,
+    #[serde(default)]
+    pub themes: Vec<ThemeItem>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
@@ -24,14 +33,61 @@ pub struct ConfigItem {
     pub display_name: String,
 }
 
+/// A font which is never picked directly, but fills in characters other fonts lack,
+/// like emoji and non-Latin scripts.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FallbackFontItem {
+    pub file: PathBuf,
+    /// Family name of the font, as it is named inside the font file
+    pub family: String,
+    /// Optional fallback fonts are skipped when their file is missing, so large fonts
+    /// don't have to be shipped everywhere.
+    #[serde(default)]
+    pub optional: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct CardItem {
     pub file: PathBuf,
     pub display_name: String,
+    /// Width and height of the area a custom background image covers.
+    /// Layouts without this don't support background images.
+    #[serde(default)]
+    pub background_size: Option<(u32, u32)>,
     #[serde(flatten)]
     pub customizations: Customizations,
 }
 
+/// A leaderboard template, which is drawn with the colors and font of the card it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LeaderboardItem {
+    pub file: PathBuf,
+    /// Internal name of the card this leaderboard belongs to
+    pub card: String,
+}
+
+/// A template comparing two members, which is drawn with the colors and font of the
+/// card it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ComparisonItem {
+    pub file: PathBuf,
+    /// Internal name of the card this comparison belongs to
+    pub card: String,
+}
+
+/// A preset look for a card, which sets every color, the font and the layout at once.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ThemeItem {
+    pub internal_name: String,
+    pub display_name: String,
+    /// Internal name of the card layout this theme uses
+    pub card: String,
+    /// Internal name of the font this theme uses
+    pub font: String,
+    #[serde(flatten)]
+    pub palette: Palette,
+}
+
 pub trait NameableItem {
     fn display_name(&self) -> &str;
     fn internal_name(&self) -> &str;
@@ -56,3 +112,13 @@ impl NameableItem for CardItem {
         &self.customizations.internal_name
     }
 }
+
+impl NameableItem for ThemeItem {
+    fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    fn internal_name(&self) -> &str {
+        &self.internal_name
+    }
+}
@@ -2,7 +2,7 @@ use serde::{de::Visitor, Deserialize, Deserializer};
 
 use crate::Error;
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Customizations {
     pub username: Color,
     pub rank: Color,
@@ -16,6 +16,23 @@ pub struct Customizations {
     pub font: String,
     pub toy: Option<String>,
     pub internal_name: String,
+    /// Render animated avatars as an animated card
+    #[serde(default)]
+    pub animated_avatar: bool,
+}
+
+/// Every color on a card, without the rest of its settings
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Palette {
+    pub username: Color,
+    pub rank: Color,
+    pub level: Color,
+    pub border: Color,
+    pub background: Color,
+    pub progress_foreground: Color,
+    pub progress_background: Color,
+    pub background_xp_count: Color,
+    pub foreground_xp_count: Color,
 }
 
 macro_rules! add_output {
@@ -30,6 +47,59 @@ macro_rules! add_output {
 }
 
 impl Customizations {
+    /// Every color setting, with the name of its field
+    #[must_use]
+    pub const fn colors(&self) -> [(&'static str, Color); 9] {
+        [
+            ("username", self.username),
+            ("rank", self.rank),
+            ("level", self.level),
+            ("border", self.border),
+            ("background", self.background),
+            ("progress_foreground", self.progress_foreground),
+            ("progress_background", self.progress_background),
+            ("background_xp_count", self.background_xp_count),
+            ("foreground_xp_count", self.foreground_xp_count),
+        ]
+    }
+
+    /// The colors of these customizations
+    #[must_use]
+    pub const fn palette(&self) -> Palette {
+        Palette {
+            username: self.username,
+            rank: self.rank,
+            level: self.level,
+            border: self.border,
+            background: self.background,
+            progress_foreground: self.progress_foreground,
+            progress_background: self.progress_background,
+            background_xp_count: self.background_xp_count,
+            foreground_xp_count: self.foreground_xp_count,
+        }
+    }
+
+    /// These customizations with the colors, font and layout of a theme.
+    /// The toy and animation settings are kept.
+    #[must_use]
+    pub fn with_theme(&self, palette: &Palette, font: &str, card: &str) -> Self {
+        Self {
+            username: palette.username,
+            rank: palette.rank,
+            level: palette.level,
+            border: palette.border,
+            background: palette.background,
+            progress_foreground: palette.progress_foreground,
+            progress_background: palette.progress_background,
+            background_xp_count: palette.background_xp_count,
+            foreground_xp_count: palette.foreground_xp_count,
+            font: font.to_owned(),
+            toy: self.toy.clone(),
+            internal_name: card.to_owned(),
+            animated_avatar: self.animated_avatar,
+        }
+    }
+
     /// Generate a user representation of their customizations
     /// # Errors
     /// Never, realistically. Just uses write! internally.
@@ -74,43 +144,158 @@ impl Customizations {
                 .map_or_else(|| "None".to_owned(), ToString::to_string)
         )?;
         add_output!(f, "Card", self.internal_name, defaults.internal_name);
+        add_output!(
+            f,
+            "Animated avatar",
+            self.animated_avatar,
+            defaults.animated_avatar
+        );
         Ok(f)
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct Color {
-    red: u8,
-    green: u8,
-    blue: u8,
+/// A color setting on a card: either a single color, or a two-color linear gradient.
+///
+/// Colors are written as 6 or 8 digit hex (with optional alpha) or as CSS color names.
+/// Gradients are two colors and an optional CSS angle, separated by commas,
+/// like `#ff0000,#0000ff,45deg`. Gradients without an angle go from left to right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    Solid(Rgba),
+    Gradient(Gradient),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rgba {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Gradient {
+    pub from: Rgba,
+    pub to: Rgba,
+    /// CSS gradient angle in degrees: 0 points up, 90 points right
+    pub angle: u16,
 }
 
+/// Gradients without an angle go from left to right
+const DEFAULT_GRADIENT_ANGLE: u16 = 90;
+
 impl Color {
-    /// Takes hex-color input and converts it to a Color.
+    /// Parses a color or gradient, as described on [`Color`].
     /// # Errors
-    /// Errors if the hex color is invalid
-    pub fn from_hex(hex: &impl AsRef<str>) -> Result<Self, Error> {
-        let hex = hex.as_ref();
-        let hex = hex.trim_start_matches('#');
-        if hex.len() != 6 {
-            return Err(Error::InvalidLength);
+    /// Errors if the color or gradient is invalid
+    pub fn parse(input: &impl AsRef<str>) -> Result<Self, Error> {
+        let input = input.as_ref();
+        let invalid = || Error::InvalidColor(input.to_owned());
+        let mut parts = input.split(',').map(str::trim);
+        let first = parts.next().ok_or_else(invalid)?;
+        let Some(second) = parts.next() else {
+            return Ok(Self::Solid(Rgba::parse(first).ok_or_else(invalid)?));
+        };
+        let angle = match parts.next() {
+            Some(angle) => {
+                let degrees: u16 = angle.trim_end_matches("deg").trim_end().parse()?;
+                degrees % 360
+            }
+            None => DEFAULT_GRADIENT_ANGLE,
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
         }
-        Ok(Self {
-            red: u8::from_str_radix(&hex[0..=1], 16)?,
-            green: u8::from_str_radix(&hex[2..=3], 16)?,
-            blue: u8::from_str_radix(&hex[4..=5], 16)?,
-        })
+        Ok(Self::Gradient(Gradient {
+            from: Rgba::parse(first).ok_or_else(invalid)?,
+            to: Rgba::parse(second).ok_or_else(invalid)?,
+            angle,
+        }))
     }
 
     #[must_use]
     pub const fn new(red: u8, green: u8, blue: u8) -> Self {
-        Self { red, green, blue }
+        Self::Solid(Rgba {
+            red,
+            green,
+            blue,
+            alpha: u8::MAX,
+        })
+    }
+
+    /// The SVG paint for this color. Gradients refer to a `<linearGradient>` with the id `gradient_id`.
+    #[must_use]
+    pub fn paint(&self, gradient_id: &str) -> String {
+        match self {
+            Self::Solid(color) => color.to_string(),
+            Self::Gradient(_) => format!("url(#{gradient_id})"),
+        }
+    }
+}
+
+impl Rgba {
+    fn parse(input: &str) -> Option<Self> {
+        // Hex colors have always been allowed without the leading #
+        let is_bare_hex =
+            matches!(input.len(), 6 | 8) && input.chars().all(|c| c.is_ascii_hexdigit());
+        let color: svgtypes::Color = if is_bare_hex {
+            format!("#{input}").parse().ok()?
+        } else {
+            input.parse().ok()?
+        };
+        Some(Self {
+            red: color.red,
+            green: color.green,
+            blue: color.blue,
+            alpha: color.alpha,
+        })
+    }
+
+    /// The color without its alpha channel, as `#RRGGBB`
+    #[must_use]
+    pub fn opaque_hex(&self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.red, self.green, self.blue)
+    }
+
+    /// The alpha channel as an SVG opacity, from 0 to 1
+    #[must_use]
+    pub fn opacity(&self) -> f32 {
+        f32::from(self.alpha) / f32::from(u8::MAX)
+    }
+}
+
+impl Gradient {
+    /// The start and end points of this gradient, as fractions of the bounding box
+    /// of the element it fills, in the order `(x1, y1, x2, y2)`.
+    #[must_use]
+    pub fn vector(&self) -> (f32, f32, f32, f32) {
+        let (sin, cos) = f32::from(self.angle).to_radians().sin_cos();
+        let round = |v: f32| (v * 1000.0).round() / 1000.0;
+        (
+            round(0.5 - sin / 2.0),
+            round(0.5 + cos / 2.0),
+            round(0.5 + sin / 2.0),
+            round(0.5 - cos / 2.0),
+        )
+    }
+}
+
+impl std::fmt::Display for Rgba {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.red, self.green, self.blue)?;
+        if self.alpha != u8::MAX {
+            write!(f, "{:02X}", self.alpha)?;
+        }
+        Ok(())
     }
 }
 
 impl std::fmt::Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "#{:02X}{:02X}{:02X}", self.red, self.green, self.blue)
+        match self {
+            Self::Solid(color) => write!(f, "{color}"),
+            Self::Gradient(Gradient { from, to, angle }) => write!(f, "{from},{to},{angle}deg"),
+        }
     }
 }
 
@@ -138,20 +323,26 @@ impl<'de> Visitor<'de> for ColorVisitor {
     type Value = Color;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("a hex string or a 3-byte array")
+        formatter.write_str("a color string or a 3 or 4 byte array")
     }
 
     fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        let [r, g, b] = v else {
-            return Err(E::invalid_length(
+        match v {
+            [r, g, b] => Ok(Color::new(*r, *g, *b)),
+            [red, green, blue, alpha] => Ok(Color::Solid(Rgba {
+                red: *red,
+                green: *green,
+                blue: *blue,
+                alpha: *alpha,
+            })),
+            _ => Err(E::invalid_length(
                 v.len(),
-                &"must deserialize to a length 3 byte array",
-            ));
-        };
-        Ok(Color::new(*r, *g, *b))
+                &"must deserialize to a length 3 or 4 byte array",
+            )),
+        }
     }
 
     fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
@@ -169,19 +360,12 @@ impl<'de> Visitor<'de> for ColorVisitor {
     where
         E: serde::de::Error,
     {
-        if v.len() == 7 && v.len() == 6 {
-            Err(E::invalid_length(
-                v.len(),
-                &"hex str must be 6 or 7 bytes long",
-            ))
-        } else {
-            Color::from_hex(&v).map_err(|_| {
-                E::invalid_value(
-                    serde::de::Unexpected::Other("String was not valid hex"),
-                    &"String must be valid hex with an optional # at the start",
-                )
-            })
-        }
+        Color::parse(&v).map_err(|_| {
+            E::invalid_value(
+                serde::de::Unexpected::Str(v),
+                &"a hex color, CSS color name, or two colors and an angle separated by commas",
+            )
+        })
     }
 }
 
@@ -195,11 +379,41 @@ mod test {
         color: Color,
     }
 
+    #[test]
+    fn parses_colors() {
+        assert_eq!(Color::parse(&"#FF8000").unwrap(), Color::new(255, 128, 0));
+        assert_eq!(Color::parse(&"ff8000").unwrap(), Color::new(255, 128, 0));
+        assert_eq!(Color::parse(&"red").unwrap(), Color::new(255, 0, 0));
+        let translucent = Color::parse(&"#ff800080").unwrap();
+        assert_eq!(translucent.to_string(), "#FF800080");
+        assert!(Color::parse(&"#ff80").is_ok());
+        assert!(matches!(
+            Color::parse(&"notacolor"),
+            Err(Error::InvalidColor(_))
+        ));
+    }
+
+    #[test]
+    fn parses_gradients() {
+        let gradient = Color::parse(&"red, #0000ff80, 180deg").unwrap();
+        assert_eq!(gradient.to_string(), "#FF0000,#0000FF80,180deg");
+        assert_eq!(Color::parse(&gradient.to_string()).unwrap(), gradient);
+        let Color::Gradient(gradient) = gradient else {
+            panic!("expected a gradient");
+        };
+        assert_eq!(gradient.vector(), (0.5, 0.0, 0.5, 1.0));
+        let Color::Gradient(default) = Color::parse(&"red,blue").unwrap() else {
+            panic!("expected a gradient");
+        };
+        assert_eq!(default.vector(), (0.0, 0.5, 1.0, 0.5));
+        assert!(Color::parse(&"red,blue,90deg,green").is_err());
+        assert!(Color::parse(&"red,blue,sideways").is_err());
+    }
+
     #[test]
     fn basic_roundtrip() {
         let mut shared_serialized = Vec::with_capacity(32);
         for red in u8::MIN..=u8::MAX {
-            eprintln!("red progress: {red}");
             for green in u8::MIN..=u8::MAX {
                 for blue in u8::MIN..=u8::MAX {
                     let color = Color::new(red, green, blue);
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use resvg::tiny_skia::Pixmap;
+
+use crate::Error;
+
+/// How rendered cards and leaderboards are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Png(PngCompression),
+    /// Lossless WebP. Cards come out several times smaller than as PNG, and encode about as fast.
+    WebpLossless,
+    /// Lossy WebP, with a quality from 0 to 100. Much smaller than lossless for cards with
+    /// photo backgrounds, but text gets slightly blurry at low qualities.
+    WebpLossy {
+        quality: u8,
+    },
+}
+
+/// Quality of lossy WebP when none is given
+pub const DEFAULT_WEBP_QUALITY: u8 = 85;
+
+/// How hard PNG encoding tries to make files small. Smaller files take longer to encode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PngCompression {
+    #[default]
+    Fast,
+    Balanced,
+    Best,
+}
+
+impl ImageFormat {
+    /// File extension for images in this format, without the dot
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png(_) => "png",
+            Self::WebpLossless | Self::WebpLossy { .. } => "webp",
+        }
+    }
+
+    /// Encode a rendered image in this format
+    /// # Errors
+    /// Errors if the encoder does, which will almost always be a library bug.
+    pub fn encode(self, pixmap: &Pixmap) -> Result<Vec<u8>, Error> {
+        let rgba = demultiplied_rgba(pixmap);
+        let mut output = Vec::new();
+        match self {
+            Self::Png(compression) => {
+                let mut encoder = png::Encoder::new(&mut output, pixmap.width(), pixmap.height());
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                match compression {
+                    PngCompression::Fast => encoder.set_compression(png::Compression::Fast),
+                    PngCompression::Balanced => encoder.set_compression(png::Compression::Default),
+                    PngCompression::Best => {
+                        encoder.set_compression(png::Compression::Best);
+                        encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+                    }
+                }
+                encoder.write_header()?.write_image_data(&rgba)?;
+            }
+            Self::WebpLossless => {
+                image_webp::WebPEncoder::new(&mut output).encode(
+                    &rgba,
+                    pixmap.width(),
+                    pixmap.height(),
+                    image_webp::ColorType::Rgba8,
+                )?;
+            }
+            Self::WebpLossy { quality } => {
+                let encoded = webp::Encoder::from_rgba(&rgba, pixmap.width(), pixmap.height())
+                    .encode_simple(false, f32::from(quality.min(100)))
+                    .map_err(Error::WebpLossyEncode)?;
+                output.extend_from_slice(&encoded);
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Default for ImageFormat {
+    fn default() -> Self {
+        Self::Png(PngCompression::default())
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = UnknownImageFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" | "png-fast" => Ok(Self::Png(PngCompression::Fast)),
+            "png-balanced" => Ok(Self::Png(PngCompression::Balanced)),
+            "png-best" => Ok(Self::Png(PngCompression::Best)),
+            "webp" => Ok(Self::WebpLossless),
+            "webp-lossy" => Ok(Self::WebpLossy {
+                quality: DEFAULT_WEBP_QUALITY,
+            }),
+            _ => s
+                .strip_prefix("webp-lossy-")
+                .and_then(|quality| quality.parse().ok())
+                .filter(|quality| *quality <= 100)
+                .map(|quality| Self::WebpLossy { quality })
+                .ok_or_else(|| UnknownImageFormat(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Unknown image format `{0}`, expected png, png-fast, png-balanced, png-best, webp, \
+     webp-lossy or webp-lossy-<quality from 0 to 100>"
+)]
+pub struct UnknownImageFormat(String);
+
+/// Pixmaps store premultiplied alpha, but image formats want it straight
+fn demultiplied_rgba(pixmap: &Pixmap) -> Vec<u8> {
+    pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use resvg::tiny_skia::{Color, Pixmap};
+
+    use super::{ImageFormat, PngCompression, DEFAULT_WEBP_QUALITY};
+
+    #[test]
+    fn encodes_every_format() {
+        let mut pixmap = Pixmap::new(32, 16).unwrap();
+        pixmap.fill(Color::from_rgba8(255, 128, 0, 128));
+        for format in [
+            ImageFormat::Png(PngCompression::Fast),
+            ImageFormat::Png(PngCompression::Balanced),
+            ImageFormat::Png(PngCompression::Best),
+        ] {
+            let png = format.encode(&pixmap).unwrap();
+            let reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+            assert_eq!(reader.info().size(), (32, 16));
+        }
+        for format in [
+            ImageFormat::WebpLossless,
+            ImageFormat::WebpLossy { quality: 0 },
+            ImageFormat::WebpLossy { quality: 100 },
+        ] {
+            let webp = format.encode(&pixmap).unwrap();
+            let mut decoder = image_webp::WebPDecoder::new(std::io::Cursor::new(webp)).unwrap();
+            assert_eq!(decoder.dimensions(), (32, 16));
+            assert_eq!(decoder.is_lossy(), format != ImageFormat::WebpLossless);
+        }
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!(
+            "webp".parse::<ImageFormat>().unwrap(),
+            ImageFormat::WebpLossless
+        );
+        assert_eq!(
+            "png-best".parse::<ImageFormat>().unwrap(),
+            ImageFormat::Png(PngCompression::Best)
+        );
+        assert_eq!(
+            "webp-lossy".parse::<ImageFormat>().unwrap(),
+            ImageFormat::WebpLossy {
+                quality: DEFAULT_WEBP_QUALITY
+            }
+        );
+        assert_eq!(
+            "webp-lossy-60".parse::<ImageFormat>().unwrap(),
+            ImageFormat::WebpLossy { quality: 60 }
+        );
+        assert!("webp-lossy-101".parse::<ImageFormat>().is_err());
+        assert!("avif".parse::<ImageFormat>().is_err());
+    }
+}
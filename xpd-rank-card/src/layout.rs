@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use resvg::usvg::roxmltree;
+use tera::Tera;
+
+/// Internal name of a layout uploaded by a guild. Cards with this layout are drawn
+/// with their owner's template.
+pub const CUSTOM_LAYOUT: &str = "custom.svg";
+/// Largest template a guild can upload
+pub const MAX_LAYOUT_BYTES: usize = 64 * 1024;
+/// Largest width and height of any rendered card
+pub const MAX_RENDER_DIMENSION: u32 = 4096;
+/// Largest area of any rendered card, so one render can't hold more than a 16 MiB pixmap
+pub const MAX_RENDER_PIXELS: u64 = 4 * 1024 * 1024;
+/// Uploaded layouts which take longer than this to render a card are refused
+pub const MAX_LAYOUT_RENDER_TIME: Duration = Duration::from_secs(1);
+/// Largest SVG an uploaded layout may render to
+pub const MAX_LAYOUT_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+
+/// Everything resvg draws, minus scripting, animation, links and foreign content.
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "svg",
+    "g",
+    "defs",
+    "symbol",
+    "use",
+    "style",
+    "title",
+    "desc",
+    "rect",
+    "circle",
+    "ellipse",
+    "line",
+    "polyline",
+    "polygon",
+    "path",
+    "text",
+    "tspan",
+    "textPath",
+    "image",
+    "clipPath",
+    "mask",
+    "pattern",
+    "marker",
+    "linearGradient",
+    "radialGradient",
+    "stop",
+    "filter",
+    "feBlend",
+    "feColorMatrix",
+    "feComponentTransfer",
+    "feComposite",
+    "feDropShadow",
+    "feFlood",
+    "feFuncA",
+    "feFuncB",
+    "feFuncG",
+    "feFuncR",
+    "feGaussianBlur",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+];
+
+/// Tera tags a layout may use. Anything which pulls in other templates or defines
+/// macros could recurse forever, so it is left out.
+const ALLOWED_TAGS: &[&str] = &[
+    "if",
+    "elif",
+    "else",
+    "endif",
+    "for",
+    "endfor",
+    "break",
+    "continue",
+    "set",
+    "set_global",
+    "filter",
+    "endfilter",
+    "raw",
+    "endraw",
+    "include",
+];
+
+/// Tera functions which can allocate without bound, or read the environment
+const FORBIDDEN_FUNCTIONS: &[&str] = &["range", "get_env", "get_random", "now", "throw"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    #[error("Layouts can be at most {} KiB", MAX_LAYOUT_BYTES / 1024)]
+    TooLarge,
+    #[error("Layouts can't use the `{0}` tag")]
+    ForbiddenTag(String),
+    #[error("Layouts can't use the `{0}` function")]
+    ForbiddenFunction(String),
+    #[error("Layouts can't put a `for` loop inside another one")]
+    NestedLoop,
+    #[error("Layouts can't use `{0}` inside a `for` loop")]
+    AssignmentInLoop(String),
+    #[error("`set` can only use numbers, comparisons and plain values, not `{0}`")]
+    ForbiddenAssignment(char),
+    #[error("Layouts can only include \"gradients.svg\", not {0}")]
+    ForbiddenInclude(String),
+    #[error("Layouts can't use `<{0}>` elements")]
+    ForbiddenElement(String),
+    #[error("Layouts can't use the `{0}` attribute")]
+    ForbiddenAttribute(String),
+    #[error("Layouts can only link to embedded PNG or JPEG images, toys, the avatar or their own elements, not `{0}`")]
+    ForbiddenHref(String),
+    #[error("Layouts can only use `url()` to refer to their own elements, not `{0}`")]
+    ForbiddenUrl(String),
+    #[error("Layouts can't use `@import`")]
+    ForbiddenImport,
+    #[error("The layout isn't a valid template: {0}")]
+    Template(String),
+    #[error("The layout doesn't render to valid SVG: {0}")]
+    InvalidSvg(String),
+    #[error("The layout failed to render: {0}")]
+    Render(String),
+    #[error("The layout took {0:?} to render, the limit is {MAX_LAYOUT_RENDER_TIME:?}")]
+    TooSlow(Duration),
+    #[error("The layout rendered to more than {} MiB of SVG", MAX_LAYOUT_OUTPUT_BYTES / 1024 / 1024)]
+    OutputTooLarge,
+}
+
+/// Check the template source of an uploaded layout for tags and functions it may not use.
+/// This only looks at Tera syntax. The SVG it renders to is checked with [`check_svg`].
+///
+/// Loops can't be nested or assign variables, so the work a loop does is bounded by the
+/// length of the list it walks, rather than growing with every pass. Assignments can't join
+/// strings or lists, so no variable can grow larger than the template and its context.
+pub fn check_template(template: &str) -> Result<(), LayoutError> {
+    if template.len() > MAX_LAYOUT_BYTES {
+        return Err(LayoutError::TooLarge);
+    }
+    for (opener, closer) in [("{%", "%}"), ("{{", "}}")] {
+        let mut rest = template;
+        let mut in_loop = false;
+        while let Some(start) = rest.find(opener) {
+            let after = &rest[start + opener.len()..];
+            let end = after.find(closer).unwrap_or(after.len());
+            let block = after[..end].trim_matches(|c: char| c == '-' || c.is_whitespace());
+            if opener == "{%" {
+                check_tag(block)?;
+                in_loop = check_loop(block, in_loop)?;
+                check_assignment(block)?;
+            }
+            check_functions(block)?;
+            rest = &after[end..];
+        }
+    }
+    Ok(())
+}
+
+fn check_tag(block: &str) -> Result<(), LayoutError> {
+    let (tag, args) = block.split_once(char::is_whitespace).unwrap_or((block, ""));
+    if !ALLOWED_TAGS.contains(&tag) {
+        return Err(LayoutError::ForbiddenTag(tag.to_string()));
+    }
+    let args = args.trim();
+    if tag == "include" && args != "\"gradients.svg\"" {
+        return Err(LayoutError::ForbiddenInclude(args.to_string()));
+    }
+    Ok(())
+}
+
+/// Only let `set` copy values and do arithmetic. Anything which builds strings or lists could
+/// double a variable with every assignment, and never write output for the render limits to see.
+fn check_assignment(block: &str) -> Result<(), LayoutError> {
+    let (tag, value) = block.split_once(char::is_whitespace).unwrap_or((block, ""));
+    if tag != "set" && tag != "set_global" {
+        return Ok(());
+    }
+    let mut quote = None;
+    for c in value.chars() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            None if matches!(c, '"' | '\'' | '`') => quote = Some(c),
+            None if matches!(c, '~' | '|' | '[') => {
+                return Err(LayoutError::ForbiddenAssignment(c));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether the template is inside a `for` loop after `block`
+fn check_loop(block: &str, in_loop: bool) -> Result<bool, LayoutError> {
+    let tag = block.split_whitespace().next().unwrap_or_default();
+    match tag {
+        "for" if in_loop => Err(LayoutError::NestedLoop),
+        "for" => Ok(true),
+        "endfor" => Ok(false),
+        "set" | "set_global" if in_loop => Err(LayoutError::AssignmentInLoop(tag.to_string())),
+        _ => Ok(in_loop),
+    }
+}
+
+fn check_functions(block: &str) -> Result<(), LayoutError> {
+    for name in FORBIDDEN_FUNCTIONS {
+        for (index, _) in block.match_indices(name) {
+            let starts_word = block[..index]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !(c.is_alphanumeric() || c == '_'));
+            let is_call = block[index + name.len()..].trim_start().starts_with('(');
+            if starts_word && is_call {
+                return Err(LayoutError::ForbiddenFunction((*name).to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check a rendered layout for elements and links resvg shouldn't see. `images` are the
+/// toys, which templates refer to by name.
+pub fn check_svg(svg: &str, images: &HashMap<String, Arc<Vec<u8>>>) -> Result<(), LayoutError> {
+    let document =
+        roxmltree::Document::parse(svg).map_err(|e| LayoutError::InvalidSvg(e.to_string()))?;
+    for node in document.descendants() {
+        if node.is_text()
+            && node
+                .parent_element()
+                .is_some_and(|p| p.has_tag_name("style"))
+        {
+            let css = node.text().unwrap_or_default();
+            if css.contains("@import") {
+                return Err(LayoutError::ForbiddenImport);
+            }
+            check_urls(css)?;
+        }
+        if !node.is_element() {
+            continue;
+        }
+        let tag = node.tag_name();
+        if tag.namespace() != Some(SVG_NAMESPACE) || !ALLOWED_ELEMENTS.contains(&tag.name()) {
+            return Err(LayoutError::ForbiddenElement(tag.name().to_string()));
+        }
+        for attribute in node.attributes() {
+            let name = attribute.name();
+            if name.starts_with("on") {
+                return Err(LayoutError::ForbiddenAttribute(name.to_string()));
+            }
+            if name == "href" {
+                check_href(attribute.value(), images)?;
+            }
+            check_urls(attribute.value())?;
+        }
+    }
+    Ok(())
+}
+
+fn check_href(href: &str, images: &HashMap<String, Arc<Vec<u8>>>) -> Result<(), LayoutError> {
+    let allowed = href.starts_with('#')
+        || href.starts_with("data:image/png;base64,")
+        || href.starts_with("data:image/jpeg;base64,")
+        || href == crate::BACKGROUND_IMAGE_HREF
+        || href == crate::ANIMATED_AVATAR_HREF
+        || images.contains_key(href);
+    if allowed {
+        Ok(())
+    } else {
+        let shown: String = href.chars().take(64).collect();
+        Err(LayoutError::ForbiddenHref(shown))
+    }
+}
+
+fn check_urls(value: &str) -> Result<(), LayoutError> {
+    for (index, _) in value.match_indices("url(") {
+        let target = value[index + 4..]
+            .trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'');
+        if !target.starts_with('#') {
+            let shown: String = target.chars().take(64).collect();
+            return Err(LayoutError::ForbiddenUrl(shown));
+        }
+    }
+    Ok(())
+}
+
+/// Render an uploaded layout, giving up once it has taken longer than
+/// [`MAX_LAYOUT_RENDER_TIME`] since `start`, or written more than [`MAX_LAYOUT_OUTPUT_BYTES`].
+/// Tera can't be interrupted, so the limits are checked every time it writes output.
+/// # Errors
+/// Errors if the template fails to render, or hits either limit.
+pub fn render(tera: &Tera, context: &tera::Context, start: Instant) -> Result<String, LayoutError> {
+    let mut output = LimitedWriter {
+        buffer: Vec::new(),
+        start,
+        exceeded: None,
+    };
+    let rendered = tera.render_to(CUSTOM_LAYOUT, context, &mut output);
+    if let Some(error) = output.exceeded {
+        return Err(error);
+    }
+    rendered.map_err(|e| LayoutError::Template(error_chain(&e)))?;
+    String::from_utf8(output.buffer).map_err(|e| LayoutError::Render(e.to_string()))
+}
+
+struct LimitedWriter {
+    buffer: Vec<u8>,
+    start: Instant,
+    exceeded: Option<LayoutError>,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let elapsed = self.start.elapsed();
+        if elapsed > MAX_LAYOUT_RENDER_TIME {
+            self.exceeded = Some(LayoutError::TooSlow(elapsed));
+        } else if self.buffer.len() + buf.len() > MAX_LAYOUT_OUTPUT_BYTES {
+            self.exceeded = Some(LayoutError::OutputTooLarge);
+        }
+        if self.exceeded.is_some() {
+            return Err(std::io::Error::other("layout render limit exceeded"));
+        }
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Every cause of an error, for users who need to find the mistake in their template
+pub fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use tera::Tera;
+
+    use super::{check_svg, check_template, render, LayoutError, CUSTOM_LAYOUT};
+
+    fn svg(body: &str) -> String {
+        format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">{body}</svg>"#)
+    }
+
+    #[test]
+    fn allows_card_templates() {
+        for template in [
+            include_str!("../../xpd-card-resources/cards/classic.svg"),
+            include_str!("../../xpd-card-resources/cards/vertical.svg"),
+        ] {
+            check_template(template).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_dangerous_tags() {
+        assert!(matches!(
+            check_template(r#"{% include "custom.svg" %}"#),
+            Err(LayoutError::ForbiddenInclude(_))
+        ));
+        assert!(matches!(
+            check_template("{% macro a() %}{% endmacro %}"),
+            Err(LayoutError::ForbiddenTag(_))
+        ));
+        assert!(matches!(
+            check_template("{% for i in range(end=1000000000) %}{% endfor %}"),
+            Err(LayoutError::ForbiddenFunction(_))
+        ));
+        assert!(matches!(
+            check_template(r#"{{ get_env(name="DATABASE_URL") }}"#),
+            Err(LayoutError::ForbiddenFunction(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_unbounded_loops() {
+        check_template("{% for c in name %}{{ c }}{% endfor %}{% for c in name %}{% endfor %}")
+            .unwrap();
+        assert!(matches!(
+            check_template("{% for a in name %}{% for b in name %}{% endfor %}{% endfor %}"),
+            Err(LayoutError::NestedLoop)
+        ));
+        assert!(matches!(
+            check_template("{% for c in name %}{%- set_global s = s ~ s -%}{% endfor %}"),
+            Err(LayoutError::AssignmentInLoop(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_growing_assignments() {
+        check_template(r#"{% set_global w = (percentage * 14) + 80 %}{% set s = "a~b|c[d]" %}"#)
+            .unwrap();
+        let doubling = format!(
+            "{{% set s = name ~ name %}}{}",
+            "{% set s = s ~ s %}".repeat(3000)
+        );
+        assert!(matches!(
+            check_template(&doubling),
+            Err(LayoutError::ForbiddenAssignment('~'))
+        ));
+        assert!(matches!(
+            check_template("{% set s = name | replace(from=\"a\", to=name) %}"),
+            Err(LayoutError::ForbiddenAssignment('|'))
+        ));
+        assert!(matches!(
+            check_template("{% set_global s = [name, name] %}"),
+            Err(LayoutError::ForbiddenAssignment('['))
+        ));
+    }
+
+    #[test]
+    fn render_stops_at_limits() {
+        let mut tera = Tera::default();
+        tera.add_raw_template(CUSTOM_LAYOUT, "{% for c in items %}{{ c }}{% endfor %}")
+            .unwrap();
+        let mut context = tera::Context::new();
+        context.insert("items", &vec!["x"; 16]);
+        assert_eq!(
+            render(&tera, &context, Instant::now()).unwrap(),
+            "x".repeat(16)
+        );
+        let late = Instant::now().checked_sub(Duration::from_secs(2)).unwrap();
+        assert!(matches!(
+            render(&tera, &context, late),
+            Err(LayoutError::TooSlow(_))
+        ));
+        context.insert("items", &vec!["x".repeat(1024 * 1024); 5]);
+        assert!(matches!(
+            render(&tera, &context, Instant::now()),
+            Err(LayoutError::OutputTooLarge)
+        ));
+    }
+
+    #[test]
+    fn refuses_dangerous_svg() {
+        let images = HashMap::new();
+        check_svg(
+            &svg(r##"<rect fill="url(#gradient)" /><use href="#a" />"##),
+            &images,
+        )
+        .unwrap();
+        assert!(matches!(
+            check_svg(&svg("<script>alert(1)</script>"), &images),
+            Err(LayoutError::ForbiddenElement(_))
+        ));
+        assert!(matches!(
+            check_svg(
+                &svg(r#"<image href="https://example.com/a.png" />"#),
+                &images
+            ),
+            Err(LayoutError::ForbiddenHref(_))
+        ));
+        assert!(matches!(
+            check_svg(&svg(r#"<rect onclick="alert(1)" />"#), &images),
+            Err(LayoutError::ForbiddenAttribute(_))
+        ));
+        assert!(matches!(
+            check_svg(
+                &svg("<style>rect { fill: url(https://example.com) }</style>"),
+                &images
+            ),
+            Err(LayoutError::ForbiddenUrl(_))
+        ));
+        assert!(matches!(
+            check_svg(
+                &svg(
+                    r#"<foreignObject><div xmlns="http://www.w3.org/1999/xhtml" /></foreignObject>"#
+                ),
+                &images
+            ),
+            Err(LayoutError::ForbiddenElement(_))
+        ));
+    }
+}
@@ -1,24 +1,58 @@
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+mod animation;
+mod background;
+pub mod cache;
 #[allow(clippy::module_name_repetitions)]
 mod config;
 pub mod customizations;
+mod encoding;
+mod layout;
+mod lint;
+mod pool;
 
-use std::{collections::HashMap, ops::Deref, path::Path, sync::Arc, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
 
-use customizations::Customizations;
-use rayon::ThreadPoolBuilder;
-use resvg::usvg::{
-    fontdb::{Database, Family, Query},
-    ImageKind, ImageRendering,
+use cache::{ByteCache, CacheStats};
+use customizations::{Color, Customizations};
+use pool::RenderPool;
+use rayon::prelude::*;
+use resvg::{
+    tiny_skia::Pixmap,
+    usvg::{
+        fontdb::{Database, Family, Query, ID},
+        FontResolver, ImageKind, ImageRendering,
+    },
 };
+use sha2::{Digest, Sha256};
 use tera::{Tera, Value};
-use tracing::debug;
+use tracing::{debug, info, warn};
 
-pub use crate::config::{CardItem, Config, ConfigItem, NameableItem};
+pub use crate::{
+    animation::{
+        MAX_ANIMATED_CARD_BYTES, MAX_ANIMATION_FRAMES, MAX_ANIMATION_PIXELS, MAX_AVATAR_DIMENSION,
+    },
+    config::{
+        CardItem, ComparisonItem, Config, ConfigItem, FallbackFontItem, LeaderboardItem,
+        NameableItem, ThemeItem,
+    },
+    encoding::{ImageFormat, PngCompression, UnknownImageFormat, DEFAULT_WEBP_QUALITY},
+    layout::{
+        LayoutError, CUSTOM_LAYOUT, MAX_LAYOUT_BYTES, MAX_RENDER_DIMENSION, MAX_RENDER_PIXELS,
+    },
+    lint::{lint, Diagnostic, LintReport, SampleRender, Severity},
+    pool::{PoolOptions, PoolStats},
+};
 
 /// Context is the main argument of [`InnerSvgState::render`], and takes parameters for what to put on
 /// the card.
-#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Context {
     /// Level of the user for display
     pub level: u64,
@@ -36,6 +70,116 @@ pub struct Context {
     pub customizations: customizations::Customizations,
     /// Base64-encoded PNG string.
     pub avatar: String,
+    /// The highest reward role the user has earned
+    pub reward: Option<RewardRole>,
+    /// The next reward role the user can earn
+    pub next_reward: Option<NextReward>,
+    /// PNG background image, as made by [`InnerSvgState::prepare_background`].
+    /// Templates see this as an href in `background_image`.
+    #[serde(skip)]
+    pub background_image: Option<Arc<Vec<u8>>>,
+    /// Owner of the uploaded layout this card is drawn with, when `customizations`
+    /// use [`CUSTOM_LAYOUT`]. Cards whose owner has no layout use the default card.
+    #[serde(skip)]
+    pub layout_owner: Option<u64>,
+}
+
+/// A reward role, as shown on a card
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RewardRole {
+    /// Name of the role
+    pub name: String,
+    /// Color of the role. Templates pick their own color for roles without one.
+    pub color: Option<customizations::Color>,
+    /// Unicode emoji the role uses as its icon
+    pub emoji: Option<String>,
+    /// Base64-encoded PNG string of the role's icon image
+    pub icon: Option<String>,
+}
+
+/// A reward role the user has not earned yet
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NextReward {
+    pub role: RewardRole,
+    /// Level the role is given at
+    pub level: u64,
+    /// XP the user still needs to reach `level`
+    pub xp_needed: u64,
+}
+
+/// Rendered cards are kept for repeated requests, like `/rank` spam during events.
+/// A card changes whenever its user gains XP, so entries don't need to live long.
+const RENDER_CACHE_BYTES: usize = 64 * 1024 * 1024;
+const RENDER_CACHE_TTL: Duration = Duration::from_mins(5);
+
+/// Templates can `{% include "gradients.svg" %}` inside their `<defs>` to define
+/// every gradient their customizations use.
+const GRADIENTS_TEMPLATE_NAME: &str = "gradients.svg";
+const GRADIENTS_TEMPLATE: &str = r#"{% for gradient in gradients %}
+<linearGradient id="{{ gradient.id }}" x1="{{ gradient.x1 }}" y1="{{ gradient.y1 }}" x2="{{ gradient.x2 }}" y2="{{ gradient.y2 }}">
+  <stop offset="0" stop-color="{{ gradient.from }}" stop-opacity="{{ gradient.from_opacity }}" />
+  <stop offset="1" stop-color="{{ gradient.to }}" stop-opacity="{{ gradient.to_opacity }}" />
+</linearGradient>
+{% endfor %}"#;
+
+/// Templates see this as the `background_image` of a card with a custom background
+const BACKGROUND_IMAGE_HREF: &str = "custom-background";
+
+/// Templates see this as the avatar of an animated card. Each frame is rendered
+/// with this href pointing at a different frame of the avatar.
+const ANIMATED_AVATAR_HREF: &str = "animated-avatar-frame";
+
+/// The most rows a [`LeaderboardContext`] can show. Any more are not rendered.
+pub const MAX_LEADERBOARD_ROWS: usize = 10;
+
+/// [`LeaderboardContext`] is the main argument of [`InnerSvgState::render_leaderboard`],
+/// and holds one page of a leaderboard.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LeaderboardContext {
+    /// Rows to display, from the top down. At most [`MAX_LEADERBOARD_ROWS`] are drawn.
+    pub rows: Vec<LeaderboardRow>,
+    /// Customization data. The template is picked from the card in `internal_name`.
+    pub customizations: customizations::Customizations,
+}
+
+/// A single user on a [`LeaderboardContext`]
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LeaderboardRow {
+    /// Rank of the user for display
+    pub rank: i64,
+    /// Username
+    pub name: String,
+    /// Level of the user for display
+    pub level: u64,
+    /// Percentage of the way to the next level, out of 100
+    pub percentage: u64,
+    /// Current XP count
+    pub current: u64,
+    /// Total XP needed to complete this level
+    pub needed: u64,
+    /// Base64-encoded PNG string. May be empty, in which case a placeholder is drawn.
+    pub avatar: String,
+}
+
+/// [`CompareContext`] is the main argument of [`SvgState::render_compare`], and puts
+/// two members side by side.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CompareContext {
+    /// The member being compared, drawn on the left
+    pub left: LeaderboardRow,
+    /// The member they are compared with, drawn on the right
+    pub right: LeaderboardRow,
+    /// XP between the two members
+    pub xp_gap: u64,
+    /// Levels between the two members
+    pub level_gap: u64,
+    /// Places on the leaderboard between the two members
+    pub rank_gap: u64,
+    /// Messages the member behind has to send to catch up, at the guild's average XP
+    /// per message. `None` when messages don't give any XP.
+    pub messages_to_catch_up: Option<u64>,
+    /// Customization data. The template is picked from the card in `internal_name`.
+    pub customizations: customizations::Customizations,
 }
 
 #[derive(Clone)]
@@ -47,21 +191,133 @@ impl SvgState {
     /// # Errors
     /// This function usually fails when your manifest.toml is invalid.
     pub fn new(path: impl AsRef<Path>) -> Result<Self, NewSvgStateError> {
-        Ok(Self(Arc::new(InnerSvgState::new(path.as_ref())?)))
+        Self::with_pool_options(path, PoolOptions::default())
     }
 
-    /// this function renders an SVG on the internal thread pool, and returns PNG-encoded image
-    /// data on completion.
+    /// Create a new [`SvgState`], with a differently sized render pool
+    ///
+    /// # Errors
+    /// This function usually fails when your manifest.toml is invalid.
+    pub fn with_pool_options(
+        path: impl AsRef<Path>,
+        options: PoolOptions,
+    ) -> Result<Self, NewSvgStateError> {
+        Ok(Self(Arc::new(InnerSvgState::with_pool_options(
+            path.as_ref(),
+            options,
+        )?)))
+    }
+
+    /// this function renders an SVG on the internal thread pool, and returns image data
+    /// encoded in `format` on completion.
     /// # Errors
     /// Errors on [`resvg`](https://docs.rs/resvg) library failure. This will almost always be a library bug.
-    pub async fn render(&self, data: Context) -> Result<Vec<u8>, Error> {
+    pub async fn render(&self, data: Context, format: ImageFormat) -> Result<Vec<u8>, Error> {
+        // Cache keys digest the avatar and background, so even cached cards are looked up
+        // on the pool rather than the async runtime
         let cloned_self = self.clone();
-        let (send, recv) = tokio::sync::oneshot::channel();
         debug!("starting async render of SVG");
-        self.threads.spawn(move || {
-            send.send(cloned_self.sync_render(&data)).ok();
-        });
-        recv.await?
+        self.pool
+            .run(move || cloned_self.sync_render(&data, format))
+            .await?
+    }
+
+    /// Render a card with an animated avatar on the internal thread pool, and return
+    /// GIF-encoded image data on completion. `avatar_gif` is the raw GIF avatar, and
+    /// the `avatar` in `data` is ignored.
+    /// # Errors
+    /// Errors if the avatar can't be decoded, or is too large, or the resulting animation is
+    /// larger than [`MAX_ANIMATED_CARD_BYTES`]. Callers should fall back to [`Self::render`].
+    pub async fn render_animated(
+        &self,
+        data: Context,
+        avatar_gif: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        let cloned_self = self.clone();
+        debug!("starting async render of animated SVG");
+        self.pool
+            .run(move || cloned_self.sync_render_animated(&data, &avatar_gif))
+            .await?
+    }
+
+    /// Validate an uploaded background image, then crop and resize it for a card layout
+    /// on the internal thread pool. Returns PNG-encoded image data on completion.
+    /// # Errors
+    /// Errors if the image isn't a PNG, JPEG, GIF or WebP, if it is too small or too large,
+    /// or if the layout doesn't support backgrounds.
+    pub async fn prepare_background(&self, card: String, image: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let cloned_self = self.clone();
+        self.pool
+            .run(move || cloned_self.sync_prepare_background(&card, image))
+            .await?
+    }
+
+    /// Validate and register an uploaded layout on the internal thread pool.
+    /// See [`InnerSvgState::sync_register_layout`].
+    /// # Errors
+    /// Errors if the layout fails validation.
+    pub async fn register_layout(&self, owner: u64, template: String) -> Result<(), Error> {
+        let cloned_self = self.clone();
+        Ok(self
+            .pool
+            .run(move || cloned_self.sync_register_layout(owner, &template))
+            .await??)
+    }
+
+    /// Validate an uploaded layout on the internal thread pool, without registering it.
+    /// See [`InnerSvgState::sync_validate_layout`].
+    /// # Errors
+    /// Errors if the layout fails validation.
+    pub async fn validate_layout(&self, template: String) -> Result<(), Error> {
+        let cloned_self = self.clone();
+        self.pool
+            .run(move || cloned_self.sync_validate_layout(&template).map(drop))
+            .await??;
+        Ok(())
+    }
+
+    /// Reload card resources from disk on the internal thread pool. Reloads render every
+    /// sample card, so they aren't held to the usual render deadline.
+    /// See [`InnerSvgState::sync_reload`].
+    /// # Errors
+    /// Errors if the new resources are invalid, in which case the old ones stay in use.
+    pub async fn reload(&self) -> Result<Arc<Resources>, NewSvgStateError> {
+        let cloned_self = self.clone();
+        self.pool
+            .run_with_deadline(None, move || cloned_self.sync_reload())
+            .await?
+    }
+
+    /// Render a leaderboard on the internal thread pool, and return image data encoded
+    /// in `format` on completion.
+    /// # Errors
+    /// Errors on [`resvg`](https://docs.rs/resvg) library failure. This will almost always be a library bug.
+    pub async fn render_leaderboard(
+        &self,
+        data: LeaderboardContext,
+        format: ImageFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let cloned_self = self.clone();
+        debug!("starting async render of leaderboard SVG");
+        self.pool
+            .run(move || cloned_self.sync_render_leaderboard(&data, format))
+            .await?
+    }
+
+    /// Render a comparison of two members on the internal thread pool, and return image
+    /// data encoded in `format` on completion.
+    /// # Errors
+    /// Errors on [`resvg`](https://docs.rs/resvg) library failure. This will almost always be a library bug.
+    pub async fn render_compare(
+        &self,
+        data: CompareContext,
+        format: ImageFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let cloned_self = self.clone();
+        debug!("starting async render of comparison SVG");
+        self.pool
+            .run(move || cloned_self.sync_render_compare(&data, format))
+            .await?
     }
 }
 
@@ -75,21 +331,377 @@ impl Deref for SvgState {
 
 /// This struct should be constructed with [`InnerSvgState::new`] to begin rendering rank cards
 pub struct InnerSvgState {
+    data_dir: PathBuf,
+    /// Replaced as a whole by [`Self::sync_reload`]
+    resources: RwLock<Arc<Resources>>,
+    pool: RenderPool,
+    render_cache: ByteCache<CardKey>,
+    /// Templates uploaded with [`SvgState::register_layout`], by owner
+    /// Uploaded layouts by owner. `None` marks a layout which failed validation, so it
+    /// isn't validated again on every render.
+    layouts: RwLock<HashMap<u64, Option<Arc<Tera>>>>,
+}
+
+impl InnerSvgState {
+    /// Create a new [`InnerSvgState`]
+    ///
+    /// # Errors
+    /// This function will error if your manifest lies or is invalid
+    pub fn new(data_dir: &Path) -> Result<Self, NewSvgStateError> {
+        Self::with_pool_options(data_dir, PoolOptions::default())
+    }
+
+    /// Create a new [`InnerSvgState`], with a differently sized render pool
+    ///
+    /// # Errors
+    /// This function will error if your manifest lies or is invalid
+    pub fn with_pool_options(
+        data_dir: &Path,
+        options: PoolOptions,
+    ) -> Result<Self, NewSvgStateError> {
+        let resources = Resources::new(data_dir)?;
+        Ok(Self {
+            data_dir: data_dir.to_path_buf(),
+            resources: RwLock::new(Arc::new(resources)),
+            pool: RenderPool::new(options)?,
+            render_cache: ByteCache::new(RENDER_CACHE_BYTES, RENDER_CACHE_TTL),
+            layouts: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// The card resources in use right now. Renders hold on to these until they finish,
+    /// so a reload halfway through a card never mixes old and new resources.
+    #[must_use]
+    pub fn resources(&self) -> Arc<Resources> {
+        self.resources
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Directory the card resources are loaded from
+    #[must_use]
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Load the resource directory again, and start using it if every card and
+    /// leaderboard renders. Renders already running finish with the old resources.
+    /// # Errors
+    /// Errors if the new resources are invalid, in which case the old ones stay in use.
+    pub fn sync_reload(&self) -> Result<Arc<Resources>, NewSvgStateError> {
+        let start = Instant::now();
+        let resources = Arc::new(Resources::new(&self.data_dir)?);
+        *self
+            .resources
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = resources.clone();
+        // Cached cards were drawn with the old templates and fonts
+        self.render_cache.clear();
+        info!(
+            path = ?self.data_dir,
+            millis_taken = start.elapsed().as_millis(),
+            "Reloaded card resources"
+        );
+        Ok(resources)
+    }
+
+    /// This function is very fast. It does not need to be async.
+    /// # Errors
+    /// Errors if tera has a problem
+    pub fn render_svg(&self, context: &Context) -> Result<String, Error> {
+        self.render_svg_with(&self.resources(), context)
+    }
+
+    fn render_svg_with(&self, resources: &Resources, context: &Context) -> Result<String, Error> {
+        let card = &context.customizations.internal_name;
+        if card != CUSTOM_LAYOUT {
+            return resources.render_card_svg(card, context);
+        }
+        let Some(layout) = context.layout_owner.and_then(|owner| self.layout(owner)) else {
+            return resources.render_card_svg(&resources.config.defaults.card, context);
+        };
+        // Templates can branch on anything in the context, so every render is held to
+        // the same limits as the samples, and checked
+        let svg = layout::render(&layout, &template_context(context)?, Instant::now())?;
+        layout::check_svg(&svg, &resources.images)?;
+        Ok(svg)
+    }
+
+    fn layout(&self, owner: u64) -> Option<Arc<Tera>> {
+        self.layouts
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&owner)
+            .cloned()
+            .flatten()
+    }
+
+    /// Whether `owner`'s uploaded layout has been registered, or failed to register
+    #[must_use]
+    pub fn has_layout(&self, owner: u64) -> bool {
+        self.layouts
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(&owner)
+    }
+
+    /// Check an uploaded layout, and compile it into its own [`Tera`].
+    /// Sample cards are rendered with it to make sure it renders quickly and only
+    /// draws things resvg can safely draw.
+    /// # Errors
+    /// Errors if the layout is too large, uses forbidden template features or SVG elements,
+    /// fails to render, or renders too slowly.
+    pub fn sync_validate_layout(&self, template: &str) -> Result<Tera, LayoutError> {
+        layout::check_template(template)?;
+        let mut tera = new_tera().map_err(|e| LayoutError::Template(layout::error_chain(&e)))?;
+        tera.add_raw_template(CUSTOM_LAYOUT, template)
+            .map_err(|e| LayoutError::Template(layout::error_chain(&e)))?;
+        let resources = self.resources();
+        let customizations = Customizations {
+            internal_name: CUSTOM_LAYOUT.to_string(),
+            ..resources.default.clone()
+        };
+        for context in resources.samples(customizations) {
+            let start = Instant::now();
+            let ctx = template_context(&context)
+                .map_err(|e| LayoutError::Render(layout::error_chain(&e)))?;
+            let svg = layout::render(&tera, &ctx, start)?;
+            layout::check_svg(&svg, &resources.images)?;
+            resources
+                .rasterize_pixmap(&svg, &context.customizations.font, Vec::new())
+                .map_err(|e| LayoutError::Render(layout::error_chain(&e)))?;
+            let elapsed = start.elapsed();
+            if elapsed > layout::MAX_LAYOUT_RENDER_TIME {
+                return Err(LayoutError::TooSlow(elapsed));
+            }
+        }
+        Ok(tera)
+    }
+
+    /// Render a card, encoded in `format`. Cards are cached, so rendering the same context
+    /// twice in a short time only draws it once.
+    /// # Errors
+    /// Errors if tera has a problem, or resvg does.
+    pub fn sync_render(&self, context: &Context, format: ImageFormat) -> Result<Vec<u8>, Error> {
+        let key = CardKey::new(context, format);
+        if let Some(image) = self.render_cache.get(&key) {
+            return Ok(image.to_vec());
+        }
+        let start = Instant::now();
+        let resources = self.resources();
+        let svg = self.render_svg_with(&resources, context)?;
+        let pixmap = resources.rasterize_pixmap(
+            &svg,
+            &context.customizations.font,
+            background_images(context),
+        )?;
+        let image = format.encode(&pixmap)?;
+        debug!(
+            micros_taken = start.elapsed().as_micros(),
+            ?format,
+            "Rendered SVG image"
+        );
+        self.render_cache.insert(key, Arc::new(image.clone()));
+        Ok(image)
+    }
+
+    /// Validate a layout with [`Self::sync_validate_layout`], and use it for cards owned by
+    /// `owner` from now on. This replaces any layout `owner` had before. A layout which fails
+    /// validation is remembered, and `owner`'s cards use the default card until
+    /// [`Self::remove_layout`] is called.
+    /// # Errors
+    /// Errors if the layout fails validation.
+    pub fn sync_register_layout(&self, owner: u64, template: &str) -> Result<(), LayoutError> {
+        let (tera, result) = match self.sync_validate_layout(template) {
+            Ok(tera) => (Some(Arc::new(tera)), Ok(())),
+            Err(error) => (None, Err(error)),
+        };
+        self.layouts
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(owner, tera);
+        self.evict_layout_cards(owner);
+        result
+    }
+
+    /// Stop using `owner`'s uploaded layout. Their cards fall back to the default card, or to
+    /// whatever layout is registered for them next.
+    pub fn remove_layout(&self, owner: u64) {
+        self.layouts
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&owner);
+        self.evict_layout_cards(owner);
+    }
+
+    /// Cached cards drawn with `owner`'s old layout, or without it
+    fn evict_layout_cards(&self, owner: u64) {
+        self.render_cache
+            .remove_matching(|key| key.layout_owner == Some(owner));
+    }
+
+    /// Hit and miss counts for the rendered card cache
+    #[must_use]
+    pub fn render_cache_stats(&self) -> CacheStats {
+        self.render_cache.stats()
+    }
+
+    /// Size, queue length and failure counts for the render thread pool
+    #[must_use]
+    pub fn render_pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// Render an animated GIF for a card, with one frame per (sampled) avatar frame.
+    /// # Errors
+    /// Errors if the avatar GIF is invalid or too large, if tera has a problem, or resvg does.
+    pub fn sync_render_animated(
+        &self,
+        context: &Context,
+        avatar_gif: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let start = Instant::now();
+        let avatar_frames = animation::decode_avatar(avatar_gif)?;
+        let resources = self.resources();
+        let svg = self.render_svg_with(
+            &resources,
+            &Context {
+                avatar: ANIMATED_AVATAR_HREF.to_string(),
+                ..context.clone()
+            },
+        )?;
+        let render_frame = |frame: &animation::AvatarFrame| {
+            let mut images = background_images(context);
+            images.push((ANIMATED_AVATAR_HREF, ImageKind::PNG(frame.png.clone())));
+            let pixmap = resources.rasterize_pixmap(&svg, &context.customizations.font, images)?;
+            Ok((pixmap, frame.delay))
+        };
+        let mut frames = Vec::with_capacity(avatar_frames.len());
+        if let Some((first, rest)) = avatar_frames.split_first() {
+            let first = render_frame(first)?;
+            // Check the whole animation fits before drawing the rest of it
+            let pixels = u64::from(first.0.width())
+                * u64::from(first.0.height())
+                * avatar_frames.len() as u64;
+            if pixels > MAX_ANIMATION_PIXELS {
+                return Err(Error::RenderArea {
+                    pixels,
+                    max: MAX_ANIMATION_PIXELS,
+                });
+            }
+            frames.push(first);
+            frames.extend(
+                rest.par_iter()
+                    .map(render_frame)
+                    .collect::<Result<Vec<_>, Error>>()?,
+            );
+        }
+        let gif = animation::encode_animation(&frames)?;
+        debug!(
+            micros_taken = start.elapsed().as_micros(),
+            frames = frames.len(),
+            "Rendered animated SVG image"
+        );
+        Ok(gif)
+    }
+
+    /// Render the SVG for a leaderboard. See [`Resources::render_leaderboard_svg`].
+    /// # Errors
+    /// Errors if tera has a problem
+    pub fn render_leaderboard_svg(&self, context: &LeaderboardContext) -> Result<String, Error> {
+        self.resources().render_leaderboard_svg(context)
+    }
+
+    /// Render a leaderboard, encoded in `format`.
+    /// # Errors
+    /// Errors if tera has a problem, or resvg does.
+    pub fn sync_render_leaderboard(
+        &self,
+        context: &LeaderboardContext,
+        format: ImageFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let start = Instant::now();
+        let pixmap = self.resources().render_leaderboard(context)?;
+        let image = format.encode(&pixmap)?;
+        debug!(
+            micros_taken = start.elapsed().as_micros(),
+            rows = context.rows.len(),
+            ?format,
+            "Rendered leaderboard SVG image"
+        );
+        Ok(image)
+    }
+
+    /// Render a comparison of two members, encoded in `format`.
+    /// # Errors
+    /// Errors if tera has a problem, or resvg does.
+    pub fn sync_render_compare(
+        &self,
+        context: &CompareContext,
+        format: ImageFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let start = Instant::now();
+        let pixmap = self.resources().render_compare(context)?;
+        let image = format.encode(&pixmap)?;
+        debug!(
+            micros_taken = start.elapsed().as_micros(),
+            ?format,
+            "Rendered comparison SVG image"
+        );
+        Ok(image)
+    }
+
+    /// Crop and resize a background image to fit a card layout, and encode it as a PNG.
+    /// # Errors
+    /// Errors if the image isn't a PNG, JPEG, GIF or WebP, if it is too small or too large,
+    /// or if the layout doesn't support backgrounds.
+    pub fn sync_prepare_background(&self, card: &str, image: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let resources = self.resources();
+        let (width, height) = resources
+            .config
+            .cards
+            .iter()
+            .find(|item| item.customizations.internal_name == card)
+            .and_then(|item| item.background_size)
+            .ok_or(Error::NoBackgroundSupport)?;
+        let image = background::validate(image)?;
+        let svg = background::fit_svg(width, height);
+        let png = resources
+            .rasterize_pixmap(&svg, "", vec![(BACKGROUND_IMAGE_HREF, image)])?
+            .encode_png()?;
+        Ok(png)
+    }
+}
+
+/// Everything loaded from the resource directory: the manifest, fonts, toys and templates.
+/// [`InnerSvgState::sync_reload`] replaces all of it at once.
+pub struct Resources {
     fontdb: Arc<Database>,
+    fallback_fonts: Arc<[ID]>,
     tera: Tera,
-    threads: rayon::ThreadPool,
     images: HashMap<String, Arc<Vec<u8>>>,
     defaults: HashMap<String, Customizations>,
     default: Customizations,
+    leaderboards: HashSet<String>,
+    comparisons: HashSet<String>,
     config: Config,
 }
 
-impl InnerSvgState {
-    /// Create a new [`InnerSvgState`]
+impl Resources {
+    /// Load and check the resources in `data_dir`. Every card and leaderboard is rendered
+    /// once, so templates which only fail when rendered are caught here.
     ///
     /// # Errors
     /// This function will error if your manifest lies or is invalid
     pub fn new(data_dir: &Path) -> Result<Self, NewSvgStateError> {
+        let resources = Self::load(data_dir)?;
+        resources.check()?;
+        Ok(resources)
+    }
+
+    /// Load the resources in `data_dir` without rendering anything
+    fn load(data_dir: &Path) -> Result<Self, NewSvgStateError> {
         let config_path = data_dir.join("manifest.toml");
         let config = std::fs::read_to_string(config_path)?;
         let config: Config = toml::from_str(&config)?;
@@ -105,12 +717,11 @@ impl InnerSvgState {
                 .query(&test_query)
                 .ok_or_else(|| NewSvgStateError::WrongFontName(font.internal_name.clone()))?;
         }
+        let fallback_fonts = load_fallback_fonts(&mut fonts, data_dir, &config.fallback_fonts)?;
 
         let mut defaults = HashMap::with_capacity(config.cards.len());
 
-        let mut tera = Tera::default();
-        tera.autoescape_on(vec!["svg", "html", "xml", "htm"]);
-        tera.register_filter("integerhumanize", int_humanize);
+        let mut tera = new_tera()?;
 
         let mut template_files = Vec::with_capacity(config.cards.len());
         for card in &config.cards {
@@ -123,18 +734,59 @@ impl InnerSvgState {
                 Some(card.customizations.internal_name.clone()),
             ));
         }
+        let leaderboards = card_templates(
+            data_dir,
+            &defaults,
+            config
+                .leaderboards
+                .iter()
+                .map(|item| (&item.card, &item.file)),
+            leaderboard_template_name,
+            NewSvgStateError::UnknownLeaderboardCard,
+            &mut template_files,
+        )?;
+        let comparisons = card_templates(
+            data_dir,
+            &defaults,
+            config
+                .comparisons
+                .iter()
+                .map(|item| (&item.card, &item.file)),
+            compare_template_name,
+            NewSvgStateError::UnknownComparisonCard,
+            &mut template_files,
+        )?;
         tera.add_template_files(template_files)?;
 
+        for theme in &config.themes {
+            if !defaults.contains_key(&theme.card) {
+                return Err(NewSvgStateError::UnknownThemeCard(theme.card.clone()));
+            }
+            if !config
+                .fonts
+                .iter()
+                .any(|font| font.internal_name == theme.font)
+            {
+                return Err(NewSvgStateError::UnknownThemeFont(theme.font.clone()));
+            }
+        }
+
         let default = defaults
             .get(&config.defaults.card)
             .ok_or(NewSvgStateError::InvalidDefault(
                 config.defaults.card.clone(),
             ))?
             .clone();
-
-        let threads = ThreadPoolBuilder::new()
-            .thread_name(|i| format!("svg-renderer-{i}"))
-            .build()?;
+        if !leaderboards.contains(&config.defaults.card) {
+            return Err(NewSvgStateError::MissingDefaultLeaderboard(
+                config.defaults.card,
+            ));
+        }
+        if !comparisons.contains(&config.defaults.card) {
+            return Err(NewSvgStateError::MissingDefaultComparison(
+                config.defaults.card,
+            ));
+        }
 
         let images = config
             .toys
@@ -149,12 +801,14 @@ impl InnerSvgState {
 
         Ok(Self {
             fontdb: Arc::new(fonts),
+            fallback_fonts: fallback_fonts.into(),
             tera,
-            threads,
             images,
             config,
             defaults,
             default,
+            leaderboards,
+            comparisons,
         })
     }
 
@@ -164,22 +818,99 @@ impl InnerSvgState {
         &self.config
     }
 
-    /// This function is very fast. It does not need to be async.
+    /// Render every card, leaderboard and comparison with sample data
+    fn check(&self) -> Result<(), NewSvgStateError> {
+        for card in &self.config.cards {
+            let name = &card.customizations.internal_name;
+            for context in self.samples(card.customizations.clone()) {
+                self.render_card_svg(name, &context)
+                    .and_then(|svg| {
+                        self.rasterize_pixmap(&svg, &context.customizations.font, Vec::new())
+                    })
+                    .map_err(|e| NewSvgStateError::SampleRender {
+                        template: name.clone(),
+                        reason: layout::error_chain(&e),
+                    })?;
+            }
+        }
+        for card in &self.leaderboards {
+            let [sample, _] = self.samples(self.defaults[card].clone());
+            let context = LeaderboardContext {
+                rows: vec![leaderboard_row(&sample); MAX_LEADERBOARD_ROWS],
+                customizations: sample.customizations,
+            };
+            self.render_leaderboard(&context)
+                .map_err(|e| NewSvgStateError::SampleRender {
+                    template: leaderboard_template_name(card),
+                    reason: layout::error_chain(&e),
+                })?;
+        }
+        for card in &self.comparisons {
+            let [ahead, behind] = self.samples(self.defaults[card].clone());
+            let context = sample_compare(&ahead, &behind);
+            self.render_compare(&context)
+                .map_err(|e| NewSvgStateError::SampleRender {
+                    template: compare_template_name(card),
+                    reason: layout::error_chain(&e),
+                })?;
+        }
+        Ok(())
+    }
+
+    fn samples(&self, customizations: Customizations) -> [Context; 2] {
+        sample_contexts(customizations, self.images.keys().next().cloned())
+    }
+
+    fn render_card_svg(&self, card: &str, context: &Context) -> Result<String, Error> {
+        Ok(self.tera.render(card, &template_context(context)?)?)
+    }
+
+    /// Render the SVG for a leaderboard. Rows past [`MAX_LEADERBOARD_ROWS`] are left out.
+    /// Cards without their own leaderboard template use the default card's.
     /// # Errors
     /// Errors if tera has a problem
-    pub fn render_svg(&self, context: &Context) -> Result<String, Error> {
-        let ctx = tera::Context::from_serialize(context)?;
-        Ok(self
-            .tera
-            .render(&context.customizations.internal_name, &ctx)?)
+    pub fn render_leaderboard_svg(&self, context: &LeaderboardContext) -> Result<String, Error> {
+        let card = &context.customizations.internal_name;
+        let template = if self.leaderboards.contains(card) {
+            leaderboard_template_name(card)
+        } else {
+            leaderboard_template_name(&self.config.defaults.card)
+        };
+        let ctx = leaderboard_template_context(context)?;
+        Ok(self.tera.render(&template, &ctx)?)
+    }
+
+    fn render_leaderboard(&self, context: &LeaderboardContext) -> Result<Pixmap, Error> {
+        let svg = self.render_leaderboard_svg(context)?;
+        self.rasterize_pixmap(&svg, &context.customizations.font, Vec::new())
     }
 
-    /// Render the PNG for a card.
+    /// Render the SVG for a comparison of two members.
+    /// Cards without their own comparison template use the default card's.
     /// # Errors
-    /// Errors if tera has a problem, or resvg does.
-    pub fn sync_render(&self, context: &Context) -> Result<Vec<u8>, Error> {
-        let start = Instant::now();
-        let svg = self.render_svg(context)?;
+    /// Errors if tera has a problem
+    pub fn render_compare_svg(&self, context: &CompareContext) -> Result<String, Error> {
+        let card = &context.customizations.internal_name;
+        let template = if self.comparisons.contains(card) {
+            compare_template_name(card)
+        } else {
+            compare_template_name(&self.config.defaults.card)
+        };
+        let ctx = compare_template_context(context)?;
+        Ok(self.tera.render(&template, &ctx)?)
+    }
+
+    fn render_compare(&self, context: &CompareContext) -> Result<Pixmap, Error> {
+        let svg = self.render_compare_svg(context)?;
+        self.rasterize_pixmap(&svg, &context.customizations.font, Vec::new())
+    }
+
+    fn rasterize_pixmap(
+        &self,
+        svg: &str,
+        font: &str,
+        extra_images: Vec<(&'static str, ImageKind)>,
+    ) -> Result<Pixmap, Error> {
         let resolve_data =
             Box::new(
                 |mime: &str, data: Arc<Vec<u8>>, _: &resvg::usvg::Options| match mime {
@@ -190,6 +921,9 @@ impl InnerSvgState {
             );
         let images_clone = self.images.clone();
         let resolve_string = Box::new(move |href: &str, _: &resvg::usvg::Options| {
+            if let Some((_, image)) = extra_images.iter().find(|(name, _)| *name == href) {
+                return Some(image.clone());
+            }
             debug!(href, "fetching toy image");
             images_clone.get(href).cloned().map(ImageKind::PNG)
         });
@@ -199,25 +933,38 @@ impl InnerSvgState {
                 resolve_string,
             },
             image_rendering: ImageRendering::OptimizeSpeed,
-            font_family: context.customizations.font.to_string(),
+            font_family: font.to_string(),
+            font_resolver: FontResolver {
+                select_font: FontResolver::default_font_selector(),
+                select_fallback: fallback_selector(self.fallback_fonts.clone()),
+            },
             fontdb: self.fontdb.clone(),
             ..Default::default()
         };
-        let tree = resvg::usvg::Tree::from_str(&svg, &opt)?;
+        let tree = resvg::usvg::Tree::from_str(svg, &opt)?;
         let pixmap_size = tree.size().to_int_size();
-        let mut pixmap = resvg::tiny_skia::Pixmap::new(pixmap_size.width(), pixmap_size.height())
-            .ok_or(Error::PixmapCreation)?;
+        if pixmap_size.width() > MAX_RENDER_DIMENSION || pixmap_size.height() > MAX_RENDER_DIMENSION
+        {
+            return Err(Error::RenderTooLarge {
+                width: pixmap_size.width(),
+                height: pixmap_size.height(),
+            });
+        }
+        let pixels = u64::from(pixmap_size.width()) * u64::from(pixmap_size.height());
+        if pixels > MAX_RENDER_PIXELS {
+            return Err(Error::RenderArea {
+                pixels,
+                max: MAX_RENDER_PIXELS,
+            });
+        }
+        let mut pixmap =
+            Pixmap::new(pixmap_size.width(), pixmap_size.height()).ok_or(Error::PixmapCreation)?;
         resvg::render(
             &tree,
             resvg::tiny_skia::Transform::default(),
             &mut pixmap.as_mut(),
         );
-        let png = pixmap.encode_png()?;
-        debug!(
-            micros_taken = start.elapsed().as_micros(),
-            "Rendered SVG image"
-        );
-        Ok(png)
+        Ok(pixmap)
     }
 
     #[must_use]
@@ -225,10 +972,307 @@ impl InnerSvgState {
         &self.default
     }
 
+    /// Default customizations for a card layout. Uploaded layouts use the default card's.
     #[must_use]
     pub fn customizations_for(&self, key: &str) -> Option<&Customizations> {
+        if key == CUSTOM_LAYOUT {
+            return Some(&self.default);
+        }
         self.defaults.get(key)
     }
+
+    #[must_use]
+    pub fn theme(&self, internal_name: &str) -> Option<&ThemeItem> {
+        self.config
+            .themes
+            .iter()
+            .find(|theme| theme.internal_name == internal_name)
+    }
+}
+
+/// A gradient, as templates see it in `gradients`
+#[derive(serde::Serialize)]
+struct GradientDefinition {
+    id: String,
+    x1: f32,
+    y1: f32,
+    x2: f32,
+    y2: f32,
+    from: String,
+    from_opacity: f32,
+    to: String,
+    to_opacity: f32,
+}
+
+fn new_tera() -> Result<Tera, tera::Error> {
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec!["svg", "html", "xml", "htm"]);
+    tera.register_filter("integerhumanize", int_humanize);
+    tera.register_filter("bidi_isolate", bidi_isolate);
+    tera.add_raw_template(GRADIENTS_TEMPLATE_NAME, GRADIENTS_TEMPLATE)?;
+    Ok(tera)
+}
+
+fn template_context(context: &Context) -> Result<tera::Context, Error> {
+    let mut ctx = tera::Context::from_serialize(context)?;
+    insert_customizations(&mut ctx, &context.customizations)?;
+    if context.background_image.is_some() {
+        ctx.insert("background_image", BACKGROUND_IMAGE_HREF);
+    }
+    Ok(ctx)
+}
+
+fn leaderboard_template_context(context: &LeaderboardContext) -> Result<tera::Context, Error> {
+    let rows = &context.rows[..context.rows.len().min(MAX_LEADERBOARD_ROWS)];
+    let mut ctx = tera::Context::new();
+    ctx.insert("rows", rows);
+    insert_customizations(&mut ctx, &context.customizations)?;
+    Ok(ctx)
+}
+
+fn compare_template_context(context: &CompareContext) -> Result<tera::Context, Error> {
+    let mut ctx = tera::Context::from_serialize(context)?;
+    insert_customizations(&mut ctx, &context.customizations)?;
+    Ok(ctx)
+}
+
+/// Cards with and without every optional part, so templates are checked down each branch
+fn sample_contexts(customizations: Customizations, toy: Option<String>) -> [Context; 2] {
+    let reward = RewardRole {
+        name: "Reward".to_string(),
+        color: Some(Color::new(255, 128, 0)),
+        emoji: Some("⭐".to_string()),
+        icon: None,
+    };
+    let full = Context {
+        level: 12,
+        rank: 3,
+        name: "Sample User".to_string(),
+        percentage: 60,
+        current: 600,
+        needed: 1000,
+        customizations: Customizations {
+            toy,
+            ..customizations.clone()
+        },
+        avatar: ANIMATED_AVATAR_HREF.to_string(),
+        reward: Some(reward.clone()),
+        next_reward: Some(NextReward {
+            role: reward,
+            level: 20,
+            xp_needed: 12_345,
+        }),
+        background_image: None,
+        layout_owner: None,
+    };
+    let bare = Context {
+        customizations: Customizations {
+            toy: None,
+            ..customizations
+        },
+        reward: None,
+        next_reward: None,
+        ..full.clone()
+    };
+    [full, bare]
+}
+
+/// The leaderboard row for the user on a card
+fn leaderboard_row(context: &Context) -> LeaderboardRow {
+    LeaderboardRow {
+        rank: context.rank,
+        name: context.name.clone(),
+        level: context.level,
+        percentage: context.percentage,
+        current: context.current,
+        needed: context.needed,
+        avatar: String::new(),
+    }
+}
+
+/// A comparison of two sample cards, with the first one ahead
+fn sample_compare(ahead: &Context, behind: &Context) -> CompareContext {
+    let behind = Context {
+        level: ahead.level / 2,
+        rank: ahead.rank + 4,
+        current: ahead.current / 3,
+        ..behind.clone()
+    };
+    CompareContext {
+        left: leaderboard_row(ahead),
+        right: leaderboard_row(&behind),
+        xp_gap: ahead.current - behind.current,
+        level_gap: ahead.level - behind.level,
+        rank_gap: behind.rank.abs_diff(ahead.rank),
+        messages_to_catch_up: Some(20),
+        customizations: ahead.customizations.clone(),
+    }
+}
+
+/// Queue up templates which belong to a card, like leaderboards, and return the cards
+/// which have one. `unknown_card` is the error for templates naming a card that doesn't exist.
+fn card_templates<'a>(
+    data_dir: &Path,
+    defaults: &HashMap<String, Customizations>,
+    items: impl Iterator<Item = (&'a String, &'a PathBuf)>,
+    template_name: fn(&str) -> String,
+    unknown_card: fn(String) -> NewSvgStateError,
+    template_files: &mut Vec<(PathBuf, Option<String>)>,
+) -> Result<HashSet<String>, NewSvgStateError> {
+    let mut cards = HashSet::new();
+    for (card, file) in items {
+        if !defaults.contains_key(card) {
+            return Err(unknown_card(card.clone()));
+        }
+        cards.insert(card.clone());
+        template_files.push((data_dir.join(file), Some(template_name(card))));
+    }
+    Ok(cards)
+}
+
+/// Load the manifest's fallback fonts, returning their faces in the order they should be tried.
+fn load_fallback_fonts(
+    fonts: &mut Database,
+    data_dir: &Path,
+    items: &[FallbackFontItem],
+) -> Result<Vec<ID>, NewSvgStateError> {
+    let mut ids = Vec::with_capacity(items.len());
+    for item in items {
+        let path = data_dir.join(&item.file);
+        if item.optional && !path.exists() {
+            warn!(
+                ?path,
+                family = item.family,
+                "Optional fallback font not found"
+            );
+            continue;
+        }
+        fonts.load_font_file(path)?;
+        let query = Query {
+            families: &[Family::Name(&item.family)],
+            ..Query::default()
+        };
+        let id = fonts
+            .query(&query)
+            .ok_or_else(|| NewSvgStateError::WrongFontName(item.family.clone()))?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// Try the manifest's fallback fonts in order before letting usvg pick any font with the character.
+fn fallback_selector(fallback_fonts: Arc<[ID]>) -> resvg::usvg::FallbackSelectionFn<'static> {
+    let default = FontResolver::default_fallback_selector();
+    Box::new(move |c, used, fontdb| {
+        fallback_fonts
+            .iter()
+            .copied()
+            .find(|id| !used.contains(id) && has_char(fontdb, *id, c))
+            .or_else(|| default(c, used, fontdb))
+    })
+}
+
+fn has_char(fontdb: &Database, id: ID, c: char) -> bool {
+    fontdb
+        .with_face_data(id, |data, index| {
+            ttf_parser::Face::parse(data, index)
+                .ok()
+                .and_then(|face| face.glyph_index(c))
+                .is_some()
+        })
+        .unwrap_or(false)
+}
+
+/// Give templates the customizations with every color replaced by its SVG paint,
+/// and the `<linearGradient>` data for colors which are gradients.
+fn insert_customizations(
+    ctx: &mut tera::Context,
+    customizations: &Customizations,
+) -> Result<(), Error> {
+    let mut value = tera::Context::from_serialize(customizations)?.into_json();
+    let mut gradients = Vec::new();
+    for (name, color) in customizations.colors() {
+        let id = format!("gradient-{name}");
+        value[name] = Value::String(color.paint(&id));
+        if let Color::Gradient(gradient) = color {
+            let (x1, y1, x2, y2) = gradient.vector();
+            gradients.push(GradientDefinition {
+                id,
+                x1,
+                y1,
+                x2,
+                y2,
+                from: gradient.from.opaque_hex(),
+                from_opacity: gradient.from.opacity(),
+                to: gradient.to.opaque_hex(),
+                to_opacity: gradient.to.opacity(),
+            });
+        }
+    }
+    ctx.insert("customizations", &value);
+    ctx.insert("gradients", &gradients);
+    Ok(())
+}
+
+fn background_images(context: &Context) -> Vec<(&'static str, ImageKind)> {
+    context
+        .background_image
+        .clone()
+        .map(|png| (BACKGROUND_IMAGE_HREF, ImageKind::PNG(png)))
+        .into_iter()
+        .collect()
+}
+
+/// Check that an image can be used as a card background, without fitting it to a layout.
+/// # Errors
+/// Errors if the image isn't a PNG, JPEG, GIF or WebP, or if it is too small or too large.
+pub fn check_background(image: Vec<u8>) -> Result<(), Error> {
+    background::validate(image).map(drop)
+}
+
+/// Cards are cached by a SHA-256 digest of their whole context, so two cards only share an
+/// entry when they are drawn from exactly the same data, without the cache holding a copy of
+/// every avatar and background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CardKey {
+    digest: [u8; 32],
+    format: ImageFormat,
+    /// Kept apart from the digest, so a changed layout only evicts its own cards
+    layout_owner: Option<u64>,
+}
+
+impl CardKey {
+    fn new(context: &Context, format: ImageFormat) -> Self {
+        let mut hasher = Sha256Hasher(Sha256::new());
+        context.hash(&mut hasher);
+        Self {
+            digest: hasher.0.finalize().into(),
+            format,
+            layout_owner: context.layout_owner,
+        }
+    }
+}
+
+/// Feeds a [`Hash`] implementation into SHA-256
+struct Sha256Hasher(Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_le_bytes(digest[..8].try_into().unwrap_or_default())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+fn leaderboard_template_name(card: &str) -> String {
+    format!("leaderboard/{card}")
+}
+
+fn compare_template_name(card: &str) -> String {
+    format!("compare/{card}")
 }
 
 fn config_item_tuple(ci: ConfigItem) -> Result<(String, Arc<Vec<u8>>), NewSvgStateError> {
@@ -262,6 +1306,16 @@ fn int_humanize(v: &Value, _hm: &HashMap<String, Value>) -> tera::Result<Value>
     Ok(Value::String(format!("{xp_trim}{suffix}")))
 }
 
+/// Wrap text in a first-strong isolate. usvg lays every line out left-to-right, so without
+/// this, right-to-left names with numbers or Latin text in them come out in the wrong order.
+#[allow(clippy::unnecessary_wraps)]
+fn bidi_isolate(v: &Value, _hm: &HashMap<String, Value>) -> tera::Result<Value> {
+    match v {
+        Value::String(text) => Ok(Value::String(format!("\u{2068}{text}\u{2069}"))),
+        _ => Ok(v.clone()),
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Tera error: {0}")]
@@ -276,8 +1330,42 @@ pub enum Error {
     Recv(#[from] tokio::sync::oneshot::error::RecvError),
     #[error("Pixmap Creation error!")]
     PixmapCreation,
-    #[error("Invalid length! Color hex data length must be exactly 6 characters!")]
-    InvalidLength,
+    #[error("`{0}` is not a color. Use hex like #FF8000 or #FF800080, a CSS color name, or a gradient like #FF8000,#0080FF,90deg")]
+    InvalidColor(String),
+    #[error("GIF decoding error: {0}")]
+    GifDecode(#[from] gif::DecodingError),
+    #[error("GIF encoding error: {0}")]
+    GifEncode(#[from] gif::EncodingError),
+    #[error("WebP encoding error: {0}")]
+    WebpEncode(#[from] image_webp::EncodingError),
+    #[error("Lossy WebP encoding error: {0:?}")]
+    WebpLossyEncode(webp::WebPEncodingError),
+    #[error("Animated avatar is too large!")]
+    AvatarTooLarge,
+    #[error("Animated card is too large!")]
+    AnimationTooLarge,
+    #[error("Background images must be PNG, JPEG, GIF or WebP files")]
+    UnsupportedBackground,
+    #[error(
+        "Background images must be between {min}x{min} and {max}x{max} pixels, this one is {width}x{height}",
+        min = background::MIN_DIMENSION,
+        max = background::MAX_DIMENSION
+    )]
+    BackgroundSize { width: usize, height: usize },
+    #[error("This card layout doesn't support background images")]
+    NoBackgroundSupport,
+    #[error("Cards can be at most {MAX_RENDER_DIMENSION}x{MAX_RENDER_DIMENSION} pixels, this one is {width}x{height}")]
+    RenderTooLarge { width: u32, height: u32 },
+    #[error("{0}")]
+    Layout(#[from] LayoutError),
+    #[error("The card renderer is busy, try again in a moment")]
+    RendererBusy,
+    #[error("Rendering took longer than {0:?}")]
+    RenderTimeout(Duration),
+    #[error("Renderer panicked: {0}")]
+    RenderPanicked(String),
+    #[error("This render needs {pixels} pixels, the limit is {max}")]
+    RenderArea { pixels: u64, max: u64 },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -294,4 +1382,20 @@ pub enum NewSvgStateError {
     WrongFontName(String),
     #[error("Default customization (name `{0}`) not found")]
     InvalidDefault(String),
+    #[error("Leaderboard is for unknown card `{0}`")]
+    UnknownLeaderboardCard(String),
+    #[error("Default card `{0}` has no leaderboard template")]
+    MissingDefaultLeaderboard(String),
+    #[error("Comparison is for unknown card `{0}`")]
+    UnknownComparisonCard(String),
+    #[error("Default card `{0}` has no comparison template")]
+    MissingDefaultComparison(String),
+    #[error("Theme uses unknown card `{0}`")]
+    UnknownThemeCard(String),
+    #[error("Theme uses unknown font `{0}`")]
+    UnknownThemeFont(String),
+    #[error("Template `{template}` failed to render a sample: {reason}")]
+    SampleRender { template: String, reason: String },
+    #[error("Reload failed to run: {0}")]
+    Pool(#[from] Error),
 }
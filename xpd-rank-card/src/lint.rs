@@ -0,0 +1,739 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use resvg::{
+    tiny_skia::{Color as SkiaColor, Pixmap},
+    usvg::{
+        fontdb::{Database, Family, Query},
+        ImageKind,
+    },
+};
+use tera::{
+    ast::{Expr, ExprVal, FunctionCall, Node},
+    Tera, Value,
+};
+
+use crate::{
+    compare_template_context, compare_template_name, customizations::Customizations,
+    layout::error_chain, leaderboard_row, leaderboard_template_context, leaderboard_template_name,
+    new_tera, sample_compare, sample_contexts, template_context, Config, Context, Error,
+    LeaderboardContext, NewSvgStateError, Resources, ANIMATED_AVATAR_HREF, MAX_LEADERBOARD_ROWS,
+};
+
+/// The highest level a user can reach, with the most XP an `i64` can hold
+const MAX_LEVEL: u64 = 1_768_802;
+const MAX_XP: u64 = 9_223_372_036_854_775_807;
+const MAX_LEVEL_NEXT_XP: u64 = 9_223_387_666_076_518_400;
+/// Discord display names can be this long
+const MAX_NAME_CHARS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem [`lint`] found in a resource pack
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The check which found the problem, like `font` or `template-variable`
+    pub check: &'static str,
+    /// File the problem is in, relative to the resource directory
+    pub file: Option<PathBuf>,
+    pub message: String,
+}
+
+/// A card or leaderboard drawn with edge-case data, for designers to look over
+#[derive(Debug, Clone)]
+pub struct SampleRender {
+    /// File name for the render, like `classic-long-name.png`
+    pub name: String,
+    pub png: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LintReport {
+    pub diagnostics: Vec<Diagnostic>,
+    pub renders: Vec<SampleRender>,
+}
+
+impl LintReport {
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        check: &'static str,
+        file: Option<&Path>,
+        message: String,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            check,
+            file: file.map(Path::to_path_buf),
+            message,
+        });
+    }
+
+    fn error(&mut self, check: &'static str, file: Option<&Path>, message: String) {
+        self.push(Severity::Error, check, file, message);
+    }
+
+    fn warning(&mut self, check: &'static str, file: Option<&Path>, message: String) {
+        self.push(Severity::Warning, check, file, message);
+    }
+}
+
+/// Check every font, toy and template of the resource pack in `data_dir`.
+///
+/// Each card and leaderboard is also drawn with edge-case data. Unlike [`Resources::new`],
+/// this keeps going after the first problem, so everything wrong is reported at once.
+#[must_use]
+pub fn lint(data_dir: &Path) -> LintReport {
+    let mut report = LintReport::default();
+    let manifest = Path::new("manifest.toml");
+    let config = std::fs::read_to_string(data_dir.join(manifest))
+        .map_err(NewSvgStateError::from)
+        .and_then(|config| Ok(toml::from_str::<Config>(&config)?));
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            report.error("manifest", Some(manifest), e.to_string());
+            return report;
+        }
+    };
+    check_fonts(&mut report, data_dir, &config);
+    check_toys(&mut report, data_dir, &config);
+    check_templates(&mut report, data_dir, &config);
+    match Resources::load(data_dir) {
+        Ok(resources) => render_samples(&mut report, &resources),
+        Err(e) => report.error(
+            "resources",
+            None,
+            format!("The resources don't load, so nothing was rendered: {e}"),
+        ),
+    }
+    report
+}
+
+fn check_fonts(report: &mut LintReport, data_dir: &Path, config: &Config) {
+    let mut fonts = Database::new();
+    for font in &config.fonts {
+        if let Err(e) = fonts.load_font_file(data_dir.join(&font.file)) {
+            report.error("font", Some(&font.file), format!("Can't load font: {e}"));
+        } else if !resolves(&fonts, &font.internal_name) {
+            report.error(
+                "font",
+                Some(&font.file),
+                format!("No font is named `{}`", font.internal_name),
+            );
+        }
+    }
+    for font in &config.fallback_fonts {
+        let path = data_dir.join(&font.file);
+        if font.optional && !path.exists() {
+            report.warning(
+                "fallback-font",
+                Some(&font.file),
+                format!("Optional fallback font `{}` is missing", font.family),
+            );
+        } else if let Err(e) = fonts.load_font_file(path) {
+            report.error(
+                "fallback-font",
+                Some(&font.file),
+                format!("Can't load font: {e}"),
+            );
+        } else if !resolves(&fonts, &font.family) {
+            report.error(
+                "fallback-font",
+                Some(&font.file),
+                format!("No font is named `{}`", font.family),
+            );
+        }
+    }
+    let names: HashSet<&str> = config
+        .fonts
+        .iter()
+        .map(|font| font.internal_name.as_str())
+        .collect();
+    for card in &config.cards {
+        if !names.contains(card.customizations.font.as_str()) {
+            report.error(
+                "font",
+                Some(&card.file),
+                format!(
+                    "Card `{}` uses unknown font `{}`",
+                    card.customizations.internal_name, card.customizations.font
+                ),
+            );
+        }
+    }
+    for theme in &config.themes {
+        if !names.contains(theme.font.as_str()) {
+            report.error(
+                "font",
+                Some(Path::new("manifest.toml")),
+                format!(
+                    "Theme `{}` uses unknown font `{}`",
+                    theme.internal_name, theme.font
+                ),
+            );
+        }
+    }
+}
+
+fn resolves(fonts: &Database, family: &str) -> bool {
+    let query = Query {
+        families: &[Family::Name(family)],
+        ..Query::default()
+    };
+    fonts.query(&query).is_some()
+}
+
+fn check_toys(report: &mut LintReport, data_dir: &Path, config: &Config) {
+    for toy in &config.toys {
+        let decoded = std::fs::read(data_dir.join(&toy.file))
+            .map_err(|e| e.to_string())
+            .and_then(|data| decode_png(&data).map_err(|e| e.to_string()));
+        if let Err(e) = decoded {
+            report.error(
+                "toy",
+                Some(&toy.file),
+                format!("Toy `{}` isn't a readable PNG: {e}", toy.internal_name),
+            );
+        }
+    }
+}
+
+fn decode_png(data: &[u8]) -> Result<(), png::DecodingError> {
+    let mut reader = png::Decoder::new(data).read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buffer)?;
+    Ok(())
+}
+
+fn check_templates(report: &mut LintReport, data_dir: &Path, config: &Config) {
+    for card in &config.cards {
+        let [mut full, _] = sample_contexts(card.customizations.clone(), Some("toy".to_string()));
+        full.background_image = Some(Arc::new(Vec::new()));
+        let known = template_context(&full).map(tera::Context::into_json);
+        check_template(
+            report,
+            data_dir,
+            &card.file,
+            &card.customizations.internal_name,
+            known,
+        );
+    }
+    for leaderboard in &config.leaderboards {
+        let Some(card) = config
+            .cards
+            .iter()
+            .find(|card| card.customizations.internal_name == leaderboard.card)
+        else {
+            report.error(
+                "leaderboard",
+                Some(&leaderboard.file),
+                format!("Leaderboard is for unknown card `{}`", leaderboard.card),
+            );
+            continue;
+        };
+        let [sample, _] = sample_contexts(card.customizations.clone(), None);
+        let context = LeaderboardContext {
+            rows: vec![leaderboard_row(&sample)],
+            customizations: sample.customizations,
+        };
+        let known = leaderboard_template_context(&context).map(tera::Context::into_json);
+        check_template(
+            report,
+            data_dir,
+            &leaderboard.file,
+            &leaderboard_template_name(&leaderboard.card),
+            known,
+        );
+    }
+    for comparison in &config.comparisons {
+        let Some(card) = config
+            .cards
+            .iter()
+            .find(|card| card.customizations.internal_name == comparison.card)
+        else {
+            report.error(
+                "comparison",
+                Some(&comparison.file),
+                format!("Comparison is for unknown card `{}`", comparison.card),
+            );
+            continue;
+        };
+        let [ahead, behind] = sample_contexts(card.customizations.clone(), None);
+        let context = sample_compare(&ahead, &behind);
+        let known = compare_template_context(&context).map(tera::Context::into_json);
+        check_template(
+            report,
+            data_dir,
+            &comparison.file,
+            &compare_template_name(&comparison.card),
+            known,
+        );
+    }
+}
+
+/// Parse a template, and check every variable it uses is one it is rendered with.
+/// `known` is the context the template sees.
+fn check_template(
+    report: &mut LintReport,
+    data_dir: &Path,
+    file: &Path,
+    name: &str,
+    known: Result<Value, Error>,
+) {
+    let source = match std::fs::read_to_string(data_dir.join(file)) {
+        Ok(source) => source,
+        Err(e) => {
+            report.error("template", Some(file), format!("Can't read template: {e}"));
+            return;
+        }
+    };
+    let mut tera = match new_tera() {
+        Ok(tera) => tera,
+        Err(e) => {
+            report.error("template", Some(file), error_chain(&e));
+            return;
+        }
+    };
+    if let Err(e) = tera.add_raw_template(name, &source) {
+        report.error("template", Some(file), error_chain(&e));
+        return;
+    }
+    let known = match known {
+        Ok(known) => known,
+        Err(e) => {
+            report.error("template", Some(file), error_chain(&e));
+            return;
+        }
+    };
+    let mut paths = HashSet::new();
+    variable_paths(&known, "", &mut paths);
+    for variable in unknown_variables(&tera, name, &paths) {
+        report.error(
+            "template-variable",
+            Some(file),
+            format!("`{variable}` isn't a variable this template is rendered with"),
+        );
+    }
+}
+
+/// Every path into `value` templates can use, like `customizations.username`.
+/// Items of arrays are named with `[]`, like `rows[].name`.
+fn variable_paths(value: &Value, prefix: &str, paths: &mut HashSet<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                variable_paths(value, &path, paths);
+                paths.insert(path);
+            }
+        }
+        Value::Array(items) => {
+            let path = format!("{prefix}[]");
+            for item in items {
+                variable_paths(item, &path, paths);
+            }
+            paths.insert(path);
+        }
+        _ => {}
+    }
+}
+
+fn unknown_variables(tera: &Tera, name: &str, paths: &HashSet<String>) -> Vec<String> {
+    let Ok(template) = tera.get_template(name) else {
+        return Vec::new();
+    };
+    let mut check = VariableCheck {
+        known: paths,
+        globals: HashSet::new(),
+        unknown: Vec::new(),
+    };
+    check.nodes(&template.ast, &mut HashMap::new());
+    check.unknown
+}
+
+/// Variables a template defines itself, mapped to the context path they stand for.
+/// Variables from `set` stand for nothing in the context, and aren't checked.
+type Scope = HashMap<String, Option<String>>;
+
+/// Walks a template, collecting variables which aren't in the context it is rendered with
+struct VariableCheck<'a> {
+    known: &'a HashSet<String>,
+    /// Variables from `set_global`, which are visible outside the loop they're set in
+    globals: HashSet<String>,
+    unknown: Vec<String>,
+}
+
+impl VariableCheck<'_> {
+    fn nodes(&mut self, nodes: &[Node], scope: &mut Scope) {
+        for node in nodes {
+            match node {
+                Node::VariableBlock(_, expr) => self.expr(expr, scope),
+                Node::Set(_, set) => {
+                    self.expr(&set.value, scope);
+                    if set.global {
+                        self.globals.insert(set.key.clone());
+                    } else {
+                        scope.insert(set.key.clone(), None);
+                    }
+                }
+                Node::FilterSection(_, section, _) => {
+                    self.call(&section.filter, scope);
+                    self.nodes(&section.body, scope);
+                }
+                Node::Block(_, block, _) => self.nodes(&block.body, scope),
+                Node::Forloop(_, forloop, _) => {
+                    self.expr(&forloop.container, scope);
+                    let item = match &forloop.container.val {
+                        ExprVal::Ident(ident) => self.resolve(ident, scope).map(|p| p + "[]"),
+                        _ => None,
+                    };
+                    let mut inner = scope.clone();
+                    inner.insert(forloop.value.clone(), item);
+                    if let Some(key) = &forloop.key {
+                        inner.insert(key.clone(), None);
+                    }
+                    inner.insert("loop".to_string(), None);
+                    self.nodes(&forloop.body, &mut inner);
+                    if let Some(body) = &forloop.empty_body {
+                        self.nodes(body, &mut scope.clone());
+                    }
+                }
+                Node::If(condition, _) => {
+                    for (_, expr, body) in &condition.conditions {
+                        self.expr(expr, scope);
+                        self.nodes(body, scope);
+                    }
+                    if let Some((_, body)) = &condition.otherwise {
+                        self.nodes(body, scope);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, scope: &Scope) {
+        self.value(&expr.val, scope);
+        for filter in &expr.filters {
+            self.call(filter, scope);
+        }
+    }
+
+    fn call(&mut self, call: &FunctionCall, scope: &Scope) {
+        for arg in call.args.values() {
+            self.expr(arg, scope);
+        }
+    }
+
+    fn value(&mut self, value: &ExprVal, scope: &Scope) {
+        match value {
+            ExprVal::Ident(ident) => self.ident(ident, scope),
+            ExprVal::Math(math) => {
+                self.expr(&math.lhs, scope);
+                self.expr(&math.rhs, scope);
+            }
+            ExprVal::Logic(logic) => {
+                self.expr(&logic.lhs, scope);
+                self.expr(&logic.rhs, scope);
+            }
+            ExprVal::In(within) => {
+                self.expr(&within.lhs, scope);
+                self.expr(&within.rhs, scope);
+            }
+            ExprVal::Test(test) => {
+                self.ident(&test.ident, scope);
+                for arg in &test.args {
+                    self.expr(arg, scope);
+                }
+            }
+            ExprVal::FunctionCall(call) => self.call(call, scope),
+            ExprVal::MacroCall(call) => {
+                for arg in call.args.values() {
+                    self.expr(arg, scope);
+                }
+            }
+            ExprVal::Array(items) => {
+                for item in items {
+                    self.expr(item, scope);
+                }
+            }
+            ExprVal::StringConcat(concat) => {
+                for value in &concat.values {
+                    self.value(value, scope);
+                }
+            }
+            ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+        }
+    }
+
+    fn ident(&mut self, ident: &str, scope: &Scope) {
+        if let Some(path) = self.resolve(ident, scope) {
+            if !self.known.contains(&path) && !self.unknown.contains(&path) {
+                self.unknown.push(path);
+            }
+        }
+    }
+
+    /// The context path `ident` refers to, or `None` for variables the template sets itself.
+    /// Anything after an index, like `rows[0].name`, isn't checked.
+    fn resolve(&self, ident: &str, scope: &Scope) -> Option<String> {
+        let ident = ident.split('[').next().unwrap_or(ident);
+        let (head, rest) = ident
+            .split_once('.')
+            .map_or((ident, None), |(head, rest)| (head, Some(rest)));
+        if head.starts_with("__tera") || self.globals.contains(head) {
+            return None;
+        }
+        let base = match scope.get(head) {
+            Some(None) => return None,
+            Some(Some(path)) => path.clone(),
+            None => head.to_string(),
+        };
+        Some(rest.map_or_else(|| base.clone(), |rest| format!("{base}.{rest}")))
+    }
+}
+
+/// A context for each edge case, named for the file it is rendered to
+fn edge_cases(resources: &Resources, card: &Context) -> Vec<(String, Context)> {
+    let mut cases = vec![
+        (
+            "zero-xp".to_string(),
+            Context {
+                level: 0,
+                percentage: 0,
+                current: 0,
+                needed: 100,
+                ..card.clone()
+            },
+        ),
+        (
+            "max-level".to_string(),
+            Context {
+                level: MAX_LEVEL,
+                rank: 1,
+                percentage: 99,
+                current: MAX_XP,
+                needed: MAX_LEVEL_NEXT_XP,
+                ..card.clone()
+            },
+        ),
+        (
+            "long-name".to_string(),
+            Context {
+                name: "W".repeat(MAX_NAME_CHARS),
+                ..card.clone()
+            },
+        ),
+        (
+            "no-rewards".to_string(),
+            Context {
+                reward: None,
+                next_reward: None,
+                ..card.clone()
+            },
+        ),
+    ];
+    let mut toys: Vec<&String> = resources.images.keys().collect();
+    toys.sort();
+    for toy in toys {
+        let mut context = card.clone();
+        context.customizations.toy = Some(toy.clone());
+        let name = toy.trim_end_matches(".png");
+        cases.push((format!("toy-{name}"), context));
+    }
+    cases
+}
+
+fn render_samples(report: &mut LintReport, resources: &Resources) {
+    let avatar = match placeholder_avatar() {
+        Ok(avatar) => avatar,
+        Err(e) => {
+            report.error("render", None, error_chain(&e));
+            return;
+        }
+    };
+    for card in &resources.config.cards {
+        let internal_name = &card.customizations.internal_name;
+        let [full, _] = sample_contexts(card.customizations.clone(), None);
+        for (case, context) in edge_cases(resources, &full) {
+            let rendered = resources
+                .render_card_svg(internal_name, &context)
+                .and_then(|svg| {
+                    rasterize_sample(resources, &svg, &context.customizations, &avatar)
+                });
+            let name = format!("{}-{case}.png", internal_name.trim_end_matches(".svg"));
+            push_render(report, &card.file, name, rendered);
+        }
+    }
+    for leaderboard in &resources.config.leaderboards {
+        let Some(customizations) = resources.defaults.get(&leaderboard.card) else {
+            continue;
+        };
+        let [full, _] = sample_contexts(customizations.clone(), None);
+        // Every other row has an avatar, so both branches of the template are drawn
+        let rows = edge_cases(resources, &full)
+            .iter()
+            .cycle()
+            .take(MAX_LEADERBOARD_ROWS)
+            .enumerate()
+            .map(|(index, (_, context))| {
+                let mut row = leaderboard_row(context);
+                if index % 2 == 0 {
+                    row.avatar = ANIMATED_AVATAR_HREF.to_string();
+                }
+                row
+            })
+            .collect();
+        let context = LeaderboardContext {
+            rows,
+            customizations: customizations.clone(),
+        };
+        let rendered = resources
+            .render_leaderboard_svg(&context)
+            .and_then(|svg| rasterize_sample(resources, &svg, &context.customizations, &avatar));
+        let name = format!(
+            "leaderboard-{}.png",
+            leaderboard.card.trim_end_matches(".svg")
+        );
+        push_render(report, &leaderboard.file, name, rendered);
+    }
+    for comparison in &resources.config.comparisons {
+        let Some(customizations) = resources.defaults.get(&comparison.card) else {
+            continue;
+        };
+        let [full, _] = sample_contexts(customizations.clone(), None);
+        let cases = edge_cases(resources, &full);
+        let find = |name: &str| {
+            cases
+                .iter()
+                .find(|(case, _)| case == name)
+                .map_or(&full, |(_, context)| context)
+        };
+        // The longest names and largest numbers on one side, and a tie
+        let mut uneven = sample_compare(find("max-level"), find("long-name"));
+        uneven.left.avatar = ANIMATED_AVATAR_HREF.to_string();
+        uneven.messages_to_catch_up = Some(MAX_XP / 15);
+        let mut tied = sample_compare(&full, &full);
+        tied.right = tied.left.clone();
+        tied.xp_gap = 0;
+        tied.level_gap = 0;
+        tied.rank_gap = 0;
+        for (case, context) in [("uneven", uneven), ("tied", tied)] {
+            let rendered = resources.render_compare_svg(&context).and_then(|svg| {
+                rasterize_sample(resources, &svg, &context.customizations, &avatar)
+            });
+            let name = format!(
+                "compare-{}-{case}.png",
+                comparison.card.trim_end_matches(".svg")
+            );
+            push_render(report, &comparison.file, name, rendered);
+        }
+    }
+}
+
+/// Draw a sample as a PNG, with the placeholder avatar behind every avatar href
+fn rasterize_sample(
+    resources: &Resources,
+    svg: &str,
+    customizations: &Customizations,
+    avatar: &Arc<Vec<u8>>,
+) -> Result<Vec<u8>, Error> {
+    let images = vec![(ANIMATED_AVATAR_HREF, ImageKind::PNG(avatar.clone()))];
+    let pixmap = resources.rasterize_pixmap(svg, &customizations.font, images)?;
+    Ok(pixmap.encode_png()?)
+}
+
+fn push_render(
+    report: &mut LintReport,
+    file: &Path,
+    name: String,
+    rendered: Result<Vec<u8>, Error>,
+) {
+    match rendered {
+        Ok(png) => report.renders.push(SampleRender { name, png }),
+        Err(e) => report.error(
+            "render",
+            Some(file),
+            format!("Sample `{name}` failed to render: {}", error_chain(&e)),
+        ),
+    }
+}
+
+/// A plain grey square, standing in for a user's avatar
+fn placeholder_avatar() -> Result<Arc<Vec<u8>>, Error> {
+    let mut pixmap = Pixmap::new(256, 256).ok_or(Error::PixmapCreation)?;
+    pixmap.fill(SkiaColor::from_rgba8(128, 128, 128, 255));
+    Ok(Arc::new(pixmap.encode_png()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::Path};
+
+    use serde_json::json;
+
+    use super::{check_templates, unknown_variables, variable_paths, LintReport};
+    use crate::{new_tera, Config};
+
+    fn unknown(template: &str) -> Vec<String> {
+        let known = json!({
+            "name": "",
+            "customizations": { "username": "" },
+            "rows": [{ "name": "" }],
+        });
+        let mut paths = HashSet::new();
+        variable_paths(&known, "", &mut paths);
+        let mut tera = new_tera().unwrap();
+        tera.add_raw_template("test.svg", template).unwrap();
+        unknown_variables(&tera, "test.svg", &paths)
+    }
+
+    #[test]
+    fn bundled_templates_pass() {
+        let data_dir = Path::new("../xpd-card-resources");
+        let manifest = std::fs::read_to_string(data_dir.join("manifest.toml")).unwrap();
+        let config: Config = toml::from_str(&manifest).unwrap();
+        let mut report = LintReport::default();
+        check_templates(&mut report, data_dir, &config);
+        assert!(report.diagnostics.is_empty(), "{:#?}", report.diagnostics);
+    }
+
+    #[test]
+    fn finds_unknown_variables() {
+        assert!(unknown("{{ name }}{{ customizations.username }}").is_empty());
+        assert!(
+            unknown("{% for row in rows %}{{ row.name }}{{ loop.index }}{% endfor %}").is_empty()
+        );
+        assert!(unknown("{% set width = 10 %}{{ width * 2 }}").is_empty());
+        assert_eq!(unknown("{% if nmae %}{% endif %}"), ["nmae"]);
+        assert_eq!(
+            unknown("{{ customizations.usernme | bidi_isolate }}"),
+            ["customizations.usernme"]
+        );
+        assert_eq!(
+            unknown("{% for row in rows %}{{ row.rank }}{% endfor %}"),
+            ["rows[].rank"]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    any::Any,
+    fmt::Display,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rayon::ThreadPoolBuilder;
+use tokio::sync::Semaphore;
+use tracing::{error, warn};
+
+use crate::Error;
+
+/// How many threads render, and how much work may wait for them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolOptions {
+    /// Render threads. Zero picks one per CPU.
+    pub threads: usize,
+    /// Jobs which may be queued or running at once. Any more are refused with
+    /// [`Error::RendererBusy`].
+    pub max_pending: usize,
+    /// How long callers wait for a card before giving up with [`Error::RenderTimeout`]
+    pub deadline: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            threads: 0,
+            max_pending: 64,
+            deadline: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A rayon pool which refuses work when it's backed up, stops waiting for slow renders,
+/// and turns panics into errors instead of aborting the process.
+pub struct RenderPool {
+    threads: rayon::ThreadPool,
+    slots: Arc<Semaphore>,
+    max_pending: usize,
+    deadline: Duration,
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    completed: AtomicU64,
+    rejected: AtomicU64,
+    abandoned: AtomicU64,
+    timed_out: AtomicU64,
+    panicked: AtomicU64,
+}
+
+impl RenderPool {
+    pub fn new(options: PoolOptions) -> Result<Self, rayon::ThreadPoolBuildError> {
+        let threads = ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .thread_name(|i| format!("svg-renderer-{i}"))
+            .build()?;
+        Ok(Self {
+            threads,
+            slots: Arc::new(Semaphore::new(options.max_pending)),
+            max_pending: options.max_pending,
+            deadline: options.deadline,
+            counters: Arc::new(Counters::default()),
+        })
+    }
+
+    /// Run `job` on the pool, waiting at most the pool's deadline for it.
+    /// A render which misses its deadline keeps its thread until it finishes, but
+    /// still holds its queue slot, so a stuck pool fills up and refuses new work
+    /// rather than growing without bound.
+    pub async fn run<T, F>(&self, job: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.run_with_deadline(Some(self.deadline), job).await
+    }
+
+    /// [`Self::run`], with a different deadline. `None` waits for as long as `job` takes.
+    pub async fn run_with_deadline<T, F>(
+        &self,
+        deadline: Option<Duration>,
+        job: F,
+    ) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let Ok(permit) = self.slots.clone().try_acquire_owned() else {
+            self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+            warn!(max_pending = self.max_pending, "Render pool is full");
+            return Err(Error::RendererBusy);
+        };
+        let counters = self.counters.clone();
+        let (send, recv) = tokio::sync::oneshot::channel();
+        self.threads.spawn(move || {
+            let _permit = permit;
+            // Nobody is waiting for this any more, so don't spend a thread on it
+            if send.is_closed() {
+                counters.abandoned.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            let result = std::panic::catch_unwind(AssertUnwindSafe(job)).map_err(|payload| {
+                counters.panicked.fetch_add(1, Ordering::Relaxed);
+                let message = panic_message(payload.as_ref());
+                error!(message, "Render panicked");
+                Error::RenderPanicked(message)
+            });
+            counters.completed.fetch_add(1, Ordering::Relaxed);
+            send.send(result).ok();
+        });
+        let result = match deadline {
+            Some(deadline) => tokio::time::timeout(deadline, recv).await.map_err(|_| {
+                self.counters.timed_out.fetch_add(1, Ordering::Relaxed);
+                warn!(?deadline, "Render missed its deadline");
+                Error::RenderTimeout(deadline)
+            })?,
+            None => recv.await,
+        };
+        result?
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            threads: self.threads.current_num_threads(),
+            pending: self.max_pending - self.slots.available_permits(),
+            max_pending: self.max_pending,
+            completed: self.counters.completed.load(Ordering::Relaxed),
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+            abandoned: self.counters.abandoned.load(Ordering::Relaxed),
+            timed_out: self.counters.timed_out.load(Ordering::Relaxed),
+            panicked: self.counters.panicked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub threads: usize,
+    /// Jobs queued or running right now
+    pub pending: usize,
+    pub max_pending: usize,
+    pub completed: u64,
+    /// Jobs refused because the queue was full
+    pub rejected: u64,
+    /// Jobs skipped because their caller stopped waiting before they started
+    pub abandoned: u64,
+    pub timed_out: u64,
+    pub panicked: u64,
+}
+
+impl Display for PoolStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} threads, {}/{} pending, {} completed, {} rejected, {} abandoned, {} timed out, {} panicked",
+            self.threads,
+            self.pending,
+            self.max_pending,
+            self.completed,
+            self.rejected,
+            self.abandoned,
+            self.timed_out,
+            self.panicked
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::{PoolOptions, RenderPool};
+    use crate::Error;
+
+    fn pool(max_pending: usize, deadline: Duration) -> RenderPool {
+        RenderPool::new(PoolOptions {
+            threads: 1,
+            max_pending,
+            deadline,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn panics_become_errors() {
+        let pool = pool(4, Duration::from_secs(5));
+        let result: Result<(), Error> = pool.run(|| panic!("bad font")).await;
+        assert!(matches!(result, Err(Error::RenderPanicked(message)) if message == "bad font"));
+        // The pool still works afterwards
+        assert_eq!(pool.run(|| 1).await.unwrap(), 1);
+        assert_eq!(pool.stats().panicked, 1);
+    }
+
+    #[tokio::test]
+    async fn slow_renders_time_out_and_full_queues_refuse_work() {
+        let pool = pool(1, Duration::from_millis(50));
+        let (release, wait) = mpsc::channel::<()>();
+        let result = pool.run(move || wait.recv().ok()).await;
+        assert!(matches!(result, Err(Error::RenderTimeout(_))));
+        // The stuck render still holds the only slot
+        assert!(matches!(pool.run(|| ()).await, Err(Error::RendererBusy)));
+        release.send(()).unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.timed_out, stats.rejected), (1, 1));
+    }
+}
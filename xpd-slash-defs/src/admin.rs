@@ -31,6 +31,8 @@ pub enum AdminCommand {
     Stats(AdminCommandStats),
     #[command(name = "inspectcooldown")]
     InspectCooldown(AdminCommandInspectCooldown),
+    #[command(name = "reloadcards")]
+    ReloadCards(AdminCommandReloadCards),
 }
 
 impl AdminCommand {
@@ -68,6 +70,13 @@ pub struct AdminCommandGuildStats {
 #[command(name = "stats", desc = "Get some basic stats about the bot in general")]
 pub struct AdminCommandStats;
 
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "reloadcards",
+    desc = "Reload card layouts, fonts and toys from disk"
+)]
+pub struct AdminCommandReloadCards;
+
 #[derive(CommandModel, CreateCommand)]
 #[command(name = "resetuser", desc = "Reset the stats & custom card of a user")]
 pub struct AdminCommandResetUser {
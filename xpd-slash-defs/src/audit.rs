@@ -1,21 +1,36 @@
-use twilight_interactions::command::{CommandModel, CreateCommand};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use twilight_interactions::{
+    command::{
+        internal::{CommandOptionData, CreateOptionData},
+        CommandModel, CommandOption, CreateCommand, CreateOption,
+    },
+    error::ParseOptionErrorType,
+};
 use twilight_model::{
+    application::{
+        command::{CommandOption as CommandOptionDefinition, CommandOptionType},
+        interaction::{application_command::CommandOptionValue, InteractionDataResolved},
+    },
     guild::Permissions,
     id::{marker::UserMarker, Id},
 };
+use xpd_common::{AuditLogAction, GuildAuditKind, DISCORD_EPOCH_SECS};
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "audit",
-    desc = "Download audit logs for your server",
+    desc = "View and undo moderator actions in your server",
     dm_permission = false,
     default_permissions = "Self::default_permissions"
 )]
-pub struct AuditLogCommand {
-    #[command(desc = "Fetch audit logs triggered by this moderator")]
-    pub moderator: Option<Id<UserMarker>>,
-    #[command(desc = "Fetch audit logs acting on this user")]
-    pub user: Option<Id<UserMarker>>,
+pub enum AuditLogCommand {
+    #[command(name = "list")]
+    List(AuditLogCommandList),
+    #[command(name = "revert")]
+    Revert(AuditLogCommandRevert),
+    #[command(name = "settings")]
+    Settings(AuditLogCommandSettings),
 }
 
 impl AuditLogCommand {
@@ -24,3 +39,289 @@ impl AuditLogCommand {
         Permissions::MODERATE_MEMBERS
     }
 }
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "list", desc = "View or download audit logs for your server")]
+pub struct AuditLogCommandList {
+    #[command(desc = "Fetch audit logs triggered by this moderator")]
+    pub moderator: Option<Id<UserMarker>>,
+    #[command(desc = "Fetch audit logs acting on this user")]
+    pub user: Option<Id<UserMarker>>,
+    #[command(desc = "Only fetch actions after this time (unix timestamp, or relative like 2h)")]
+    pub since: Option<TimestampOption>,
+    #[command(desc = "Only fetch actions before this time (unix timestamp, or relative like 2h)")]
+    pub until: Option<TimestampOption>,
+    #[command(desc = "Only fetch this kind of action")]
+    pub action: Option<AuditLogActionOption>,
+    #[command(desc = "How to show the audit log (defaults to a CSV file)")]
+    pub format: Option<AuditLogFormat>,
+}
+
+#[derive(CommandOption, CreateOption, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditLogActionOption {
+    #[option(name = "Add", value = "add")]
+    Add,
+    #[option(name = "Remove", value = "remove")]
+    Remove,
+    #[option(name = "Set", value = "set")]
+    Set,
+    #[option(name = "Reset", value = "reset")]
+    Reset,
+    #[option(name = "Import", value = "import")]
+    Import,
+    #[option(name = "Revert", value = "revert")]
+    Revert,
+}
+
+impl From<AuditLogActionOption> for AuditLogAction {
+    fn from(value: AuditLogActionOption) -> Self {
+        match value {
+            AuditLogActionOption::Add => Self::Add,
+            AuditLogActionOption::Remove => Self::Remove,
+            AuditLogActionOption::Set => Self::Set,
+            AuditLogActionOption::Reset => Self::Reset,
+            AuditLogActionOption::Import => Self::Import,
+            AuditLogActionOption::Revert => Self::Revert,
+        }
+    }
+}
+
+#[derive(CommandOption, CreateOption, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AuditLogFormat {
+    #[default]
+    #[option(name = "CSV file", value = "csv")]
+    Csv,
+    #[option(name = "JSON file", value = "json")]
+    Json,
+    #[option(name = "Message with pages", value = "embed")]
+    Embed,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "settings",
+    desc = "View or download changes to server settings, rewards, and bulk XP actions"
+)]
+pub struct AuditLogCommandSettings {
+    #[command(desc = "Fetch changes made by this member")]
+    pub actor: Option<Id<UserMarker>>,
+    #[command(desc = "Only fetch changes after this time (unix timestamp, or relative like 2h)")]
+    pub since: Option<TimestampOption>,
+    #[command(desc = "Only fetch changes before this time (unix timestamp, or relative like 2h)")]
+    pub until: Option<TimestampOption>,
+    #[command(desc = "Only fetch this kind of change")]
+    pub kind: Option<GuildAuditKindOption>,
+    #[command(desc = "How to show the audit log (defaults to a CSV file)")]
+    pub format: Option<AuditLogFormat>,
+}
+
+#[derive(CommandOption, CreateOption, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuildAuditKindOption {
+    #[option(name = "Config update", value = "config_update")]
+    ConfigUpdate,
+    #[option(name = "Config reset", value = "config_reset")]
+    ConfigReset,
+    #[option(name = "Reward added", value = "reward_add")]
+    RewardAdd,
+    #[option(name = "Reward removed", value = "reward_remove")]
+    RewardRemove,
+    #[option(name = "Guild card update", value = "guild_card_update")]
+    GuildCardUpdate,
+    #[option(name = "Guild card reset", value = "guild_card_reset")]
+    GuildCardReset,
+    #[option(name = "Import", value = "import")]
+    Import,
+    #[option(name = "Server XP reset", value = "guild_reset")]
+    GuildReset,
+    #[option(name = "Backup restored", value = "restore")]
+    Restore,
+}
+
+impl From<GuildAuditKindOption> for GuildAuditKind {
+    fn from(value: GuildAuditKindOption) -> Self {
+        match value {
+            GuildAuditKindOption::ConfigUpdate => Self::ConfigUpdate,
+            GuildAuditKindOption::ConfigReset => Self::ConfigReset,
+            GuildAuditKindOption::RewardAdd => Self::RewardAdd,
+            GuildAuditKindOption::RewardRemove => Self::RewardRemove,
+            GuildAuditKindOption::GuildCardUpdate => Self::GuildCardUpdate,
+            GuildAuditKindOption::GuildCardReset => Self::GuildCardReset,
+            GuildAuditKindOption::Import => Self::Import,
+            GuildAuditKindOption::GuildReset => Self::GuildReset,
+            GuildAuditKindOption::Restore => Self::Restore,
+        }
+    }
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "revert",
+    desc = "Undo an audit log entry, or everything a moderator did in a time range"
+)]
+pub struct AuditLogCommandRevert {
+    #[command(desc = "ID of the audit log entry to revert", min_value = 1)]
+    pub entry: Option<i64>,
+    #[command(desc = "Revert every action taken by this moderator")]
+    pub moderator: Option<Id<UserMarker>>,
+    #[command(desc = "Only revert actions after this time (unix timestamp, or relative like 2h)")]
+    pub since: Option<TimestampOption>,
+    #[command(desc = "Only revert actions before this time (unix timestamp, or relative like 2h)")]
+    pub until: Option<TimestampOption>,
+}
+
+/// A point in time, given either as a unix timestamp (optionally in discord `<t:...>` markup),
+/// or as a duration before now, like `30m`, `12h`, `7d` or `2w`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimestampOption(i64);
+
+impl TimestampOption {
+    /// The last second of the year 9999
+    const MAX_UNIX: i64 = 253_402_300_799;
+
+    /// Seconds since the unix epoch
+    #[must_use]
+    pub const fn unix(self) -> i64 {
+        self.0
+    }
+
+    /// Seconds since the discord epoch, which is how audit log timestamps are stored.
+    #[must_use]
+    pub const fn audit_timestamp(self) -> i64 {
+        self.0 - DISCORD_EPOCH_SECS
+    }
+
+    fn parse(input: &str, now: i64) -> Result<Self, String> {
+        let input = input.trim();
+        let markup = input
+            .strip_prefix("<t:")
+            .and_then(|v| v.strip_suffix('>'))
+            .map(|v| v.split_once(':').map_or(v, |(ts, _style)| ts));
+        if let Some(ts) = markup {
+            return ts
+                .parse()
+                .map_err(|_| format!("`{input}` is not a valid discord timestamp"))
+                .and_then(Self::checked);
+        }
+        if let Ok(unix) = input.parse() {
+            return Self::checked(unix);
+        }
+
+        let (count, unit) = input.split_at(input.trim_end_matches(char::is_alphabetic).len());
+        let count: i64 = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("`{input}` is not a timestamp or a duration like `2h`"))?;
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            "w" => 60 * 60 * 24 * 7,
+            _ => return Err(format!("Unknown time unit `{unit}`, use s, m, h, d or w")),
+        };
+        count
+            .checked_mul(unit_secs)
+            .ok_or_else(|| format!("`{input}` is too far in the past"))
+            .and_then(|ago| Self::checked(now.saturating_sub(ago)))
+    }
+
+    fn checked(unix: i64) -> Result<Self, String> {
+        if (0..=Self::MAX_UNIX).contains(&unix) {
+            Ok(Self(unix))
+        } else {
+            Err(format!("<t:{unix}> is not a supported time"))
+        }
+    }
+}
+
+impl CommandOption for TimestampOption {
+    fn from_option(
+        value: CommandOptionValue,
+        _data: CommandOptionData,
+        _resolved: Option<&InteractionDataResolved>,
+    ) -> Result<Self, ParseOptionErrorType> {
+        if let CommandOptionValue::String(string) = value {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |v| v.as_secs().try_into().unwrap_or(i64::MAX));
+            Self::parse(&string, now).map_err(ParseOptionErrorType::InvalidChoice)
+        } else {
+            Err(ParseOptionErrorType::InvalidType(value.kind()))
+        }
+    }
+}
+
+impl CreateOption for TimestampOption {
+    fn create_option(data: CreateOptionData) -> CommandOptionDefinition {
+        CommandOptionDefinition {
+            autocomplete: Some(data.autocomplete),
+            channel_types: None,
+            choices: None,
+            description: data.description,
+            description_localizations: data.description_localizations,
+            kind: CommandOptionType::String,
+            max_length: Some(32),
+            max_value: None,
+            min_length: Some(1),
+            min_value: None,
+            name: data.name,
+            name_localizations: data.name_localizations,
+            options: None,
+            required: data.required,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimestampOption;
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn unix() {
+        assert_eq!(
+            TimestampOption::parse("1600000000", NOW).unwrap().unix(),
+            1_600_000_000
+        );
+    }
+
+    #[test]
+    fn discord_markup() {
+        assert_eq!(
+            TimestampOption::parse("<t:1600000000>", NOW)
+                .unwrap()
+                .unix(),
+            1_600_000_000
+        );
+        assert_eq!(
+            TimestampOption::parse("<t:1600000000:R>", NOW)
+                .unwrap()
+                .unix(),
+            1_600_000_000
+        );
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(TimestampOption::parse("90s", NOW).unwrap().unix(), NOW - 90);
+        assert_eq!(
+            TimestampOption::parse("2h", NOW).unwrap().unix(),
+            NOW - 7200
+        );
+        assert_eq!(
+            TimestampOption::parse("1w", NOW).unwrap().unix(),
+            NOW - 604_800
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(TimestampOption::parse("yesterday", NOW).is_err());
+        assert!(TimestampOption::parse("3y", NOW).is_err());
+        assert!(TimestampOption::parse("", NOW).is_err());
+        assert!(TimestampOption::parse("-5", NOW).is_err());
+        assert!(TimestampOption::parse("9999999999999", NOW).is_err());
+        assert!(TimestampOption::parse("9999w", NOW).is_err());
+    }
+}
@@ -2,7 +2,7 @@
 use twilight_interactions::command::{
     AutocompleteValue, CommandModel, CommandOption, CreateCommand, CreateOption, ResolvedUser,
 };
-use twilight_model::guild::Permissions;
+use twilight_model::{channel::Attachment, guild::Permissions};
 use xpd_rank_card::customizations::Color;
 
 #[derive(CommandModel, CreateCommand)]
@@ -19,6 +19,12 @@ pub enum CardCommand {
     Fetch(CardCommandFetch),
     #[command(name = "edit")]
     Edit(CardCommandEdit),
+    #[command(name = "theme")]
+    Theme(CardCommandTheme),
+    #[command(name = "save-theme")]
+    SaveTheme(CardCommandSaveTheme),
+    #[command(name = "delete-theme")]
+    DeleteTheme(CardCommandDeleteTheme),
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -36,6 +42,14 @@ pub enum GuildCardCommand {
     Fetch(GuildCardCommandFetch),
     #[command(name = "edit")]
     Edit(CardCommandEdit),
+    #[command(name = "theme")]
+    Theme(CardCommandTheme),
+    #[command(name = "save-theme")]
+    SaveTheme(CardCommandSaveTheme),
+    #[command(name = "delete-theme")]
+    DeleteTheme(CardCommandDeleteTheme),
+    #[command(name = "layout")]
+    Layout(GuildCardCommandLayout),
 }
 
 impl GuildCardCommand {
@@ -66,10 +80,49 @@ pub struct CardCommandFetch {
 )]
 pub struct GuildCardCommandFetch;
 
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "layout",
+    desc = "Draw your server's cards with your own layout."
+)]
+#[allow(clippy::large_enum_variant)]
+pub enum GuildCardCommandLayout {
+    #[command(name = "upload")]
+    Upload(GuildCardCommandLayoutUpload),
+    #[command(name = "download")]
+    Download(GuildCardCommandLayoutDownload),
+    #[command(name = "remove")]
+    Remove(GuildCardCommandLayoutRemove),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "upload",
+    desc = "Upload a Tera/SVG card layout, and use it for the server card."
+)]
+pub struct GuildCardCommandLayoutUpload {
+    #[command(desc = "The layout template, as an .svg file")]
+    pub layout: Attachment,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "download",
+    desc = "Download your server's uploaded card layout."
+)]
+pub struct GuildCardCommandLayoutDownload;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "remove",
+    desc = "Delete your server's uploaded card layout, and go back to the default layout."
+)]
+pub struct GuildCardCommandLayoutRemove;
+
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "edit",
-    desc = "Edit card colors by specifying hex codes for values you would like to change."
+    desc = "Edit card colors with hex codes, color names, or gradients like #FF8000,#0080FF,90deg"
 )]
 pub struct CardCommandEdit {
     #[command(desc = "What color to use for the background")]
@@ -96,6 +149,48 @@ pub struct CardCommandEdit {
     pub toy_image: Option<String>,
     #[command(desc = "What layout to use for the card", autocomplete = true)]
     pub card_layout: Option<String>,
+    #[command(desc = "Animate the card if the avatar is animated (GIF)")]
+    pub animated_avatar: Option<bool>,
+    #[command(desc = "An image (PNG, JPEG, GIF or WebP) to use as the card background")]
+    pub background_image: Option<Attachment>,
+    #[command(desc = "Remove the card's background image")]
+    pub remove_background_image: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "theme",
+    desc = "Apply a preset theme, a saved theme, or a theme someone shared with you."
+)]
+pub struct CardCommandTheme {
+    #[command(
+        desc = "Theme name or share code",
+        autocomplete = true,
+        max_length = 32
+    )]
+    pub theme: String,
+    #[command(desc = "Show what the theme looks like without applying it")]
+    pub preview: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "save-theme",
+    desc = "Save the card's current colors, font and layout as a theme you can share."
+)]
+pub struct CardCommandSaveTheme {
+    #[command(
+        desc = "Name of the theme. Saving with an existing name replaces it.",
+        max_length = 32
+    )]
+    pub name: String,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "delete-theme", desc = "Delete a saved theme.")]
+pub struct CardCommandDeleteTheme {
+    #[command(desc = "Name of the theme", autocomplete = true, max_length = 32)]
+    pub name: String,
 }
 
 #[derive(CommandModel, Debug)]
@@ -107,6 +202,12 @@ pub enum CardCommandAutocomplete {
     Fetch(NoAutocomplete),
     #[command(name = "reset")]
     Reset(NoAutocomplete),
+    #[command(name = "theme")]
+    Theme(CardCommandThemeAutocomplete),
+    #[command(name = "save-theme")]
+    SaveTheme(NoAutocomplete),
+    #[command(name = "delete-theme")]
+    DeleteTheme(CardCommandDeleteThemeAutocomplete),
 }
 
 #[derive(CommandModel, Debug)]
@@ -117,10 +218,25 @@ pub struct CardCommandEditAutocomplete {
     pub card_layout: AutocompleteValue<String>,
 }
 
+#[derive(CommandModel, Debug)]
+#[command(autocomplete = true)]
+pub struct CardCommandThemeAutocomplete {
+    pub theme: AutocompleteValue<String>,
+}
+
+#[derive(CommandModel, Debug)]
+#[command(autocomplete = true)]
+pub struct CardCommandDeleteThemeAutocomplete {
+    pub name: AutocompleteValue<String>,
+}
+
 #[derive(CommandModel, Debug)]
 #[command(autocomplete = true)]
 pub struct NoAutocomplete;
 
+/// Longest color setting that can be stored, like `#FF800080,#0080FFFF,315deg` with some spaces
+pub const MAX_COLOR_LENGTH: u16 = 32;
+
 pub struct ColorOption(Color);
 
 impl ColorOption {
@@ -144,7 +260,7 @@ impl CommandOption for ColorOption {
         _resolved: Option<&twilight_model::application::interaction::InteractionDataResolved>,
     ) -> Result<Self, twilight_interactions::error::ParseOptionErrorType> {
         if let twilight_model::application::interaction::application_command::CommandOptionValue::String(string) = value {
-            Ok(Self(Color::from_hex(&string).map_err(|e| twilight_interactions::error::ParseOptionErrorType::InvalidChoice(format!("{e}")))?))
+            Ok(Self(Color::parse(&string).map_err(|e| twilight_interactions::error::ParseOptionErrorType::InvalidChoice(format!("{e}")))?))
         } else {
             Err(twilight_interactions::error::ParseOptionErrorType::InvalidType(value.kind()))
         }
@@ -162,9 +278,9 @@ impl CreateOption for ColorOption {
             description: data.description,
             description_localizations: data.description_localizations,
             kind: twilight_model::application::command::CommandOptionType::String,
-            max_length: Some(7),
+            max_length: Some(MAX_COLOR_LENGTH),
             max_value: None,
-            min_length: Some(6),
+            min_length: Some(3),
             min_value: None,
             name: data.name,
             name_localizations: data.name_localizations,
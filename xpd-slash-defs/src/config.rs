@@ -18,6 +18,8 @@ pub enum ConfigCommand {
     Rewards(ConfigCommandRewards),
     #[command(name = "levels")]
     Levels(ConfigCommandLevels),
+    #[command(name = "logs")]
+    Logs(ConfigCommandLogs),
     #[command(name = "perms_checkup")]
     PermsCheckup(ConfigCommandPermsCheckup),
 }
@@ -77,6 +79,22 @@ pub struct ConfigCommandRewards {
     pub one_at_a_time: Option<bool>,
 }
 
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "logs",
+    desc = "Configure where moderator and settings changes are posted",
+    dm_permission = false
+)]
+pub struct ConfigCommandLogs {
+    #[command(
+        desc = "Channel to post audit log events in as they happen",
+        channel_types = "guild_text"
+    )]
+    pub channel: Option<InteractionChannel>,
+    #[command(desc = "Stop posting audit log events")]
+    pub disable: Option<bool>,
+}
+
 #[derive(CommandModel, CreateCommand)]
 #[command(name = "reset", desc = "Reset your guild's configuration")]
 pub struct ConfigCommandReset;
@@ -1,4 +1,7 @@
-use twilight_interactions::command::{CommandModel, CreateCommand, ResolvedUser};
+use twilight_interactions::command::{
+    CommandModel, CommandOption, CreateCommand, CreateOption, ResolvedUser,
+};
+use twilight_model::guild::Role;
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
@@ -13,6 +16,19 @@ pub struct LeaderboardCommand {
     pub page: Option<i64>,
     #[command(desc = "Want to show this off to everyone?")]
     pub show_off: Option<bool>,
+    #[command(desc = "Show the leaderboard as text or as an image (defaults to text)")]
+    pub style: Option<LeaderboardStyle>,
+    #[command(desc = "Only rank members with this role")]
+    pub role: Option<Role>,
+}
+
+#[derive(CommandOption, CreateOption, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LeaderboardStyle {
+    #[default]
+    #[option(name = "Text", value = "text")]
+    Text,
+    #[option(name = "Image", value = "image")]
+    Image,
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -26,4 +42,36 @@ pub struct RankCommand {
     pub user: Option<ResolvedUser>,
     #[command(desc = "Show off this card publicly")]
     pub show_off: Option<bool>,
+    #[command(desc = "Another member to compare them with")]
+    pub compare: Option<ResolvedUser>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "levels",
+    desc = "Learn how leveling works in this server",
+    dm_permission = false
+)]
+pub enum LevelsCommand {
+    #[command(name = "calculate")]
+    Calculate(LevelsCommandCalculate),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "calculate",
+    desc = "See how much XP a level takes, and how long it would take you to get there",
+    dm_permission = false
+)]
+pub struct LevelsCommandCalculate {
+    #[command(
+        desc = "Level to work out (defaults to your next level)",
+        min_value = 1,
+        max_value = 100_000
+    )]
+    pub level: Option<i64>,
+    #[command(desc = "Amount of XP to work out, instead of a level", min_value = 1)]
+    pub xp: Option<i64>,
+    #[command(desc = "Want to show this off to everyone?")]
+    pub show_off: Option<bool>,
 }
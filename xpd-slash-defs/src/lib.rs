@@ -22,7 +22,7 @@ use crate::{
     config::ConfigCommand,
     experience::XpCommand,
     gdpr::GdprCommand,
-    levels::{LeaderboardCommand, RankCommand},
+    levels::{LeaderboardCommand, LevelsCommand, RankCommand},
     manage::ManageCommand,
 };
 
@@ -45,6 +45,7 @@ pub fn get_commands() -> Vec<Command> {
         ConfigCommand::create_command().into(),
         GuildCardCommand::create_command().into(),
         LeaderboardCommand::create_command().into(),
+        LevelsCommand::create_command().into(),
         RewardsCommand::create_command().into(),
         AuditLogCommand::create_command().into(),
         context_cmd("Get level", CommandType::User),
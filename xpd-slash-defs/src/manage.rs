@@ -1,5 +1,9 @@
-use twilight_interactions::command::{CommandModel, CreateCommand};
-use twilight_model::{channel::Attachment, guild::Permissions};
+use twilight_interactions::command::{CommandModel, CommandOption, CreateCommand, CreateOption};
+use twilight_model::{
+    channel::Attachment,
+    guild::Permissions,
+    id::{marker::RoleMarker, Id},
+};
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
@@ -16,6 +20,10 @@ pub enum ManageCommand {
     Import(ManageCommandImport),
     #[command(name = "export")]
     Export(ManageCommandExport),
+    #[command(name = "backup")]
+    Backup(ManageCommandBackup),
+    #[command(name = "restore")]
+    Restore(ManageCommandRestore),
 }
 
 impl ManageCommand {
@@ -47,16 +55,76 @@ pub struct ManageCommandResetGuild {
     dm_permission = false
 )]
 pub struct ManageCommandImport {
-    #[command(desc = "Leveling JSON file")]
+    #[command(
+        desc = "JSON or CSV export from Experienced, MEE6, Lurkr, Arcane, Tatsu or AmariBot"
+    )]
     pub levels: Attachment,
     #[command(desc = "Overwrite, rather then summing with previous leveling data")]
     pub overwrite: Option<bool>,
+    #[command(desc = "Give users the XP for their level in the file, instead of copying their XP")]
+    pub convert_levels: Option<bool>,
+    #[command(desc = "Only check the file and preview the changes, without offering to import it")]
+    pub dry_run: Option<bool>,
 }
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "export",
-    desc = "Export this server's leveling data into a JSON file",
+    desc = "Export this server's leveling data",
     dm_permission = false
 )]
-pub struct ManageCommandExport;
+pub struct ManageCommandExport {
+    #[command(desc = "File format (defaults to JSON, which can be imported again)")]
+    pub format: Option<ExportFormat>,
+    #[command(desc = "Include each user's level and leaderboard rank")]
+    pub level_and_rank: Option<bool>,
+    #[command(desc = "Include each user's display name, if the bot knows it")]
+    pub names: Option<bool>,
+    #[command(desc = "Only export users at or above this level", min_value = 0)]
+    pub min_level: Option<i64>,
+    #[command(desc = "Only export members with this role")]
+    pub role: Option<Id<RoleMarker>>,
+    #[command(desc = "Compress the file with gzip (files too big for discord always are)")]
+    pub gzip: Option<bool>,
+}
+
+#[derive(CommandOption, CreateOption, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    #[option(name = "JSON", value = "json")]
+    Json,
+    #[option(name = "CSV", value = "csv")]
+    Csv,
+    #[option(name = "NDJSON (one user per line)", value = "ndjson")]
+    Ndjson,
+}
+
+impl ExportFormat {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "backup",
+    desc = "Download a full backup of this server's levels, settings, rewards and audit logs",
+    dm_permission = false
+)]
+pub struct ManageCommandBackup;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "restore",
+    desc = "Replace this server's data with a backup from /manage backup, after confirming",
+    dm_permission = false
+)]
+pub struct ManageCommandRestore {
+    #[command(desc = "Backup file created by /manage backup")]
+    pub backup: Attachment,
+}
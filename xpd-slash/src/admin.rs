@@ -39,6 +39,7 @@ pub async fn process_admin(
         AdminCommand::GuildStats(gs) => get_guild_stats(state, gs).await,
         AdminCommand::Stats(admin::AdminCommandStats) => get_bot_stats(state).await,
         AdminCommand::InspectCooldown(ic) => inspect_cooldown(state, ic).await,
+        AdminCommand::ReloadCards(admin::AdminCommandReloadCards) => reload_cards(state).await,
     }?;
     Ok(XpdInteractionData::new()
         .ephemeral(true)
@@ -64,6 +65,8 @@ async fn reset_user(state: SlashState, leave: AdminCommandResetUser) -> Result<S
     let mut tx = state.db.begin().await?;
     let rows = xpd_database::delete_levels_user(tx.as_mut(), leave.user).await?;
     xpd_database::delete_card_customizations(tx.as_mut(), leave.user.cast()).await?;
+    xpd_database::delete_card_themes(tx.as_mut(), leave.user.cast()).await?;
+    tx.commit().await?;
     Ok(format!(
         "Reset this user's levels. They had level data in {rows} guilds."
     ))
@@ -123,7 +126,25 @@ fn fmt_opt_u64(item: Option<u64>) -> impl Display {
 async fn get_bot_stats(state: SlashState) -> Result<String, Error> {
     let levels_held = xpd_database::total_levels(&state.db).await?;
     Ok(format!(
-        "Roughly {levels_held} levels in database. Bot version `git-{CURRENT_GIT_SHA}`"
+        "Roughly {levels_held} levels in database. Bot version `git-{CURRENT_GIT_SHA}`\n\
+        Card cache: {}\nRender pool: {}\nAvatar cache: {}\nRole icon cache: {}",
+        state.svg.render_cache_stats(),
+        state.svg.render_pool_stats(),
+        state.avatars.stats(),
+        state.role_icons.stats()
+    ))
+}
+
+async fn reload_cards(state: SlashState) -> Result<String, Error> {
+    let resources = state.svg.reload().await?;
+    let config = resources.config();
+    Ok(format!(
+        "Reloaded card resources from `{}`: {} cards, {} fonts, {} toys and {} themes.",
+        state.svg.data_dir().display(),
+        config.cards.len(),
+        config.fonts.len(),
+        config.toys.len(),
+        config.themes.len()
     ))
 }
 
@@ -1,45 +1,649 @@
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use serde_json::Value;
 use twilight_model::{
+    channel::message::{
+        component::{ActionRow, Button, ButtonStyle},
+        AllowedMentions, Component, EmojiReactionType,
+    },
     http::{attachment::Attachment, interaction::InteractionResponseType},
-    id::{marker::GuildMarker, Id},
+    id::{
+        marker::{GuildMarker, InteractionMarker, UserMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFooterBuilder};
+use xpd_common::{
+    AuditLogAction, AuditLogEvent, GuildAuditEntry, GuildAuditEvent, GuildAuditKind,
+    DISCORD_EPOCH_SECS,
+};
+use xpd_database::{AuditLogFilter, GuildAuditFilter};
+use xpd_slash_defs::audit::{
+    AuditLogCommand, AuditLogCommandList, AuditLogCommandRevert, AuditLogCommandSettings,
+    AuditLogFormat, TimestampOption,
 };
-use xpd_slash_defs::audit::AuditLogCommand;
+use xpd_util::snowflake_to_timestamp;
+
+use crate::{
+    log_channel::{mirror_audit_events, LoggedEvent},
+    response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData,
+};
+
+/// Who ran the command being audited, and when.
+#[derive(Clone, Copy, Debug)]
+pub struct AuditData {
+    pub interaction: Id<InteractionMarker>,
+    pub invoker: Id<UserMarker>,
+}
 
-use crate::{response::XpdInteractionResponse, Error, SlashState, XpdInteractionData};
+impl AuditData {
+    pub fn guild_event(
+        self,
+        guild_id: Id<GuildMarker>,
+        kind: GuildAuditKind,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) -> GuildAuditEvent {
+        GuildAuditEvent {
+            guild_id,
+            actor: self.invoker,
+            timestamp: snowflake_to_timestamp(self.interaction),
+            kind,
+            before,
+            after,
+        }
+    }
+}
 
 pub async fn process_audit_logs(
     command: AuditLogCommand,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
-    let mut logs =
-        xpd_database::get_audit_log_events(&state.db, guild_id, command.user, command.moderator)
-            .await?;
-    logs.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-
-    let mut file = Vec::with_capacity(logs.len() * 128);
-    {
-        let mut csv_writer = csv::Writer::from_writer(&mut file);
-        for log in logs {
-            csv_writer.serialize(log)?;
+    match command {
+        AuditLogCommand::List(list) => list_audit_logs(list, guild_id, state).await,
+        AuditLogCommand::Settings(settings) => {
+            list_guild_audit_logs(settings, guild_id, state).await
+        }
+        AuditLogCommand::Revert(revert) => {
+            let contents = revert_audit_logs(revert, guild_id, audit, state).await?;
+            Ok(XpdInteractionData::new()
+                .allowed_mentions_o(Some(AllowedMentions::default()))
+                .ephemeral(true)
+                .embeds([EmbedBuilder::new().description(contents).build()])
+                .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
         }
-        csv_writer.flush()?;
     }
+}
 
-    if file.is_empty() {
-        Ok(XpdInteractionData::new()
-            .content("No moderator actions recorded yet, audit log is empty.".to_string())
+async fn list_audit_logs(
+    command: AuditLogCommandList,
+    guild_id: Id<GuildMarker>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let filter = AuditLogFilter {
+        user: command.user,
+        moderator: command.moderator,
+        since: command.since.map(TimestampOption::audit_timestamp),
+        until: command.until.map(TimestampOption::audit_timestamp),
+        action: command.action.map(Into::into),
+    };
+    let format = command.format.unwrap_or_default();
+    if format == AuditLogFormat::Embed {
+        let page = gen_audit_log_page(&state, guild_id, filter, 0).await?;
+        return Ok(page
+            .ephemeral(true)
+            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource));
+    }
+
+    let mut logs = xpd_database::get_audit_log_events(&state.db, guild_id, filter, None, 0).await?;
+    if logs.is_empty() {
+        return Ok(XpdInteractionData::new()
+            .content(EMPTY_AUDIT_LOG.to_string())
             .ephemeral(true)
-            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource));
+    }
+    logs.sort_by_key(|a| (a.timestamp, a.id));
+
+    let (file, filename) = if format == AuditLogFormat::Json {
+        (serde_json::to_vec_pretty(&logs)?, "audit_log.json")
     } else {
-        let attachment = Attachment {
-            description: Some("Audit logs this server".to_owned()),
-            file,
-            filename: "audit_log.txt".to_string(),
-            id: 0,
+        let mut file = Vec::with_capacity(logs.len() * 128);
+        {
+            let mut csv_writer = csv::Writer::from_writer(&mut file);
+            for log in logs {
+                csv_writer.serialize(log)?;
+            }
+            csv_writer.flush()?;
+        }
+        (file, "audit_log.csv")
+    };
+
+    let attachment = Attachment {
+        description: Some("Audit logs this server".to_owned()),
+        file,
+        filename: filename.to_string(),
+        id: 0,
+    };
+    Ok(XpdInteractionData::new()
+        .attachments([attachment])
+        .ephemeral(true)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+const EMPTY_AUDIT_LOG: &str = "No moderator actions matched, audit log is empty.";
+const ENTRIES_PER_PAGE: i64 = 10;
+
+async fn gen_audit_log_page(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    filter: AuditLogFilter,
+    zpage: i64,
+) -> Result<XpdInteractionData, Error> {
+    if zpage.is_negative() {
+        return Err(Error::PageDoesNotExist);
+    }
+    let total = xpd_database::count_audit_log_events(&state.db, guild_id, filter).await?;
+    let entries = xpd_database::get_audit_log_events(
+        &state.db,
+        guild_id,
+        filter,
+        Some(ENTRIES_PER_PAGE),
+        zpage * ENTRIES_PER_PAGE,
+    )
+    .await?;
+
+    if entries.is_empty() {
+        return if zpage == 0 {
+            Ok(XpdInteractionData::new().content(EMPTY_AUDIT_LOG.to_string()))
+        } else {
+            Err(Error::PageDoesNotExist)
         };
-        Ok(XpdInteractionData::new()
-            .attachments([attachment])
+    }
+
+    let mut description = String::with_capacity(entries.len() * 128);
+    for entry in &entries {
+        let unix = entry.timestamp + DISCORD_EPOCH_SECS;
+        let after = entry.previous + entry.delta;
+        write!(
+            description,
+            "`#{}` <t:{unix}:f> <@{}> **{}** <@{}>: {} → {after} XP",
+            entry.id,
+            entry.moderator,
+            entry.action().name(),
+            entry.user_id,
+            entry.previous,
+        )?;
+        if let Some(reverts) = entry.reverts {
+            write!(description, " (reverts `#{reverts}`)")?;
+        }
+        description.push('\n');
+    }
+
+    Ok(paged_embed(
+        "Audit log",
+        description,
+        zpage,
+        total,
+        |zpage| page_custom_id(zpage, filter),
+    ))
+}
+
+fn paged_embed(
+    title: &str,
+    description: String,
+    zpage: i64,
+    total: i64,
+    custom_id: impl Fn(i64) -> String,
+) -> XpdInteractionData {
+    let page_count = (total + ENTRIES_PER_PAGE - 1) / ENTRIES_PER_PAGE;
+    let embed = EmbedBuilder::new()
+        .title(title)
+        .description(description)
+        .footer(EmbedFooterBuilder::new(format!("{total} matching entries")))
+        .build();
+    let buttons = page_buttons(zpage, page_count, custom_id);
+    XpdInteractionData::new()
+        .allowed_mentions(AllowedMentions::default())
+        .embeds([embed])
+        .components([Component::ActionRow(ActionRow {
+            components: buttons.to_vec(),
+        })])
+}
+
+fn page_buttons(zpage: i64, page_count: i64, custom_id: impl Fn(i64) -> String) -> [Component; 3] {
+    [
+        Button {
+            custom_id: Some(custom_id(zpage - 1)),
+            disabled: zpage == 0,
+            emoji: Some(EmojiReactionType::Unicode {
+                name: "⬅".to_string(),
+            }),
+            label: Some("Previous".to_string()),
+            style: ButtonStyle::Primary,
+            url: None,
+            sku_id: None,
+        },
+        Button {
+            custom_id: Some("audit_page_indicator".to_string()),
+            disabled: true,
+            emoji: None,
+            label: Some(format!("Page {} of {page_count}", zpage + 1)),
+            style: ButtonStyle::Secondary,
+            url: None,
+            sku_id: None,
+        },
+        Button {
+            custom_id: Some(custom_id(zpage + 1)),
+            disabled: zpage + 1 >= page_count,
+            emoji: Some(EmojiReactionType::Unicode {
+                name: "➡️".to_string(),
+            }),
+            label: Some("Next".to_string()),
+            style: ButtonStyle::Primary,
+            url: None,
+            sku_id: None,
+        },
+    ]
+    .map(Component::Button)
+}
+
+/// Like the leaderboard, the page and the whole filter live in the button's custom ID,
+/// so nothing about the message has to be stored.
+/// Timestamps are at most 12 characters (see `TimestampOption`), so with two 20-character snowflakes
+/// and a short action name, this stays well under discord's 100-character limit.
+fn page_custom_id(zpage: i64, filter: AuditLogFilter) -> String {
+    format!(
+        "{AUDIT_PAGE_PREFIX}{zpage}:{}:{}:{}:{}:{}",
+        opt(filter.user),
+        opt(filter.moderator),
+        opt(filter.since),
+        opt(filter.until),
+        opt(filter.action.map(AuditLogAction::name)),
+    )
+}
+
+fn opt<T: Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn parse_opt<T: FromStr>(value: Option<&str>) -> Result<Option<T>, Error> {
+    match value {
+        Some("") => Ok(None),
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::InvalidAuditLogPage),
+        None => Err(Error::InvalidAuditLogPage),
+    }
+}
+
+fn parse_page_custom_id(custom_id: &str) -> Result<(i64, AuditLogFilter), Error> {
+    let mut parts = custom_id
+        .strip_prefix(AUDIT_PAGE_PREFIX)
+        .ok_or(Error::InvalidAuditLogPage)?
+        .split(':');
+    let zpage = parse_opt(parts.next())?.ok_or(Error::InvalidAuditLogPage)?;
+    let user = parse_opt(parts.next())?;
+    let moderator = parse_opt(parts.next())?;
+    let since = parse_opt(parts.next())?;
+    let until = parse_opt(parts.next())?;
+    let action = match parts.next() {
+        Some("") => None,
+        Some(name) => Some(AuditLogAction::from_name(name).ok_or(Error::InvalidAuditLogPage)?),
+        None => return Err(Error::InvalidAuditLogPage),
+    };
+    let filter = AuditLogFilter {
+        user,
+        moderator,
+        since,
+        until,
+        action,
+    };
+    Ok((zpage, filter))
+}
+
+pub const AUDIT_PAGE_PREFIX: &str = "audit:";
+
+/// Audit log pages are always ephemeral, so only the moderator who ran `/audit` can click these.
+pub async fn process_audit_log_page(
+    custom_id: &str,
+    guild_id: Id<GuildMarker>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let (zpage, filter) = parse_page_custom_id(custom_id)?;
+    Ok(XpdInteractionResponse::new(
+        InteractionResponseType::UpdateMessage,
+        gen_audit_log_page(&state, guild_id, filter, zpage).await?,
+    ))
+}
+
+async fn list_guild_audit_logs(
+    command: AuditLogCommandSettings,
+    guild_id: Id<GuildMarker>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let filter = GuildAuditFilter {
+        actor: command.actor,
+        since: command.since.map(TimestampOption::audit_timestamp),
+        until: command.until.map(TimestampOption::audit_timestamp),
+        kind: command.kind.map(Into::into),
+    };
+    let format = command.format.unwrap_or_default();
+    if format == AuditLogFormat::Embed {
+        let page = gen_guild_audit_log_page(&state, guild_id, filter, 0).await?;
+        return Ok(page
+            .ephemeral(true)
+            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource));
+    }
+
+    let mut logs =
+        xpd_database::get_guild_audit_events(&state.db, guild_id, filter, None, 0).await?;
+    if logs.is_empty() {
+        return Ok(XpdInteractionData::new()
+            .content(EMPTY_GUILD_AUDIT_LOG.to_string())
             .ephemeral(true)
-            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource));
+    }
+    logs.sort_by_key(|a| (a.timestamp, a.id));
+
+    let (file, filename) = if format == AuditLogFormat::Json {
+        (serde_json::to_vec_pretty(&logs)?, "settings_audit_log.json")
+    } else {
+        let mut file = Vec::with_capacity(logs.len() * 256);
+        {
+            let mut csv_writer = csv::Writer::from_writer(&mut file);
+            for log in logs {
+                csv_writer.serialize(GuildAuditCsvRow::from(log))?;
+            }
+            csv_writer.flush()?;
+        }
+        (file, "settings_audit_log.csv")
+    };
+
+    let attachment = Attachment {
+        description: Some("Settings audit logs for this server".to_owned()),
+        file,
+        filename: filename.to_string(),
+        id: 0,
+    };
+    Ok(XpdInteractionData::new()
+        .attachments([attachment])
+        .ephemeral(true)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+/// CSV can't hold nested data, so the before and after snapshots are written as JSON strings.
+#[derive(serde::Serialize)]
+struct GuildAuditCsvRow {
+    id: i64,
+    guild_id: Id<GuildMarker>,
+    actor: Id<UserMarker>,
+    timestamp: i64,
+    kind: &'static str,
+    before: String,
+    after: String,
+}
+
+impl From<GuildAuditEntry> for GuildAuditCsvRow {
+    fn from(value: GuildAuditEntry) -> Self {
+        let json = |v: Option<Value>| v.map(|v| v.to_string()).unwrap_or_default();
+        Self {
+            id: value.id,
+            guild_id: value.guild_id,
+            actor: value.actor,
+            timestamp: value.timestamp,
+            kind: value.kind.name(),
+            before: json(value.before),
+            after: json(value.after),
+        }
+    }
+}
+
+const EMPTY_GUILD_AUDIT_LOG: &str = "No settings changes matched, audit log is empty.";
+
+async fn gen_guild_audit_log_page(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    filter: GuildAuditFilter,
+    zpage: i64,
+) -> Result<XpdInteractionData, Error> {
+    if zpage.is_negative() {
+        return Err(Error::PageDoesNotExist);
+    }
+    let total = xpd_database::count_guild_audit_events(&state.db, guild_id, filter).await?;
+    let entries = xpd_database::get_guild_audit_events(
+        &state.db,
+        guild_id,
+        filter,
+        Some(ENTRIES_PER_PAGE),
+        zpage * ENTRIES_PER_PAGE,
+    )
+    .await?;
+
+    if entries.is_empty() {
+        return if zpage == 0 {
+            Ok(XpdInteractionData::new().content(EMPTY_GUILD_AUDIT_LOG.to_string()))
+        } else {
+            Err(Error::PageDoesNotExist)
+        };
+    }
+
+    let mut description = String::with_capacity(entries.len() * 128);
+    for entry in &entries {
+        let unix = entry.timestamp + DISCORD_EPOCH_SECS;
+        write!(
+            description,
+            "`#{}` <t:{unix}:f> <@{}> **{}**",
+            entry.id,
+            entry.actor,
+            entry.kind.name(),
+        )?;
+        let changes = describe_change(entry.before.as_ref(), entry.after.as_ref());
+        if !changes.is_empty() {
+            write!(description, ": {changes}")?;
+        }
+        description.push('\n');
+    }
+
+    Ok(paged_embed(
+        "Settings audit log",
+        description,
+        zpage,
+        total,
+        |zpage| guild_page_custom_id(zpage, filter),
+    ))
+}
+
+/// A short summary of what changed between two snapshots, for the paged view.
+/// Objects list their changed keys, lists show their length change, and anything else
+/// shows nothing, because the full payload is in the CSV and JSON downloads.
+pub fn describe_change(before: Option<&Value>, after: Option<&Value>) -> String {
+    match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let mut keys: Vec<&str> = before
+                .keys()
+                .chain(after.keys())
+                .filter(|key| before.get(*key) != after.get(*key))
+                .map(String::as_str)
+                .collect();
+            keys.sort_unstable();
+            keys.dedup();
+            keys.iter()
+                .map(|key| format!("`{key}`"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+        (Some(Value::Array(before)), Some(Value::Array(after))) => {
+            format!("{} → {} entries", before.len(), after.len())
+        }
+        (None, Some(Value::Object(after))) => {
+            let mut keys: Vec<&str> = after
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, _)| key.as_str())
+                .collect();
+            keys.sort_unstable();
+            keys.iter()
+                .map(|key| format!("`{key}`"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+        _ => String::new(),
+    }
+}
+
+pub const GUILD_AUDIT_PAGE_PREFIX: &str = "auditcfg:";
+
+fn guild_page_custom_id(zpage: i64, filter: GuildAuditFilter) -> String {
+    format!(
+        "{GUILD_AUDIT_PAGE_PREFIX}{zpage}:{}:{}:{}:{}",
+        opt(filter.actor),
+        opt(filter.since),
+        opt(filter.until),
+        opt(filter.kind.map(GuildAuditKind::name)),
+    )
+}
+
+fn parse_guild_page_custom_id(custom_id: &str) -> Result<(i64, GuildAuditFilter), Error> {
+    let mut parts = custom_id
+        .strip_prefix(GUILD_AUDIT_PAGE_PREFIX)
+        .ok_or(Error::InvalidAuditLogPage)?
+        .split(':');
+    let zpage = parse_opt(parts.next())?.ok_or(Error::InvalidAuditLogPage)?;
+    let actor = parse_opt(parts.next())?;
+    let since = parse_opt(parts.next())?;
+    let until = parse_opt(parts.next())?;
+    let kind = match parts.next() {
+        Some("") => None,
+        Some(name) => Some(GuildAuditKind::from_name(name).ok_or(Error::InvalidAuditLogPage)?),
+        None => return Err(Error::InvalidAuditLogPage),
+    };
+    let filter = GuildAuditFilter {
+        actor,
+        since,
+        until,
+        kind,
+    };
+    Ok((zpage, filter))
+}
+
+pub async fn process_guild_audit_log_page(
+    custom_id: &str,
+    guild_id: Id<GuildMarker>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let (zpage, filter) = parse_guild_page_custom_id(custom_id)?;
+    Ok(XpdInteractionResponse::new(
+        InteractionResponseType::UpdateMessage,
+        gen_guild_audit_log_page(&state, guild_id, filter, zpage).await?,
+    ))
+}
+
+/// Restore the XP each targeted entry's user had before that entry, newest entry first,
+/// so that reverting several actions against the same user ends at the oldest `previous`.
+async fn revert_audit_logs(
+    command: AuditLogCommandRevert,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    state: SlashState,
+) -> Result<String, Error> {
+    if command.entry.is_some()
+        && (command.moderator.is_some() || command.since.is_some() || command.until.is_some())
+    {
+        return Err(Error::WrongArgumentCount(
+            "`/audit revert` takes either an entry, or a moderator with an optional time range.",
+        ));
+    }
+
+    let mut txn = state.db.begin().await?;
+    let reverted: HashSet<i64> = xpd_database::reverted_audit_log_entries(txn.as_mut(), guild_id)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut targets = if let Some(entry_id) = command.entry {
+        let entry = xpd_database::get_audit_log_entry(txn.as_mut(), guild_id, entry_id)
+            .await?
+            .ok_or(Error::UnknownAuditLogEntry)?;
+        if reverted.contains(&entry.id) {
+            return Err(Error::AuditLogEntryAlreadyReverted);
+        }
+        vec![entry]
+    } else if let Some(moderator) = command.moderator {
+        let filter = AuditLogFilter {
+            moderator: Some(moderator),
+            since: command.since.map(TimestampOption::audit_timestamp),
+            until: command.until.map(TimestampOption::audit_timestamp),
+            ..Default::default()
+        };
+        let mut entries =
+            xpd_database::get_audit_log_events(txn.as_mut(), guild_id, filter, None, 0).await?;
+        entries.retain(|entry| !reverted.contains(&entry.id));
+        entries
+    } else {
+        return Err(Error::WrongArgumentCount(
+            "`/audit revert` requires an entry or a moderator.",
+        ));
+    };
+
+    if targets.is_empty() {
+        return Err(Error::NothingToRevert);
+    }
+
+    targets.sort_by_key(|entry| Reverse((entry.timestamp, entry.id)));
+    let timestamp = snowflake_to_timestamp(audit.interaction);
+    let mut logged = Vec::with_capacity(targets.len());
+    for entry in &targets {
+        let current = xpd_database::user_xp(txn.as_mut(), guild_id, entry.user_id)
+            .await?
+            .unwrap_or(0);
+        if current != entry.previous {
+            xpd_database::set_xp(txn.as_mut(), entry.user_id, guild_id, entry.previous).await?;
+        }
+        let audit_event = AuditLogEvent {
+            guild_id,
+            user_id: entry.user_id,
+            moderator: audit.invoker,
+            timestamp,
+            previous: current,
+            delta: entry.previous - current,
+            reset: false,
+            set: true,
+            reverts: Some(entry.id),
+            import: false,
+        };
+        // A concurrent revert of the same entry can pass the check above, so the unique
+        // index on `reverts` is what actually stops an entry being undone twice.
+        let audit_id = xpd_database::add_audit_log_event(txn.as_mut(), audit_event)
+            .await
+            .map_err(|source| match source {
+                xpd_database::Error::AlreadyReverted => Error::AuditLogEntryAlreadyReverted,
+                source => Error::Database(source),
+            })?;
+        logged.push(LoggedEvent::Xp(audit_id, audit_event));
+    }
+
+    txn.commit().await?;
+    mirror_audit_events(&state, guild_id, logged);
+
+    if let [entry] = targets.as_slice() {
+        return Ok(format!(
+            "Reverted audit log entry {}, restoring <@{}> to {} XP",
+            entry.id, entry.user_id, entry.previous
+        ));
     }
+    let users: HashSet<_> = targets.iter().map(|entry| entry.user_id).collect();
+    Ok(format!(
+        "Reverted {} audit log entries, restoring the XP of {} users",
+        targets.len(),
+        users.len()
+    ))
 }
@@ -5,6 +5,10 @@ use twilight_model::{
         interaction::application_command::CommandData,
     },
     http::interaction::{InteractionResponse, InteractionResponseType},
+    id::{
+        marker::{GenericMarker, GuildMarker, UserMarker},
+        Id,
+    },
 };
 use twilight_util::builder::InteractionResponseDataBuilder;
 use xpd_rank_card::NameableItem;
@@ -25,20 +29,34 @@ fn empty_response<T: std::fmt::Debug>(error: T) -> XpdInteractionResponse {
     .into()
 }
 
-pub fn autocomplete(state: &SlashState, data: CommandData) -> XpdInteractionResponse {
-    autocomplete_inner(state, data).unwrap_or_else(empty_response)
+pub async fn autocomplete(
+    state: &SlashState,
+    data: CommandData,
+    invoker: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+) -> XpdInteractionResponse {
+    autocomplete_inner(state, data, invoker, guild_id)
+        .await
+        .unwrap_or_else(empty_response)
 }
 
-pub fn autocomplete_inner(
+pub async fn autocomplete_inner(
     state: &SlashState,
     data: CommandData,
+    invoker: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
 ) -> Result<XpdInteractionResponse, Error> {
     debug!(options = ?data, "Got autocomplete");
+    // Saved themes belong to whoever the card belongs to
     let choices = match data.name.as_str() {
-        "card" => card_autocomplete(data, state)?.into_iter(),
-        "guild-card" => card_autocomplete(data, state)?.into_iter(),
+        "card" => card_autocomplete(data, state, invoker.cast()).await?,
+        "guild-card" => {
+            let guild_id = guild_id.ok_or(Error::NoGuildId)?;
+            card_autocomplete(data, state, guild_id.cast()).await?
+        }
         _ => return Err(Error::NoAutocompleteForCommand),
-    };
+    }
+    .into_iter();
 
     let ird = XpdInteractionData::new().choices(choices.take(25).collect::<Vec<_>>());
     Ok(XpdInteractionResponse::new(
@@ -47,24 +65,60 @@ pub fn autocomplete_inner(
     ))
 }
 
-fn card_autocomplete(
+async fn card_autocomplete(
     data: CommandData,
     state: &SlashState,
-) -> Result<impl IntoIterator<Item = CommandOptionChoice>, Error> {
+    owner: Id<GenericMarker>,
+) -> Result<Vec<CommandOptionChoice>, Error> {
     let card_autocomplete = CardCommandAutocomplete::from_interaction(data.into())?;
 
-    let CardCommandAutocomplete::Edit(edit) = card_autocomplete else {
-        return Err(Error::NoAutocompleteForCommand);
-    };
-
-    let fonts = choices(&edit.font, &state.svg.config().fonts, false);
-    let cards = choices(&edit.card_layout, &state.svg.config().cards, false);
-    let toys = choices(&edit.toy_image, &state.svg.config().toys, true);
+    match card_autocomplete {
+        CardCommandAutocomplete::Edit(edit) => {
+            let resources = state.svg.resources();
+            let fonts = choices(&edit.font, &resources.config().fonts, false);
+            let cards = choices(&edit.card_layout, &resources.config().cards, false);
+            let toys = choices(&edit.toy_image, &resources.config().toys, true);
 
-    debug!(interaction = ?edit, ?fonts, ?cards, ?toys, "picked out some choices");
+            debug!(interaction = ?edit, ?fonts, ?cards, ?toys, "picked out some choices");
 
-    let choice_chain = fonts.into_iter().chain(toys).chain(cards);
-    Ok(choice_chain)
+            Ok(fonts.into_iter().chain(toys).chain(cards).collect())
+        }
+        CardCommandAutocomplete::Theme(theme) => {
+            let AutocompleteValue::Focused(input) = &theme.theme else {
+                return Ok(Vec::new());
+            };
+            let mut output = choices(&theme.theme, &state.svg.resources().config().themes, false);
+            // Saved themes are picked by code, which also works for themes shared by others
+            let saved = xpd_database::card_themes(&state.db, owner).await?;
+            output.extend(
+                saved
+                    .into_iter()
+                    .filter(|saved| saved.theme.name.contains(input.as_str()))
+                    .map(|saved| CommandOptionChoice {
+                        name: format!("{} (saved)", saved.theme.name),
+                        name_localizations: None,
+                        value: CommandOptionChoiceValue::String(saved.code),
+                    }),
+            );
+            Ok(output)
+        }
+        CardCommandAutocomplete::DeleteTheme(delete) => {
+            let AutocompleteValue::Focused(input) = &delete.name else {
+                return Ok(Vec::new());
+            };
+            let saved = xpd_database::card_themes(&state.db, owner).await?;
+            Ok(saved
+                .into_iter()
+                .filter(|saved| saved.theme.name.contains(input.as_str()))
+                .map(|saved| CommandOptionChoice {
+                    name: saved.theme.name.clone(),
+                    name_localizations: None,
+                    value: CommandOptionChoiceValue::String(saved.theme.name),
+                })
+                .collect())
+        }
+        _ => Err(Error::NoAutocompleteForCommand),
+    }
 }
 
 fn choices<I: NameableItem>(
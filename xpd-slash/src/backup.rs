@@ -0,0 +1,938 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io::{Read, Write as _},
+    time::SystemTime,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use simpleinterpolation::Interpolation;
+use sqlx::PgConnection;
+use twilight_model::{
+    channel::{message::AllowedMentions, Attachment},
+    http::attachment::Attachment as HttpAttachment,
+    id::{
+        marker::{ChannelMarker, GuildMarker, UserMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+use xpd_common::{
+    AuditLogEntry, GuildAuditEntry, GuildAuditEvent, GuildAuditKind, GuildConfig, RoleReward,
+    UserCooldown,
+};
+use xpd_database::{
+    AuditLogFilter, CardUpdate, GuildAuditFilter, RawCustomizations, UpdateGuildConfig,
+};
+use xpd_rank_card::{customizations::Color, Config, NameableItem, CUSTOM_LAYOUT};
+
+use crate::{
+    audit::AuditData,
+    dispatch::Respondable,
+    log_channel::{mirror_audit_events, LoggedEvent},
+    manage_card::MAX_BACKGROUND_BYTES,
+    manager::{fetch_attachment, ImportUser},
+    pending::{confirm_buttons, PendingAction},
+    Error, SlashState, XpdInteractionData,
+};
+
+const ARCHIVE_FORMAT: &str = "experienced-guild-archive";
+const ARCHIVE_VERSION: u32 = 2;
+/// Largest archive we will read, after decompressing it
+const MAX_ARCHIVE_SIZE: usize = 1024 * 1024 * 25;
+/// Discord's upload limit for bots in servers without boosts. Larger archives are compressed.
+const MAX_UPLOAD_SIZE: usize = 1024 * 1024 * 10;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Everything experienced stores about a guild, in a format which can be moved between bot instances.
+/// Fields may be added within a version, but changing or removing one requires a new version.
+#[derive(Serialize, Deserialize)]
+pub struct GuildArchive {
+    format: String,
+    version: u32,
+    guild_id: Id<GuildMarker>,
+    /// Unix timestamp of when this archive was created
+    created_at: i64,
+    levels: Vec<ImportUser>,
+    config: Option<GuildConfig>,
+    rewards: Vec<RoleReward>,
+    card: Option<ArchivedCard>,
+    /// Template of the guild's uploaded card layout, added in version 2
+    #[serde(default)]
+    card_layout_template: Option<String>,
+    audit_logs: Vec<AuditLogEntry>,
+    settings_audit_logs: Vec<GuildAuditEntry>,
+    cooldowns: Vec<UserCooldown>,
+}
+
+/// Read just enough of an archive to know if we can read the rest of it.
+#[derive(Deserialize)]
+struct ArchiveHeader {
+    format: String,
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+struct ArchivedCard {
+    username: Option<String>,
+    rank: Option<String>,
+    level: Option<String>,
+    border: Option<String>,
+    background: Option<String>,
+    progress_foreground: Option<String>,
+    progress_background: Option<String>,
+    background_xp_count: Option<String>,
+    foreground_xp_count: Option<String>,
+    font: Option<String>,
+    toy_image: Option<String>,
+    card_layout: String,
+    #[serde(default)]
+    animated_avatar: Option<bool>,
+    /// Base64-encoded uploaded background image, added in version 2
+    #[serde(default)]
+    background_image: Option<String>,
+}
+
+impl ArchivedCard {
+    fn decode_background(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.background_image
+            .as_ref()
+            .map(|image| {
+                BASE64.decode(image).map_err(|_| {
+                    Error::InvalidBackup("the card background is not valid base64".to_string())
+                })
+            })
+            .transpose()
+    }
+}
+
+impl From<RawCustomizations> for ArchivedCard {
+    fn from(value: RawCustomizations) -> Self {
+        Self {
+            username: value.username,
+            rank: value.rank,
+            level: value.level,
+            border: value.border,
+            background: value.background,
+            progress_foreground: value.progress_foreground,
+            progress_background: value.progress_background,
+            background_xp_count: value.background_xp_count,
+            foreground_xp_count: value.foreground_xp_count,
+            font: value.font,
+            toy_image: value.toy_image,
+            card_layout: value.card_layout,
+            animated_avatar: value.animated_avatar,
+            background_image: None,
+        }
+    }
+}
+
+impl From<ArchivedCard> for CardUpdate {
+    fn from(value: ArchivedCard) -> Self {
+        Self {
+            username: value.username,
+            rank: value.rank,
+            level: value.level,
+            border: value.border,
+            background: value.background,
+            progress_background: value.progress_background,
+            progress_foreground: value.progress_foreground,
+            foreground_xp_count: value.foreground_xp_count,
+            background_xp_count: value.background_xp_count,
+            font: value.font,
+            toy_image: value.toy_image,
+            card_layout: Some(value.card_layout.clone()),
+            card_layout_default: value.card_layout,
+            animated_avatar: value.animated_avatar,
+        }
+    }
+}
+
+/// A validated archive, waiting for the user to confirm that it should replace their data.
+pub struct RestorePlan {
+    archive: GuildArchive,
+    filename: String,
+}
+
+pub fn backup_guild(
+    state: SlashState,
+    respondable: Respondable,
+    guild_id: Id<GuildMarker>,
+) -> String {
+    state.clone().spawn(async move {
+        let response = background_backup(&state, guild_id)
+            .await
+            .unwrap_or_else(|source| {
+                error!(?source, "Failed to back up guild");
+                XpdInteractionData::with_embed_text(format!("Failed to back up server: {source}"))
+            })
+            .ephemeral(true);
+        state.send_followup(response, respondable.token()).await;
+    });
+    "Backing up server data, check back soon!".to_string()
+}
+
+async fn background_backup(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<XpdInteractionData, Error> {
+    let mut txn = state.db.begin().await?;
+    let archive = create_archive(&mut txn, guild_id).await?;
+    txn.commit().await?;
+
+    let mut message = format!(
+        "Backed up {} users, {} rewards and {} audit log entries!",
+        archive.levels.len(),
+        archive.rewards.len(),
+        archive.audit_logs.len() + archive.settings_audit_logs.len()
+    );
+    let (file, compressed) =
+        tokio::task::spawn_blocking(move || encode_archive(&archive)).await??;
+    let mut filename = format!("backup-{guild_id}.json");
+    if compressed {
+        filename.push_str(".gz");
+        message.push_str(" The file is compressed with gzip, and can be restored as it is.");
+    }
+    let attachment = HttpAttachment::from_bytes(filename, file, 0);
+    Ok(XpdInteractionData::new()
+        .content(message)
+        .attachments([attachment]))
+}
+
+/// Serialize an archive, and compress it if it is too big to upload.
+/// Returns the file, and whether it was compressed.
+fn encode_archive(archive: &GuildArchive) -> Result<(Vec<u8>, bool), Error> {
+    let file = serde_json::to_vec(archive)?;
+    if file.len() <= MAX_UPLOAD_SIZE {
+        return Ok((file, false));
+    }
+    let mut encoder = GzEncoder::new(Vec::with_capacity(file.len() / 4), Compression::best());
+    encoder.write_all(&file)?;
+    let file = encoder.finish()?;
+    if file.len() > MAX_UPLOAD_SIZE {
+        return Err(Error::BackupTooBig);
+    }
+    Ok((file, true))
+}
+
+async fn create_archive(
+    conn: &mut PgConnection,
+    guild_id: Id<GuildMarker>,
+) -> Result<GuildArchive, Error> {
+    let levels = xpd_database::export_bulk_users(&mut *conn, guild_id)
+        .await?
+        .into_iter()
+        .map(|user| ImportUser {
+            id: user.id,
+            xp: user.xp,
+        })
+        .collect();
+    let config = xpd_database::guild_config(&mut *conn, guild_id).await?;
+    let mut rewards = xpd_database::guild_rewards(&mut *conn, guild_id).await?;
+    rewards.sort_by_key(|reward| reward.requirement);
+    let mut card = xpd_database::card_customizations(&mut *conn, &[guild_id.cast()])
+        .await?
+        .map(ArchivedCard::from);
+    if let Some(card) = &mut card {
+        card.background_image = xpd_database::card_background(&mut *conn, &[guild_id.cast()])
+            .await?
+            .map(|image| BASE64.encode(image));
+    }
+    let card_layout_template = xpd_database::guild_card_layout(&mut *conn, guild_id).await?;
+    let mut audit_logs = xpd_database::get_audit_log_events(
+        &mut *conn,
+        guild_id,
+        AuditLogFilter::default(),
+        None,
+        0,
+    )
+    .await?;
+    audit_logs.sort_by_key(|entry| entry.id);
+    let mut settings_audit_logs = xpd_database::get_guild_audit_events(
+        &mut *conn,
+        guild_id,
+        GuildAuditFilter::default(),
+        None,
+        0,
+    )
+    .await?;
+    settings_audit_logs.sort_by_key(|entry| entry.id);
+    let cooldowns = xpd_database::guild_cooldowns(&mut *conn, guild_id).await?;
+
+    let created_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |v| v.as_secs().try_into().unwrap_or(i64::MAX));
+    Ok(GuildArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        guild_id,
+        created_at,
+        levels,
+        config,
+        rewards,
+        card,
+        card_layout_template,
+        audit_logs,
+        settings_audit_logs,
+        cooldowns,
+    })
+}
+
+pub fn restore_guild(
+    state: SlashState,
+    respondable: Respondable,
+    guild_id: Id<GuildMarker>,
+    invoker: Id<UserMarker>,
+    attachment: Attachment,
+) -> String {
+    state.clone().spawn(async move {
+        let response =
+            background_restore_check(&state, &respondable, guild_id, invoker, attachment)
+                .await
+                .unwrap_or_else(|source| {
+                    error!(?source, "Failed to check guild backup");
+                    XpdInteractionData::with_embed_text(format!("Failed to read backup: {source}"))
+                })
+                .ephemeral(true);
+        state.send_followup(response, respondable.token()).await;
+    });
+    "Checking backup, check back soon!".to_string()
+}
+
+/// Download and validate a backup, then ask the user to confirm the changes it would make.
+async fn background_restore_check(
+    state: &SlashState,
+    respondable: &Respondable,
+    guild_id: Id<GuildMarker>,
+    invoker: Id<UserMarker>,
+    attachment: Attachment,
+) -> Result<XpdInteractionData, Error> {
+    let body = fetch_attachment(state, &attachment.url, MAX_ARCHIVE_SIZE).await?;
+    let mut archive = parse_archive(&decompress_archive(body)?)?;
+    validate_archive(&archive, state.svg.resources().config())?;
+    let dropped_channels = drop_foreign_channels(&mut archive, |channel| {
+        state
+            .cache
+            .channel(channel)
+            .is_some_and(|channel| channel.guild_id == Some(guild_id))
+    });
+    if let Some(template) = &archive.card_layout_template {
+        match state.svg.validate_layout(template.clone()).await {
+            Ok(()) => {}
+            Err(xpd_rank_card::Error::Layout(e)) => {
+                return Err(Error::InvalidBackup(format!(
+                    "the card layout is invalid: {e}"
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let mut txn = state.db.begin().await?;
+    let current = create_archive(&mut txn, guild_id).await?;
+    txn.commit().await?;
+
+    let mut description = describe_restore(&current, &archive, &dropped_channels)?;
+    if archive.guild_id != guild_id {
+        writeln!(
+            description,
+            "\n⚠️ This backup was made in a different server ({}).",
+            archive.guild_id
+        )?;
+    }
+    writeln!(
+        description,
+        "\nConfirming will **replace** all of this server's data with the backup's."
+    )?;
+
+    let embed = EmbedBuilder::new()
+        .title("Restore this backup?")
+        .description(description)
+        .build();
+    let plan = RestorePlan {
+        archive,
+        filename: attachment.filename,
+    };
+    state.pending.insert(
+        respondable.id(),
+        guild_id,
+        invoker,
+        PendingAction::Restore(Box::new(plan)),
+    );
+    Ok(XpdInteractionData::new()
+        .allowed_mentions(AllowedMentions::default())
+        .embeds([embed])
+        .components([confirm_buttons(respondable.id())]))
+}
+
+/// Large backups are gzipped, so both compressed and plain archives are accepted.
+fn decompress_archive(body: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if !body.starts_with(&GZIP_MAGIC) {
+        return Ok(body);
+    }
+    let mut file = Vec::with_capacity(body.len() * 4);
+    GzDecoder::new(body.as_slice())
+        .take(MAX_ARCHIVE_SIZE as u64 + 1)
+        .read_to_end(&mut file)
+        .map_err(|_| Error::InvalidBackup("the backup is not a valid gzip file".to_string()))?;
+    if file.len() > MAX_ARCHIVE_SIZE {
+        return Err(Error::InvalidBackup(format!(
+            "backups can be at most {} MiB uncompressed",
+            MAX_ARCHIVE_SIZE / 1024 / 1024
+        )));
+    }
+    Ok(file)
+}
+
+fn parse_archive(body: &[u8]) -> Result<GuildArchive, Error> {
+    let header: ArchiveHeader = serde_json::from_slice(body)
+        .map_err(|_| Error::InvalidBackup("this is not an Experienced backup".to_string()))?;
+    if header.format != ARCHIVE_FORMAT {
+        return Err(Error::InvalidBackup(
+            "this is not an Experienced backup".to_string(),
+        ));
+    }
+    if header.version > ARCHIVE_VERSION {
+        return Err(Error::UnsupportedBackupVersion(header.version));
+    }
+    serde_json::from_slice(body).map_err(|source| Error::InvalidBackup(source.to_string()))
+}
+
+fn validate_archive(archive: &GuildArchive, resources: &Config) -> Result<(), Error> {
+    let mut users = HashMap::with_capacity(archive.levels.len());
+    for user in &archive.levels {
+        if user.xp.is_negative() {
+            return Err(Error::InvalidBackup(format!(
+                "<@{}> has negative XP",
+                user.id
+            )));
+        }
+        if users.insert(user.id, user.xp).is_some() {
+            return Err(Error::InvalidBackup(format!(
+                "<@{}> is listed more than once",
+                user.id
+            )));
+        }
+    }
+
+    let mut roles = HashMap::with_capacity(archive.rewards.len());
+    let mut requirements = HashMap::with_capacity(archive.rewards.len());
+    for reward in &archive.rewards {
+        if roles.insert(reward.id, reward.requirement).is_some()
+            || requirements.insert(reward.requirement, reward.id).is_some()
+        {
+            return Err(Error::InvalidBackup(format!(
+                "the reward <@&{}> at level {} conflicts with another reward",
+                reward.id, reward.requirement
+            )));
+        }
+    }
+
+    if let Some(config) = &archive.config {
+        crate::config::validate_config(config)?;
+        if let Some(message) = &config.level_up_message {
+            crate::config::check_template_variables(message)?;
+        }
+    }
+
+    if let Some(card) = &archive.card {
+        validate_card(card, resources)?;
+    }
+    if archive
+        .card_layout_template
+        .as_ref()
+        .is_some_and(|template| template.len() > xpd_rank_card::MAX_LAYOUT_BYTES)
+    {
+        return Err(Error::InvalidBackup(
+            "the card layout is too big".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check a card the same way `/card edit` would, so a restored card always renders.
+fn validate_card(card: &ArchivedCard, resources: &Config) -> Result<(), Error> {
+    let colors = [
+        &card.username,
+        &card.rank,
+        &card.level,
+        &card.border,
+        &card.background,
+        &card.progress_foreground,
+        &card.progress_background,
+        &card.background_xp_count,
+        &card.foreground_xp_count,
+    ];
+    for color in colors.into_iter().flatten() {
+        Color::parse(color).map_err(|e| {
+            Error::InvalidBackup(format!("the card color `{color}` is invalid: {e}"))
+        })?;
+    }
+    if let Some(image) = card.decode_background()? {
+        if image.len() > MAX_BACKGROUND_BYTES {
+            return Err(Error::InvalidBackup(
+                "the card background is too big".to_string(),
+            ));
+        }
+        xpd_rank_card::check_background(image)
+            .map_err(|e| Error::InvalidBackup(format!("the card background is invalid: {e}")))?;
+    }
+
+    if let Some(font) = card
+        .font
+        .as_ref()
+        .filter(|font| !has_item(&resources.fonts, font))
+    {
+        return Err(Error::InvalidBackup(format!(
+            "the card font `{font}` does not exist"
+        )));
+    }
+    if let Some(toy) = card
+        .toy_image
+        .as_ref()
+        .filter(|toy| !has_item(&resources.toys, toy))
+    {
+        return Err(Error::InvalidBackup(format!(
+            "the card toy `{toy}` does not exist"
+        )));
+    }
+    if card.card_layout != CUSTOM_LAYOUT && !has_item(&resources.cards, &card.card_layout) {
+        return Err(Error::InvalidBackup(format!(
+            "the card layout `{}` does not exist",
+            card.card_layout
+        )));
+    }
+    Ok(())
+}
+
+fn has_item<I: NameableItem>(items: &[I], name: &str) -> bool {
+    items.iter().any(|item| item.internal_name() == name)
+}
+
+/// Clear the configured channels which `is_ours` says aren't in the server being restored.
+/// Backups from other servers, or edited ones, could otherwise send this server's level-ups
+/// and audit logs somewhere else. Returns the names of the cleared settings.
+fn drop_foreign_channels(
+    archive: &mut GuildArchive,
+    is_ours: impl Fn(Id<ChannelMarker>) -> bool,
+) -> Vec<&'static str> {
+    let Some(config) = archive.config.as_mut() else {
+        return Vec::new();
+    };
+    let mut dropped = Vec::new();
+    for (name, channel) in [
+        ("level-up channel", &mut config.level_up_channel),
+        ("log channel", &mut config.log_channel),
+    ] {
+        if channel.is_some_and(|channel| !is_ours(channel)) {
+            *channel = None;
+            dropped.push(name);
+        }
+    }
+    dropped
+}
+
+/// Summarize what restoring `archive` would change, compared to `current`.
+/// `dropped_channels` are the settings [`drop_foreign_channels`] cleared.
+fn describe_restore(
+    current: &GuildArchive,
+    archive: &GuildArchive,
+    dropped_channels: &[&str],
+) -> Result<String, Error> {
+    let mut description = String::with_capacity(512);
+    writeln!(
+        description,
+        "Backup created <t:{}:f>, from `{}` version {}\n",
+        archive.created_at, archive.format, archive.version
+    )?;
+
+    let current_levels: HashMap<Id<UserMarker>, i64> =
+        current.levels.iter().map(|v| (v.id, v.xp)).collect();
+    let mut added = 0;
+    let mut changed = 0;
+    for user in &archive.levels {
+        match current_levels.get(&user.id) {
+            None => added += 1,
+            Some(xp) if *xp != user.xp => changed += 1,
+            Some(_) => {}
+        }
+    }
+    let removed = current.levels.len() + added - archive.levels.len();
+    writeln!(
+        description,
+        "**Levels:** {} → {} users ({added} added, {removed} removed, {changed} changed)",
+        current.levels.len(),
+        archive.levels.len()
+    )?;
+
+    let current_config = current
+        .config
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+    let archive_config = archive
+        .config
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+    let config_changes =
+        crate::audit::describe_change(current_config.as_ref(), archive_config.as_ref());
+    writeln!(
+        description,
+        "**Config:** {}",
+        if current_config == archive_config {
+            "unchanged"
+        } else if config_changes.is_empty() {
+            "reset to defaults"
+        } else {
+            &config_changes
+        }
+    )?;
+
+    writeln!(
+        description,
+        "**Rewards:** {} → {}{}",
+        current.rewards.len(),
+        archive.rewards.len(),
+        if current.rewards == archive.rewards {
+            " (unchanged)"
+        } else {
+            ""
+        }
+    )?;
+    writeln!(
+        description,
+        "**Server card:** {}",
+        if current.card == archive.card {
+            "unchanged"
+        } else {
+            "changed"
+        }
+    )?;
+    writeln!(
+        description,
+        "**Card layout:** {}",
+        match (&current.card_layout_template, &archive.card_layout_template) {
+            (current, archive) if current == archive => "unchanged",
+            (_, None) => "removed",
+            (None, Some(_)) => "added",
+            (Some(_), Some(_)) => "changed",
+        }
+    )?;
+    writeln!(
+        description,
+        "**Audit log:** {} → {} XP entries, {} → {} settings entries",
+        current.audit_logs.len(),
+        archive.audit_logs.len(),
+        current.settings_audit_logs.len(),
+        archive.settings_audit_logs.len()
+    )?;
+    writeln!(
+        description,
+        "**Cooldowns:** {} → {}",
+        current.cooldowns.len(),
+        archive.cooldowns.len()
+    )?;
+    for name in dropped_channels {
+        writeln!(
+            description,
+            "\n⚠️ The backup's {name} isn't in this server, so it won't be restored."
+        )?;
+    }
+    Ok(description)
+}
+
+pub fn confirm_restore(
+    state: SlashState,
+    respondable: Respondable,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    plan: RestorePlan,
+) -> String {
+    state.clone().spawn(async move {
+        let response = apply_restore(&state, guild_id, audit, plan)
+            .await
+            .unwrap_or_else(|source| {
+                error!(?source, "Failed to restore guild backup");
+                XpdInteractionData::with_embed_text(format!(
+                    "Failed to restore backup, nothing was changed: {source}"
+                ))
+            })
+            .ephemeral(true);
+        state.send_followup(response, respondable.token()).await;
+    });
+    "Restoring backup, check back soon!".to_string()
+}
+
+/// Replace all of a guild's data with an archive's, in one transaction.
+/// Audit log entries get new IDs, so references between them are remapped.
+async fn apply_restore(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    plan: RestorePlan,
+) -> Result<XpdInteractionData, Error> {
+    let RestorePlan { archive, filename } = plan;
+    let mut txn = state.db.begin().await?;
+
+    let previous_users = xpd_database::delete_levels_guild(txn.as_mut(), guild_id).await?;
+    let levels: Vec<(Id<UserMarker>, i64)> = archive
+        .levels
+        .iter()
+        .map(|user| (user.id, user.xp))
+        .collect();
+    xpd_database::bulk_set_xp(txn.as_mut(), guild_id, &levels).await?;
+
+    xpd_database::delete_guild_config(txn.as_mut(), guild_id).await?;
+    let config = match &archive.config {
+        Some(config) => {
+            let update = UpdateGuildConfig {
+                level_up_message: config
+                    .level_up_message
+                    .as_ref()
+                    .map(Interpolation::input_value),
+                level_up_channel: config.level_up_channel,
+                ping_users: config.ping_on_level_up,
+                max_xp_per_message: config.max_xp_per_message,
+                min_xp_per_message: config.min_xp_per_message,
+                message_cooldown: config.cooldown,
+                one_at_a_time: config.one_at_a_time,
+                log_channel: config.log_channel,
+            };
+            xpd_database::update_guild_config(txn.as_mut(), guild_id, update).await?
+        }
+        None => GuildConfig::default(),
+    };
+
+    xpd_database::delete_guild_rewards(txn.as_mut(), guild_id).await?;
+    for reward in &archive.rewards {
+        xpd_database::add_reward_role(txn.as_mut(), guild_id, reward.requirement, reward.id)
+            .await?;
+    }
+
+    xpd_database::delete_card_customizations(txn.as_mut(), guild_id.cast()).await?;
+    if let Some(card) = archive.card {
+        let background = card.decode_background()?;
+        xpd_database::update_card(txn.as_mut(), guild_id.cast(), &card.into()).await?;
+        if let Some(background) = background {
+            xpd_database::set_card_background(txn.as_mut(), guild_id.cast(), &background).await?;
+        }
+    }
+    if let Some(template) = &archive.card_layout_template {
+        xpd_database::set_guild_card_layout(txn.as_mut(), guild_id, template).await?;
+    } else {
+        xpd_database::delete_guild_card_layout(txn.as_mut(), guild_id).await?;
+    }
+
+    xpd_database::delete_audit_log_events_guild(txn.as_mut(), guild_id).await?;
+    let mut new_ids = HashMap::with_capacity(archive.audit_logs.len());
+    for entry in &archive.audit_logs {
+        let mut event = entry.event();
+        event.guild_id = guild_id;
+        event.reverts = entry.reverts.and_then(|old| new_ids.get(&old).copied());
+        let new_id = xpd_database::add_audit_log_event(txn.as_mut(), event).await?;
+        new_ids.insert(entry.id, new_id);
+    }
+
+    xpd_database::delete_guild_audit_events_guild(txn.as_mut(), guild_id).await?;
+    for entry in archive.settings_audit_logs {
+        let event = GuildAuditEvent {
+            guild_id,
+            ..entry.event()
+        };
+        xpd_database::add_guild_audit_event(txn.as_mut(), event).await?;
+    }
+
+    xpd_database::delete_guild_cooldowns(txn.as_mut(), guild_id).await?;
+    xpd_database::bulk_set_cooldowns(txn.as_mut(), guild_id, &archive.cooldowns).await?;
+
+    let before = json!({ "users": previous_users });
+    let after = json!({
+        "users": archive.levels.len(),
+        "filename": filename,
+        "created_at": archive.created_at,
+    });
+    let event = audit.guild_event(guild_id, GuildAuditKind::Restore, Some(before), Some(after));
+    let event_id = xpd_database::add_guild_audit_event(txn.as_mut(), event.clone()).await?;
+    txn.commit().await?;
+
+    state.update_config(guild_id, config).await;
+    state.invalidate_rewards(guild_id).await;
+    // The restored layout is compiled the next time a card needs it
+    state.svg.remove_layout(guild_id.get());
+    mirror_audit_events(state, guild_id, vec![LoggedEvent::Guild(event_id, event)]);
+
+    Ok(XpdInteractionData::with_embed_text(format!(
+        "Restored {} users, {} rewards and {} audit log entries from the backup!",
+        archive.levels.len(),
+        archive.rewards.len(),
+        archive.audit_logs.len()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+    use serde_json::json;
+    use twilight_model::id::Id;
+    use xpd_common::GuildConfig;
+    use xpd_rank_card::{Config, CUSTOM_LAYOUT};
+
+    use super::{
+        decompress_archive, drop_foreign_channels, encode_archive, parse_archive, validate_archive,
+        ArchivedCard, GuildArchive, ARCHIVE_FORMAT, ARCHIVE_VERSION, MAX_ARCHIVE_SIZE,
+    };
+    use crate::{manager::ImportUser, Error};
+
+    fn archive() -> GuildArchive {
+        GuildArchive {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            guild_id: Id::new(1),
+            created_at: 1_700_000_000,
+            levels: vec![ImportUser {
+                id: Id::new(2),
+                xp: 100,
+            }],
+            config: None,
+            rewards: Vec::new(),
+            card: None,
+            card_layout_template: None,
+            audit_logs: Vec::new(),
+            settings_audit_logs: Vec::new(),
+            cooldowns: Vec::new(),
+        }
+    }
+
+    fn card() -> ArchivedCard {
+        ArchivedCard {
+            username: Some("#FF8000".to_string()),
+            rank: None,
+            level: None,
+            border: None,
+            background: None,
+            progress_foreground: None,
+            progress_background: None,
+            background_xp_count: None,
+            foreground_xp_count: None,
+            font: None,
+            toy_image: None,
+            card_layout: CUSTOM_LAYOUT.to_string(),
+            animated_avatar: None,
+            background_image: None,
+        }
+    }
+
+    fn resources() -> Config {
+        serde_json::from_value(json!({
+            "defaults": { "card": "classic.svg" },
+            "fonts": [],
+            "toys": [],
+            "cards": [],
+        }))
+        .unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn small_archives_are_plain_json() {
+        let (file, compressed) = encode_archive(&archive()).unwrap();
+        assert!(!compressed);
+        let parsed = parse_archive(&decompress_archive(file).unwrap()).unwrap();
+        assert_eq!(parsed.levels.len(), 1);
+    }
+
+    #[test]
+    fn gzipped_archives_restore() {
+        let file = gzip(&serde_json::to_vec(&archive()).unwrap());
+        let parsed = parse_archive(&decompress_archive(file).unwrap()).unwrap();
+        assert_eq!(parsed.levels[0].xp, 100);
+    }
+
+    #[test]
+    fn rejects_huge_decompressed_archives() {
+        let file = gzip(&vec![b' '; MAX_ARCHIVE_SIZE + 1]);
+        assert!(matches!(
+            decompress_archive(file),
+            Err(Error::InvalidBackup(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_card_colors() {
+        let mut archive = archive();
+        archive.card = Some(card());
+        assert!(validate_archive(&archive, &resources()).is_ok());
+        archive.card = Some(ArchivedCard {
+            border: Some("notacolor".to_string()),
+            ..card()
+        });
+        assert!(matches!(
+            validate_archive(&archive, &resources()),
+            Err(Error::InvalidBackup(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_card_backgrounds() {
+        let mut archive = archive();
+        for background in ["not base64!", "aGVsbG8="] {
+            archive.card = Some(ArchivedCard {
+                background_image: Some(background.to_string()),
+                ..card()
+            });
+            assert!(matches!(
+                validate_archive(&archive, &resources()),
+                Err(Error::InvalidBackup(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_card_items() {
+        let mut archive = archive();
+        archive.card = Some(ArchivedCard {
+            font: Some("Comic Sans".to_string()),
+            ..card()
+        });
+        assert!(validate_archive(&archive, &resources()).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_cooldowns() {
+        let mut archive = archive();
+        archive.config = Some(GuildConfig {
+            cooldown: Some(-5),
+            ..GuildConfig::default()
+        });
+        assert!(matches!(
+            validate_archive(&archive, &resources()),
+            Err(Error::InvalidGuildConfig(_))
+        ));
+    }
+
+    #[test]
+    fn foreign_channels_are_dropped() {
+        let mut archive = archive();
+        archive.config = Some(GuildConfig {
+            level_up_channel: Some(Id::new(10)),
+            log_channel: Some(Id::new(20)),
+            ..GuildConfig::default()
+        });
+        let dropped = drop_foreign_channels(&mut archive, |channel| channel == Id::new(10));
+        assert_eq!(dropped, ["log channel"]);
+        let config = archive.config.unwrap();
+        assert_eq!(config.level_up_channel, Some(Id::new(10)));
+        assert_eq!(config.log_channel, None);
+    }
+}
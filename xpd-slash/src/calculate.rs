@@ -0,0 +1,264 @@
+use std::fmt::Write;
+
+use twilight_model::{
+    channel::message::MessageFlags,
+    http::interaction::InteractionResponseType,
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+use xpd_common::{
+    DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MESSAGE_COOLDOWN, DEFAULT_MIN_XP_PER_MESSAGE,
+};
+use xpd_slash_defs::levels::{LevelsCommand, LevelsCommandCalculate};
+
+use crate::{response::XpdInteractionResponse, Error, SlashState, XpdInteractionData};
+
+/// Rows on the curve chart, not counting the rows for the invoker and their target
+const CHART_ROWS: u64 = 10;
+/// Characters in the longest bar of the curve chart
+const CHART_WIDTH: usize = 24;
+
+pub async fn process_levels(
+    command: LevelsCommand,
+    guild_id: Id<GuildMarker>,
+    invoker: Id<UserMarker>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    match command {
+        LevelsCommand::Calculate(calculate) => {
+            calculate_level(calculate, guild_id, invoker, state).await
+        }
+    }
+}
+
+async fn calculate_level(
+    command: LevelsCommandCalculate,
+    guild_id: Id<GuildMarker>,
+    invoker: Id<UserMarker>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let flags = if command.show_off.is_some_and(|v| v) {
+        MessageFlags::empty()
+    } else {
+        MessageFlags::EPHEMERAL
+    };
+    let user_stats = state.get_user_stats(invoker, guild_id).await?;
+    let config = xpd_database::guild_config(&state.db, guild_id)
+        .await?
+        .unwrap_or_default();
+    let current = mee6::LevelInfo::new(u64::try_from(user_stats.xp).unwrap_or(0));
+
+    let mut description = String::new();
+    let (target_level, target_xp) = match (command.level, command.xp) {
+        (Some(_), Some(_)) => return Err(Error::LevelAndXp),
+        (None, Some(xp)) => {
+            let target = mee6::LevelInfo::new(u64::try_from(xp).unwrap_or(0));
+            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+            let percentage = (target.percentage() * 100.0).round() as u64;
+            writeln!(
+                description,
+                "**{} XP** is **level {}**, {percentage}% of the way to level {}.",
+                target.xp(),
+                target.level(),
+                target.level() + 1
+            )?;
+            (target.level(), target.xp())
+        }
+        (level, None) => {
+            let level = level.map_or_else(
+                || current.level() + 1,
+                |level| u64::try_from(level).unwrap_or(0),
+            );
+            let xp = mee6::xp_needed_for_level(level);
+            writeln!(description, "**Level {level}** takes **{xp} XP** in total.")?;
+            (level, xp)
+        }
+    };
+
+    let remaining = target_xp.saturating_sub(current.xp());
+    if remaining == 0 {
+        writeln!(
+            description,
+            "You have {} XP (level {}), so you're already there!",
+            current.xp(),
+            current.level()
+        )?;
+    } else {
+        writeln!(
+            description,
+            "You have **{} XP** (level {}), so you need **{remaining} XP** more.",
+            current.xp(),
+            current.level()
+        )?;
+        let min_xp = config
+            .min_xp_per_message
+            .unwrap_or(DEFAULT_MIN_XP_PER_MESSAGE);
+        let max_xp = config
+            .max_xp_per_message
+            .unwrap_or(DEFAULT_MAX_XP_PER_MESSAGE);
+        let cooldown =
+            u64::try_from(config.cooldown.unwrap_or(DEFAULT_MESSAGE_COOLDOWN)).unwrap_or(0);
+        match messages_to_catch_up(remaining, average_xp_per_message(min_xp, max_xp)) {
+            Some(messages) => {
+                let xp_range = if min_xp == max_xp {
+                    min_xp.to_string()
+                } else {
+                    format!("{min_xp}-{max_xp}")
+                };
+                write!(
+                    description,
+                    "That's about **{messages} messages** at {xp_range} XP each"
+                )?;
+                if cooldown == 0 {
+                    writeln!(description, ".")?;
+                } else {
+                    writeln!(
+                        description,
+                        ", which takes at least **{}** with the {cooldown} second cooldown.",
+                        format_duration(messages.saturating_mul(cooldown))
+                    )?;
+                }
+            }
+            None => writeln!(
+                description,
+                "Messages don't give XP in this server, so chatting won't get you there."
+            )?,
+        }
+    }
+    write!(
+        description,
+        "```\n{}```",
+        curve_chart(current.level(), target_level)
+    )?;
+
+    let embed = EmbedBuilder::new().description(description).build();
+    Ok(XpdInteractionData::new()
+        .embeds([embed])
+        .flags(flags)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+/// Messages give a random amount of XP between the guild's minimum and maximum
+pub fn average_xp_per_message(min: i16, max: i16) -> f64 {
+    f64::midpoint(f64::from(min), f64::from(max))
+}
+
+/// How many messages earning XP it takes to close `xp_gap`, or `None` if messages
+/// don't earn any XP.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn messages_to_catch_up(xp_gap: u64, average_xp: f64) -> Option<u64> {
+    (average_xp > 0.0).then(|| (xp_gap as f64 / average_xp).ceil() as u64)
+}
+
+/// The two largest units of a duration, like "3 days, 4 hours"
+fn format_duration(seconds: u64) -> String {
+    const UNITS: [(u64, &str); 4] = [
+        (86400, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+    let Some(largest) = UNITS.iter().position(|(size, _)| seconds >= *size) else {
+        return "0 seconds".to_string();
+    };
+    let mut left = seconds;
+    UNITS[largest..]
+        .iter()
+        .take(2)
+        .filter_map(|(size, name)| {
+            let count = left / size;
+            left %= size;
+            (count > 0).then(|| format!("{count} {name}{}", if count == 1 { "" } else { "s" }))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A bar chart of the total XP each level takes, from level 1 up past the higher of
+/// `you` and `target`, with rows for both of them.
+fn curve_chart(you: u64, target: u64) -> String {
+    let top = you.max(target).max(CHART_ROWS);
+    let step = top.div_ceil(CHART_ROWS);
+    let mut levels: Vec<u64> = (1..=CHART_ROWS).map(|row| row * step).collect();
+    levels.extend([you, target].into_iter().filter(|level| *level > 0));
+    levels.sort_unstable();
+    levels.dedup();
+    let highest = levels.last().copied().unwrap_or(1);
+    let max_xp = mee6::xp_needed_for_level(highest).max(1);
+    let level_width = highest.to_string().len();
+
+    let mut chart = String::new();
+    for level in levels {
+        let xp = mee6::xp_needed_for_level(level);
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let filled = ((xp as f64 / max_xp as f64) * CHART_WIDTH as f64).round() as usize;
+        let bar = "█".repeat(filled.max(1));
+        let marker = match (level == you, level == target) {
+            (true, true) => " ◀ you, target",
+            (true, false) => " ◀ you",
+            (false, true) => " ◀ target",
+            (false, false) => "",
+        };
+        // Writing to a String can't fail
+        let _ = writeln!(
+            chart,
+            "{level:>level_width$} │{bar:<CHART_WIDTH$} {xp} XP{marker}"
+        );
+    }
+    chart
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{average_xp_per_message, curve_chart, format_duration, messages_to_catch_up};
+
+    #[test]
+    fn catch_up_estimate() {
+        let average = average_xp_per_message(15, 25);
+        assert!((average - 20.0).abs() < f64::EPSILON);
+        assert_eq!(messages_to_catch_up(0, average), Some(0));
+        assert_eq!(messages_to_catch_up(20, average), Some(1));
+        assert_eq!(messages_to_catch_up(21, average), Some(2));
+        assert_eq!(messages_to_catch_up(100, 0.0), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(0), "0 seconds");
+        assert_eq!(format_duration(61), "1 minute, 1 second");
+        assert_eq!(
+            format_duration(3 * 86400 + 4 * 3600 + 59),
+            "3 days, 4 hours"
+        );
+        assert_eq!(format_duration(86400 + 30), "1 day");
+    }
+
+    #[test]
+    fn chart_marks_you_and_target() {
+        let chart = curve_chart(7, 25);
+        let lines: Vec<&str> = chart.lines().collect();
+        // Every third level up to 30, plus 7 and 25
+        assert_eq!(lines.len(), 12);
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with(" 7 │") && line.ends_with("◀ you")));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("25 │") && line.ends_with("◀ target")));
+        assert!(lines
+            .last()
+            .unwrap()
+            .starts_with(&format!("30 │{}", "█".repeat(24))));
+    }
+}
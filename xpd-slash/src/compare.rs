@@ -0,0 +1,163 @@
+use tokio::try_join;
+use twilight_model::{
+    channel::message::MessageFlags,
+    http::{attachment::Attachment, interaction::InteractionResponseType},
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+use xpd_common::{
+    DisplayName, MemberDisplayInfo, DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE,
+};
+use xpd_rank_card::{CompareContext, LeaderboardRow};
+
+use crate::{
+    calculate::{average_xp_per_message, messages_to_catch_up},
+    levels::{get_avatar, get_customizations_fields, AvatarReference},
+    response::XpdInteractionResponse,
+    Error, SlashState, UserStats, XpdInteractionData,
+};
+
+pub async fn compare_levels(
+    guild_id: Id<GuildMarker>,
+    left: MemberDisplayInfo,
+    right: MemberDisplayInfo,
+    invoker: Id<UserMarker>,
+    showoff: Option<bool>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let flags = if showoff.is_some_and(|v| v) {
+        MessageFlags::empty()
+    } else {
+        MessageFlags::EPHEMERAL
+    };
+    let (left_stats, right_stats) = try_join!(
+        state.get_user_stats(left.id, guild_id),
+        state.get_user_stats(right.id, guild_id)
+    )?;
+    let problem = if left.id == right.id {
+        Some("Pick someone else to compare them with!".to_string())
+    } else if left.bot || right.bot {
+        Some("Bots aren't ranked, that would be silly!".to_string())
+    } else {
+        [(&left, &left_stats), (&right, &right_stats)]
+            .into_iter()
+            .find(|(_, stats)| stats.xp == 0)
+            .map(|(member, _)| {
+                if member.id == invoker {
+                    "You aren't ranked yet, because you haven't sent any messages!".to_string()
+                } else {
+                    format!(
+                        "{} isn't ranked yet, because they haven't sent any messages!",
+                        member.display_name()
+                    )
+                }
+            })
+    };
+    if let Some(problem) = problem {
+        let embed = EmbedBuilder::new().description(problem).build();
+        return Ok(XpdInteractionData::new()
+            .embeds([embed])
+            .flags(flags)
+            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource));
+    }
+    let card = gen_compare_card(
+        &state,
+        guild_id,
+        invoker,
+        (left, left_stats),
+        (right, right_stats),
+    )
+    .await?;
+    Ok(XpdInteractionData::new()
+        .attachments([card])
+        .flags(flags)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+/// Drawn with the look of the invoker's own card
+async fn gen_compare_card(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    invoker: Id<UserMarker>,
+    (left, left_stats): (MemberDisplayInfo, UserStats),
+    (right, right_stats): (MemberDisplayInfo, UserStats),
+) -> Result<Attachment, Error> {
+    let (customizations, left_avatar, right_avatar, config) = try_join!(
+        get_customizations_fields(state.clone(), invoker, Some(guild_id)),
+        get_avatar(state, avatar_reference(&left, guild_id)),
+        get_avatar(state, avatar_reference(&right, guild_id)),
+        async { Ok::<_, Error>(xpd_database::guild_config(&state.db, guild_id).await?) }
+    )?;
+    let config = config.unwrap_or_default();
+    let average_xp = average_xp_per_message(
+        config
+            .min_xp_per_message
+            .unwrap_or(DEFAULT_MIN_XP_PER_MESSAGE),
+        config
+            .max_xp_per_message
+            .unwrap_or(DEFAULT_MAX_XP_PER_MESSAGE),
+    );
+    let left_row = compare_row(&left, &left_stats, left_avatar);
+    let right_row = compare_row(&right, &right_stats, right_avatar);
+    let xp_gap = left_row.current.abs_diff(right_row.current);
+    let (leader, trailer) = if left_row.current >= right_row.current {
+        (&left_row, &right_row)
+    } else {
+        (&right_row, &left_row)
+    };
+    let description = if xp_gap == 0 {
+        format!(
+            "{} and {} are tied at {} XP.",
+            left_row.name, right_row.name, left_row.current
+        )
+    } else {
+        format!(
+            "{} (level {}, rank #{}) is {xp_gap} XP ahead of {} (level {}, rank #{}).",
+            leader.name, leader.level, leader.rank, trailer.name, trailer.level, trailer.rank
+        )
+    };
+    let context = CompareContext {
+        xp_gap,
+        level_gap: left_row.level.abs_diff(right_row.level),
+        rank_gap: left_row.rank.abs_diff(right_row.rank),
+        messages_to_catch_up: messages_to_catch_up(xp_gap, average_xp),
+        left: left_row,
+        right: right_row,
+        customizations,
+    };
+    let format = state.card_formats.text;
+    let file = state.svg.render_compare(context, format).await?;
+    Ok(Attachment {
+        description: Some(description),
+        file,
+        filename: format!("compare.{}", format.extension()),
+        id: 0,
+    })
+}
+
+fn avatar_reference(member: &MemberDisplayInfo, guild_id: Id<GuildMarker>) -> AvatarReference {
+    AvatarReference::new(
+        member.id,
+        member.avatar,
+        Some(guild_id),
+        member.local_avatar,
+    )
+}
+
+fn compare_row(member: &MemberDisplayInfo, stats: &UserStats, avatar: String) -> LeaderboardRow {
+    let level_info = mee6::LevelInfo::new(u64::try_from(stats.xp).unwrap_or(0));
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    let percentage = (level_info.percentage() * 100.0).round() as u64;
+    LeaderboardRow {
+        rank: stats.rank,
+        name: member.display_name().to_string(),
+        level: level_info.level(),
+        percentage,
+        current: level_info.xp(),
+        needed: mee6::xp_needed_for_level(level_info.level() + 1),
+        avatar,
+    }
+}
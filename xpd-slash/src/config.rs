@@ -1,4 +1,5 @@
 use simpleinterpolation::Interpolation;
+use sqlx::PgConnection;
 use twilight_model::{
     channel::{message::MessageFlags, ChannelType},
     http::interaction::InteractionResponseType,
@@ -8,27 +9,37 @@ use twilight_model::{
     },
 };
 use xpd_common::{
-    GuildConfig, DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE, TEMPLATE_VARIABLES,
+    GuildAuditKind, GuildConfig, DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE,
+    TEMPLATE_VARIABLES,
 };
 use xpd_database::UpdateGuildConfig;
-use xpd_slash_defs::config::{ConfigCommand, ConfigCommandLevels, ConfigCommandRewards};
+use xpd_slash_defs::config::{
+    ConfigCommand, ConfigCommandLevels, ConfigCommandLogs, ConfigCommandRewards,
+};
 use xpd_util::CanAddRole;
 
-use crate::{response::XpdInteractionResponse, Error, SlashState, XpdInteractionData};
+use crate::{
+    audit::AuditData,
+    log_channel::{mirror_audit_events, LoggedEvent},
+    response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData,
+};
 
 pub async fn process_config(
     command: ConfigCommand,
     guild: Id<GuildMarker>,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
     match command {
-        ConfigCommand::Reset(_) => reset_config(state, guild).await,
+        ConfigCommand::Reset(_) => reset_config(state, guild, audit).await,
         ConfigCommand::Get(_) => xpd_database::guild_config(&state.db, guild)
             .await
             .map(|v| v.unwrap_or_default().to_string())
             .map_err(Into::into),
-        ConfigCommand::Rewards(r) => process_rewards_config(state, guild, r).await,
-        ConfigCommand::Levels(l) => process_levels_config(state, guild, l).await,
+        ConfigCommand::Rewards(r) => process_rewards_config(state, guild, r, audit).await,
+        ConfigCommand::Levels(l) => process_levels_config(state, guild, l, audit).await,
+        ConfigCommand::Logs(l) => process_logs_config(state, guild, l, audit).await,
         ConfigCommand::PermsCheckup(_) => process_perm_checkup(state, guild).await,
     }
     .map(|s| {
@@ -42,12 +53,17 @@ async fn process_rewards_config(
     state: SlashState,
     guild_id: Id<GuildMarker>,
     options: ConfigCommandRewards,
+    audit: AuditData,
 ) -> Result<String, Error> {
     let new_cfg = UpdateGuildConfig::new().one_at_a_time(options.one_at_a_time);
     let mut update_txn = state.db.begin().await?;
+    let old_config = xpd_database::guild_config(update_txn.as_mut(), guild_id).await?;
     let config = xpd_database::update_guild_config(&mut update_txn, guild_id, new_cfg).await?;
     validate_config(&config)?;
+    let logged =
+        log_config_change(&mut update_txn, guild_id, audit, old_config, Some(&config)).await?;
     update_txn.commit().await?;
+    mirror_audit_events(&state, guild_id, vec![logged]);
     state.update_config(guild_id, config).await;
     Ok("Updated rewards config!".to_string())
 }
@@ -56,17 +72,13 @@ async fn process_levels_config(
     state: SlashState,
     guild_id: Id<GuildMarker>,
     options: ConfigCommandLevels,
+    audit: AuditData,
 ) -> Result<String, Error> {
     if let Some(interp_template) = options.level_up_message.as_ref() {
         if interp_template.len() > 512 {
             return Err(Error::LevelUpMessageTooLong);
         }
-        let interp = Interpolation::new(interp_template.clone())?;
-        for item in interp.variables_used() {
-            if !TEMPLATE_VARIABLES.contains(&item) {
-                return Err(Error::UnknownInterpolationVariable(item.to_string()));
-            }
-        }
+        check_template_variables(&Interpolation::new(interp_template.clone())?)?;
     }
 
     if options
@@ -89,28 +101,128 @@ async fn process_levels_config(
         min_xp_per_message,
         message_cooldown,
         one_at_a_time: None,
+        log_channel: None,
     };
     let mut validate_txn = state.db.begin().await?;
+    let old_config = xpd_database::guild_config(validate_txn.as_mut(), guild_id).await?;
     let config = xpd_database::update_guild_config(&mut validate_txn, guild_id, new_cfg).await?;
     validate_config(&config)?;
+    let logged = log_config_change(
+        &mut validate_txn,
+        guild_id,
+        audit,
+        old_config,
+        Some(&config),
+    )
+    .await?;
     validate_txn.commit().await?;
+    mirror_audit_events(&state, guild_id, vec![logged]);
     let msg = config.to_string();
     state.update_config(guild_id, config).await;
 
     Ok(msg)
 }
 
+async fn process_logs_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandLogs,
+    audit: AuditData,
+) -> Result<String, Error> {
+    if options
+        .channel
+        .as_ref()
+        .is_some_and(|v| !matches!(v.kind, ChannelType::GuildText))
+    {
+        return Err(Error::LogChannelMustBeText);
+    }
+
+    let mut txn = state.db.begin().await?;
+    let old_config = xpd_database::guild_config(txn.as_mut(), guild_id).await?;
+    let config = if options.disable == Some(true) {
+        xpd_database::clear_guild_log_channel(txn.as_mut(), guild_id)
+            .await?
+            .unwrap_or_default()
+    } else if let Some(channel) = options.channel {
+        let new_cfg = UpdateGuildConfig::new().log_channel(Some(channel.id));
+        xpd_database::update_guild_config(&mut txn, guild_id, new_cfg).await?
+    } else {
+        return Err(Error::WrongArgumentCount(
+            "`/config logs` requires a channel, or disable set to true.",
+        ));
+    };
+    let logged = log_config_change(&mut txn, guild_id, audit, old_config, Some(&config)).await?;
+    txn.commit().await?;
+    // post after committing, so the log channel change itself is mirrored to the new channel
+    mirror_audit_events(&state, guild_id, vec![logged]);
+
+    let can_post = config
+        .log_channel
+        .map(|channel| xpd_util::can_create_message(&state.cache, state.bot_id, channel))
+        .transpose()?;
+    let msg = match (config.log_channel, can_post) {
+        (Some(channel), Some(false)) => format!(
+            "Audit log events will be posted in <#{channel}>, \
+            but I can't send messages there yet!"
+        ),
+        (Some(channel), _) => format!("Audit log events will be posted in <#{channel}>."),
+        (None, _) => "Audit log events will no longer be posted.".to_string(),
+    };
+    state.update_config(guild_id, config).await;
+    Ok(msg)
+}
+
 fn safecast_to_i16(ou16: Option<i64>) -> Result<Option<i16>, Error> {
     ou16.map(TryInto::try_into).transpose().map_err(Into::into)
 }
 
-async fn reset_config(state: SlashState, guild_id: Id<GuildMarker>) -> Result<String, Error> {
-    xpd_database::delete_guild_config(&state.db, guild_id).await?;
+async fn reset_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+) -> Result<String, Error> {
+    let mut txn = state.db.begin().await?;
+    let old_config = xpd_database::guild_config(txn.as_mut(), guild_id).await?;
+    xpd_database::delete_guild_config(txn.as_mut(), guild_id).await?;
+    let logged = log_config_change(&mut txn, guild_id, audit, old_config, None).await?;
+    txn.commit().await?;
+    mirror_audit_events(&state, guild_id, vec![logged]);
     state.update_config(guild_id, GuildConfig::default()).await;
     Ok("Reset guild reward config, but NOT rewards themselves!".to_string())
 }
 
-fn validate_config(config: &GuildConfig) -> Result<(), GuildConfigErrorReport> {
+async fn log_config_change(
+    conn: &mut PgConnection,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    old: Option<GuildConfig>,
+    new: Option<&GuildConfig>,
+) -> Result<LoggedEvent, Error> {
+    let kind = if new.is_some() {
+        GuildAuditKind::ConfigUpdate
+    } else {
+        GuildAuditKind::ConfigReset
+    };
+    let before = old.map(serde_json::to_value).transpose()?;
+    let after = new.map(serde_json::to_value).transpose()?;
+    let event = audit.guild_event(guild_id, kind, before, after);
+    let id = xpd_database::add_guild_audit_event(conn, event.clone()).await?;
+    Ok(LoggedEvent::Guild(id, event))
+}
+
+pub fn check_template_variables(interp: &Interpolation) -> Result<(), Error> {
+    for item in interp.variables_used() {
+        if !TEMPLATE_VARIABLES.contains(&item) {
+            return Err(Error::UnknownInterpolationVariable(item.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Longest message cooldown, in seconds. Matches the limit on the `/config levels` option.
+pub const MAX_MESSAGE_COOLDOWN: i16 = 28800;
+
+pub fn validate_config(config: &GuildConfig) -> Result<(), GuildConfigErrorReport> {
     let max_xp_per_msg = config
         .max_xp_per_message
         .unwrap_or(DEFAULT_MAX_XP_PER_MESSAGE);
@@ -123,6 +235,15 @@ fn validate_config(config: &GuildConfig) -> Result<(), GuildConfigErrorReport> {
             max: max_xp_per_msg,
         });
     }
+    if min_xp_per_msg.is_negative() {
+        return Err(GuildConfigErrorReport::NegativeXp(min_xp_per_msg));
+    }
+    if let Some(cooldown) = config
+        .cooldown
+        .filter(|cooldown| !(0..=MAX_MESSAGE_COOLDOWN).contains(cooldown))
+    {
+        return Err(GuildConfigErrorReport::CooldownOutOfRange(cooldown));
+    }
     Ok(())
 }
 
@@ -130,6 +251,12 @@ fn validate_config(config: &GuildConfig) -> Result<(), GuildConfigErrorReport> {
 pub enum GuildConfigErrorReport {
     #[error("The selected minimum XP value of {min} is more than the selected maximum of {max}")]
     MinXpIsMoreThanMax { min: i16, max: i16 },
+    #[error("The selected XP per message of {0} is negative")]
+    NegativeXp(i16),
+    #[error(
+        "The selected message cooldown of {0} seconds must be between 0 and {MAX_MESSAGE_COOLDOWN}"
+    )]
+    CooldownOutOfRange(i16),
 }
 
 async fn process_perm_checkup(
@@ -143,6 +270,10 @@ async fn process_perm_checkup(
         .level_up_channel
         .map(|level_up| xpd_util::can_create_message(&state.cache, state.bot_id, level_up))
         .transpose()?;
+    let can_msg_in_logs = config
+        .log_channel
+        .map(|logs| xpd_util::can_create_message(&state.cache, state.bot_id, logs))
+        .transpose()?;
 
     let rewards: Vec<Id<RoleMarker>> = xpd_database::guild_rewards(&state.db, guild_id)
         .await?
@@ -152,6 +283,7 @@ async fn process_perm_checkup(
 
     let can_add_roles = xpd_util::can_manage_roles(&state.cache, state.bot_id, guild_id, &rewards)?;
     let good_msg_state = EmojiFormatBool(can_msg_in_level_up != Some(false));
+    let good_logs_state = EmojiFormatBool(can_msg_in_logs != Some(false));
 
     let can_add_roles = match can_add_roles {
         CanAddRole::Yes => "✅",
@@ -162,7 +294,8 @@ async fn process_perm_checkup(
         CanAddRole::RoleIsManaged => "⚠️ That role is managed by another bot.",
     };
     Ok(format!(
-        "Can add roles: {can_add_roles}\nCan message in level up channel: {good_msg_state}"
+        "Can add roles: {can_add_roles}\nCan message in level up channel: {good_msg_state}\n\
+        Can message in audit log channel: {good_logs_state}"
     ))
 }
 
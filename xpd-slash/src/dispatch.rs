@@ -1,4 +1,4 @@
-use twilight_interactions::command::CommandModel;
+use twilight_interactions::command::{CommandModel, ResolvedUser};
 use twilight_model::{
     application::{
         command::CommandType,
@@ -19,14 +19,15 @@ use xpd_slash_defs::{
     config::ConfigCommand,
     experience::XpCommand,
     gdpr::GdprCommand,
-    levels::{LeaderboardCommand, RankCommand},
+    levels::{LeaderboardCommand, LevelsCommand, RankCommand},
     manage::ManageCommand,
     rewards::RewardsCommand,
 };
 
 use crate::{
-    experience::XpAuditData,
+    audit::{AuditData, AUDIT_PAGE_PREFIX, GUILD_AUDIT_PAGE_PREFIX},
     leaderboard::{process_message_component, process_modal_submit},
+    pending::PENDING_ACTION_PREFIX,
     response::XpdInteractionResponse,
     Error, SlashState,
 };
@@ -61,29 +62,58 @@ pub async fn process(
         return Err(Error::NoInteractionData);
     };
 
+    let invoker: MemberDisplayInfo = match interaction.member {
+        Some(member) => MemberDisplayInfo::from_partial_member(member),
+        None => interaction.user.map(MemberDisplayInfo::from),
+    }
+    .ok_or(Error::NoInvoker)?;
+
+    let guild_id = interaction.guild_id;
+
     if matches!(
         interaction.kind,
         InteractionType::ApplicationCommandAutocomplete
     ) {
         return if let InteractionData::ApplicationCommand(data) = data {
-            Ok(crate::autocomplete::autocomplete(&state, *data))
+            Ok(crate::autocomplete::autocomplete(&state, *data, invoker.id, guild_id).await)
         } else {
             Err(Error::WrongInteractionData)
         };
     }
-
-    let invoker: MemberDisplayInfo = match interaction.member {
-        Some(member) => MemberDisplayInfo::from_partial_member(member),
-        None => interaction.user.map(MemberDisplayInfo::from),
-    }
-    .ok_or(Error::NoInvoker)?;
-
-    let guild_id = interaction.guild_id;
     match data {
         InteractionData::ApplicationCommand(cmd) => {
             process_app_cmd(state, *cmd, respondable, invoker, guild_id).await
         }
         InteractionData::MessageComponent(mcd) => {
+            if mcd.custom_id.starts_with(AUDIT_PAGE_PREFIX) {
+                return crate::audit::process_audit_log_page(
+                    &mcd.custom_id,
+                    guild_id.ok_or(Error::NoGuildId)?,
+                    state,
+                )
+                .await;
+            }
+            if mcd.custom_id.starts_with(GUILD_AUDIT_PAGE_PREFIX) {
+                return crate::audit::process_guild_audit_log_page(
+                    &mcd.custom_id,
+                    guild_id.ok_or(Error::NoGuildId)?,
+                    state,
+                )
+                .await;
+            }
+            if mcd.custom_id.starts_with(PENDING_ACTION_PREFIX) {
+                let audit = AuditData {
+                    interaction: respondable.id(),
+                    invoker: invoker.id,
+                };
+                return crate::pending::process_pending_component(
+                    &mcd.custom_id,
+                    guild_id.ok_or(Error::NoGuildId)?,
+                    respondable,
+                    audit,
+                    state,
+                );
+            }
             let Some(original_msg) = interaction.message else {
                 return Err(Error::NoInteractionMessage);
             };
@@ -133,45 +163,35 @@ async fn process_slash_cmd(
     invoker: MemberDisplayInfo,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
+    let audit = AuditData {
+        interaction: respondable.id(),
+        invoker: invoker.id,
+    };
     match data.name.as_str() {
         "help" => Ok(crate::help::help()),
         "rank" => {
             let data = RankCommand::from_interaction(data.into())?;
-            let target = data.user.map_or_else(
-                || invoker.clone(),
-                |ru| {
-                    let (nick, local_avatar) = ru
-                        .member
-                        .map_or_else(|| (None, None), |im| (im.nick, im.avatar));
-                    MemberDisplayInfo {
-                        id: ru.resolved.id,
-                        name: ru.resolved.name,
-                        global_name: ru.resolved.global_name,
-                        nick,
-                        avatar: ru.resolved.avatar,
-                        local_avatar,
-                        bot: ru.resolved.bot,
-                    }
-                },
-            );
-            crate::levels::get_level(
-                guild_id.ok_or(Error::NoGuildId)?,
-                target,
-                invoker.id,
-                data.show_off,
-                state,
-            )
-            .await
+            let guild_id = guild_id.ok_or(Error::NoGuildId)?;
+            let target = data.user.map_or_else(|| invoker.clone(), resolved_member);
+            if let Some(other) = data.compare {
+                return crate::compare::compare_levels(
+                    guild_id,
+                    target,
+                    resolved_member(other),
+                    invoker.id,
+                    data.show_off,
+                    state,
+                )
+                .await;
+            }
+            crate::levels::get_level(guild_id, target, invoker.id, data.show_off, state).await
         }
         "xp" => {
             crate::experience::process_xp(
                 XpCommand::from_interaction(data.into())?,
                 state,
                 guild_id.ok_or(Error::NoGuildId)?,
-                XpAuditData {
-                    interaction: respondable.id,
-                    invoker: invoker.id,
-                },
+                audit,
             )
             .await
         }
@@ -179,6 +199,7 @@ async fn process_slash_cmd(
             crate::config::process_config(
                 ConfigCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
+                audit,
                 state,
             )
             .await
@@ -196,6 +217,7 @@ async fn process_slash_cmd(
             crate::audit::process_audit_logs(
                 AuditLogCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
+                audit,
                 state,
             )
             .await
@@ -213,6 +235,7 @@ async fn process_slash_cmd(
             GuildCardCommand::from_interaction(data.into())?,
             &state,
             guild_id.ok_or(Error::NoGuildId)?,
+            audit,
         )
         .await?),
         "gdpr" => {
@@ -227,11 +250,21 @@ async fn process_slash_cmd(
             )
             .await
         }
+        "levels" => {
+            crate::calculate::process_levels(
+                LevelsCommand::from_interaction(data.into())?,
+                guild_id.ok_or(Error::NoGuildId)?,
+                invoker.id,
+                state,
+            )
+            .await
+        }
         "manage" => {
             crate::manager::process_manage(
                 ManageCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
                 respondable,
+                audit,
                 state,
             )
             .await
@@ -240,6 +273,7 @@ async fn process_slash_cmd(
             crate::rewards::process_rewards(
                 RewardsCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
+                audit,
                 state,
             )
             .await
@@ -290,3 +324,18 @@ async fn process_msg_cmd(
 
     crate::levels::get_level(guild_id, target, invoker.id, DEFAULT_SHOWOFF, state).await
 }
+
+fn resolved_member(user: ResolvedUser) -> MemberDisplayInfo {
+    let (nick, local_avatar) = user
+        .member
+        .map_or_else(|| (None, None), |member| (member.nick, member.avatar));
+    MemberDisplayInfo {
+        id: user.resolved.id,
+        name: user.resolved.name,
+        global_name: user.resolved.global_name,
+        nick,
+        avatar: user.resolved.avatar,
+        local_avatar,
+        bot: user.resolved.bot,
+    }
+}
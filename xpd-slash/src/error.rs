@@ -12,7 +12,7 @@ pub enum Error {
     ImageSourceAttachment(
         #[from] twilight_util::builder::embed::image_source::ImageSourceAttachmentError,
     ),
-    #[error("SVG renderer encountered an error!")]
+    #[error("{}", image_generator_message(.0))]
     ImageGenerator(#[from] xpd_rank_card::Error),
     #[error("Database encountered an error")]
     Database(#[from] xpd_database::Error),
@@ -69,6 +69,22 @@ pub enum Error {
     #[error("That file is too big to import automatically. Please email valk@randomairborne.dev or [join our support server](https://discord.com/invite/KWkPYxqNKe) to set up imports for your server."
     )]
     ImportFileTooBig,
+    #[error("That export is too big to upload, even compressed. Try filtering by level or role.")]
+    ExportFileTooBig,
+    #[error("That animated avatar is too big to put on a card.")]
+    AnimatedAvatarTooBig,
+    #[error("That background image is too big. Background images can be at most {} MiB.", crate::manage_card::MAX_BACKGROUND_BYTES / (1024 * 1024))]
+    BackgroundImageTooBig,
+    #[error("That background image is invalid: {0}")]
+    InvalidBackgroundImage(String),
+    #[error("That layout is too big. Layouts can be at most {} KiB.", xpd_rank_card::MAX_LAYOUT_BYTES / 1024)]
+    LayoutTooBig,
+    #[error("That layout is invalid: {0}")]
+    InvalidLayout(String),
+    #[error("This server has no uploaded card layout.")]
+    NoCustomLayout,
+    #[error("Card resources failed to reload, so the old ones are still in use: {0}")]
+    CardReload(#[from] xpd_rank_card::NewSvgStateError),
     #[error("This page does not exist!")]
     NoUsersForPage,
     #[error("This page does not exist!")]
@@ -89,12 +105,23 @@ pub enum Error {
     LevelUpMessageTooLong,
     #[error("Level up channel must be a text channel!")]
     LevelUpChannelMustBeText,
+    #[error("Audit log channel must be a text channel!")]
+    LogChannelMustBeText,
     #[error("That card does not exist!")]
     UnknownCard,
     #[error("That toy does not exist!")]
     UnknownToy,
     #[error("That font does not exist!")]
     UnknownFont,
+    #[error("That theme does not exist!")]
+    UnknownTheme,
+    #[error(
+        "You can save at most {} themes. Delete one with `delete-theme` first.",
+        crate::manage_card::MAX_SAVED_THEMES
+    )]
+    TooManyThemes,
+    #[error("Pick a level or an amount of XP, not both.")]
+    LevelAndXp,
     #[error("There is no autocomplete for that command.")]
     NoAutocompleteForCommand,
     #[error("Discord didn't send an interaction message for that message component")]
@@ -107,6 +134,38 @@ pub enum Error {
     BotsDontLevel,
     #[error("Nobody in this server is ranked yet.")]
     NoRanksYet,
+    #[error("Nobody with that role is ranked yet.")]
+    NoRankedRoleMembers,
     #[error("This user does not have a most recent message.")]
     NoLastMessage,
+    #[error("That audit log entry does not exist in this server.")]
+    UnknownAuditLogEntry,
+    #[error("That audit log entry has already been reverted.")]
+    AuditLogEntryAlreadyReverted,
+    #[error("No audit log entries matched, so there was nothing to revert.")]
+    NothingToRevert,
+    #[error("This audit log page is invalid. Please run /audit again.")]
+    InvalidAuditLogPage,
+    #[error("That import file is invalid: {0}")]
+    InvalidImport(String),
+    #[error("That backup is invalid: {0}")]
+    InvalidBackup(String),
+    #[error("That backup was made by a newer version of Experienced (format version {0}).")]
+    UnsupportedBackupVersion(u32),
+    #[error("This server's backup is too big to upload. Please email valk@randomairborne.dev or [join our support server](https://discord.com/invite/KWkPYxqNKe) for a copy of your data.")]
+    BackupTooBig,
+    #[error("This confirmation has expired or was already used. Please run the command again.")]
+    PendingActionExpired,
+    #[error("Only the person who ran this command can confirm it.")]
+    NotYourPendingAction,
+}
+
+/// Most renderer errors are bugs, but a busy or slow renderer is worth telling users about
+const fn image_generator_message(error: &xpd_rank_card::Error) -> &'static str {
+    match error {
+        xpd_rank_card::Error::RendererBusy | xpd_rank_card::Error::RenderTimeout(_) => {
+            "Rank cards are taking a while right now. Please try again in a moment."
+        }
+        _ => "SVG renderer encountered an error!",
+    }
 }
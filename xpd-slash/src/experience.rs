@@ -2,7 +2,7 @@ use twilight_model::{
     channel::message::AllowedMentions,
     http::interaction::InteractionResponseType,
     id::{
-        marker::{GuildMarker, InteractionMarker, UserMarker},
+        marker::{GuildMarker, UserMarker},
         Id,
     },
 };
@@ -11,18 +11,18 @@ use xpd_common::AuditLogEvent;
 use xpd_slash_defs::experience::XpCommand;
 use xpd_util::snowflake_to_timestamp;
 
-use crate::{response::XpdInteractionResponse, Error, SlashState, XpdInteractionData};
-
-pub struct XpAuditData {
-    pub interaction: Id<InteractionMarker>,
-    pub invoker: Id<UserMarker>,
-}
+use crate::{
+    audit::AuditData,
+    log_channel::{mirror_audit_events, LoggedEvent},
+    response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData,
+};
 
 pub async fn process_xp(
     data: XpCommand,
     state: SlashState,
     guild_id: Id<GuildMarker>,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<XpdInteractionResponse, Error> {
     let contents = process_experience(data, guild_id, state, audit).await?;
     Ok(XpdInteractionData::new()
@@ -36,7 +36,7 @@ async fn process_experience(
     data: XpCommand,
     guild_id: Id<GuildMarker>,
     state: SlashState,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<String, Error> {
     if !allowed_command_for_target(&data) {
         return Err(Error::BotsDontLevel);
@@ -62,7 +62,7 @@ async fn modify_user_xp(
     guild_id: Id<GuildMarker>,
     user_id: Id<UserMarker>,
     amount: i64,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<String, Error> {
     let mut txn = state.db.begin().await?;
     let xp = xpd_database::add_xp(txn.as_mut(), user_id, guild_id, amount).await?;
@@ -75,14 +75,21 @@ async fn modify_user_xp(
         user_id,
         moderator: audit.invoker,
         timestamp: snowflake_to_timestamp(audit.interaction),
-        previous: xp + amount,
+        previous: xp - amount,
         delta: amount,
         reset: false,
         set: false,
+        reverts: None,
+        import: false,
     };
-    xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
+    let audit_id = xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
 
     txn.commit().await?;
+    mirror_audit_events(
+        &state,
+        guild_id,
+        vec![LoggedEvent::Xp(audit_id, audit_event)],
+    );
     let current_level = mee6::LevelInfo::new(xp.try_into().unwrap_or(0)).level();
     let (action, targeter) = if amount.is_positive() {
         ("Added", "to")
@@ -97,7 +104,7 @@ async fn reset_user_xp(
     state: SlashState,
     guild_id: Id<GuildMarker>,
     user_id: Id<UserMarker>,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<String, Error> {
     let mut txn = state.db.begin().await?;
     let old_xp = xpd_database::delete_levels_user_guild(txn.as_mut(), user_id, guild_id).await?;
@@ -111,10 +118,17 @@ async fn reset_user_xp(
         delta: -old_xp,
         reset: true,
         set: false,
+        reverts: None,
+        import: false,
     };
-    xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
+    let audit_id = xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
 
     txn.commit().await?;
+    mirror_audit_events(
+        &state,
+        guild_id,
+        vec![LoggedEvent::Xp(audit_id, audit_event)],
+    );
 
     Ok(format!(
         "Deleted <@{user_id}> from my database in this server!"
@@ -126,7 +140,7 @@ async fn set_user_xp(
     guild_id: Id<GuildMarker>,
     user_id: Id<UserMarker>,
     setpoint: i64,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<String, Error> {
     let mut txn = state.db.begin().await?;
     let old_xp = xpd_database::user_xp(txn.as_mut(), guild_id, user_id)
@@ -143,10 +157,17 @@ async fn set_user_xp(
         delta: setpoint - old_xp,
         reset: false,
         set: true,
+        reverts: None,
+        import: false,
     };
-    xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
+    let audit_id = xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
 
     txn.commit().await?;
+    mirror_audit_events(
+        &state,
+        guild_id,
+        vec![LoggedEvent::Xp(audit_id, audit_event)],
+    );
 
     let level = mee6::LevelInfo::new(setpoint.try_into().unwrap_or(0));
     Ok(format!(
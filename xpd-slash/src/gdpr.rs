@@ -7,6 +7,7 @@ use twilight_model::{
     id::{marker::GuildMarker, Id},
 };
 use xpd_common::MemberDisplayInfo;
+use xpd_database::CardTheme;
 use xpd_slash_defs::gdpr::{GdprCommand, GdprCommandDelete};
 
 use crate::{
@@ -34,6 +35,7 @@ async fn delete(
         let mut txn = state.db.begin().await?;
         xpd_database::delete_levels_user(&mut txn, invoker.id).await?;
         xpd_database::delete_card_customizations(&mut txn, invoker.id.cast()).await?;
+        xpd_database::delete_card_themes(&mut txn, invoker.id.cast()).await?;
         xpd_database::delete_audit_log_events_user(&mut txn, invoker.id).await?;
         txn.commit().await?;
         Ok(
@@ -59,6 +61,7 @@ async fn download(
 
     let invoker_id = &[invoker.id.cast()];
     let custom_card = get_customizations(&state, invoker_id).await?;
+    let themes = xpd_database::card_themes(&state.db, invoker.id.cast()).await?;
 
     let levels: Vec<UserXpArchiveEntry> = levels
         .into_iter()
@@ -67,11 +70,15 @@ async fn download(
 
     let levels = multicsv(&levels)?;
     let custom_card = multicsv(&[custom_card])?;
+    let themes: Vec<CardTheme> = themes.into_iter().map(|saved| saved.theme).collect();
+    let themes = multicsv(&themes)?;
 
     let level_file = Attachment::from_bytes(format!("leveling-{}.csv", invoker.id), levels, 1);
     let card_file = Attachment::from_bytes(format!("card-{}.csv", invoker.id), custom_card, 2);
 
-    let attachments: Vec<Attachment> = [level_file, card_file]
+    let theme_file = Attachment::from_bytes(format!("themes-{}.csv", invoker.id), themes, 3);
+
+    let attachments: Vec<Attachment> = [level_file, card_file, theme_file]
         .into_iter()
         .filter(|v| !v.file.is_empty())
         .collect();
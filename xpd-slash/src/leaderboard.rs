@@ -1,5 +1,6 @@
 use std::{convert::TryInto, fmt::Write};
 
+use tokio::task::JoinSet;
 use twilight_model::{
     application::interaction::{
         message_component::MessageComponentInteractionData, modal::ModalInteractionData,
@@ -11,38 +12,142 @@ use twilight_model::{
         },
         Message,
     },
-    http::interaction::{InteractionResponse, InteractionResponseType},
+    http::{
+        attachment::Attachment,
+        interaction::{InteractionResponse, InteractionResponseType},
+    },
     id::{
-        marker::{GuildMarker, UserMarker},
+        marker::{GuildMarker, RoleMarker, UserMarker},
         Id,
     },
 };
-use xpd_slash_defs::levels::LeaderboardCommand;
+use xpd_common::{DisplayName, MemberDisplayInfo, UserStatus};
+use xpd_rank_card::{LeaderboardContext, LeaderboardRow};
+use xpd_slash_defs::levels::{LeaderboardCommand, LeaderboardStyle};
 
 use crate::{
-    dispatch::Respondable, response::XpdInteractionResponse, Error, SlashState, XpdInteractionData,
+    dispatch::Respondable,
+    levels::{get_avatar, get_customizations, AvatarReference},
+    response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData,
 };
 
+/// Image leaderboards prefix all of their component IDs with this.
+/// Text leaderboards have bare IDs, which is also what leaderboards from before
+/// there were styles have, so their buttons keep working.
+const IMAGE_STYLE_PREFIX: &str = "image:";
+/// Leaderboards which only rank one role's members put `role:<id>:` after the style prefix,
+/// so paging keeps the filter.
+const ROLE_FILTER_PREFIX: &str = "role:";
+
 pub async fn leaderboard(
     state: SlashState,
     guild_id: Id<GuildMarker>,
     guild_command: LeaderboardCommand,
 ) -> Result<XpdInteractionResponse, Error> {
+    // Everyone has @everyone, so there's nothing to filter
+    let role = guild_command
+        .role
+        .map(|role| role.id)
+        .filter(|role| *role != guild_id.cast());
     // "zpage" means "zero-indexed page", which is how this is represented internally.
     // We add one whenever we show it to the user, and subtract one every time we get it from the user.
     let zpage = if let Some(pick) = guild_command.page {
         pick - 1
     } else if let Some(pick) = guild_command.user {
-        state.get_user_stats(pick.resolved.id, guild_id).await?.rank / 10
+        let rank = match role {
+            Some(role) => {
+                let members = role_members(&state, guild_id, role);
+                let xp = xpd_database::user_xp(&state.db, guild_id, pick.resolved.id)
+                    .await?
+                    .unwrap_or(0);
+                xpd_database::count_among_with_higher_xp(&state.db, guild_id, &members, xp)
+                    .await?
+                    .unwrap_or(0)
+                    + 1
+            }
+            None => state.get_user_stats(pick.resolved.id, guild_id).await?.rank,
+        };
+        rank / 10
     } else {
         0
     };
     Ok(XpdInteractionResponse::new(
         InteractionResponseType::ChannelMessageWithSource,
-        gen_leaderboard(&state, guild_id, zpage, guild_command.show_off).await?,
+        gen_leaderboard(
+            &state,
+            guild_id,
+            zpage,
+            guild_command.show_off,
+            guild_command.style.unwrap_or_default(),
+            role,
+        )
+        .await?,
     ))
 }
 
+fn styled_id(style: LeaderboardStyle, id: &str) -> String {
+    match style {
+        LeaderboardStyle::Text => id.to_string(),
+        LeaderboardStyle::Image => format!("{IMAGE_STYLE_PREFIX}{id}"),
+    }
+}
+
+fn split_style(custom_id: &str) -> (LeaderboardStyle, &str) {
+    custom_id
+        .strip_prefix(IMAGE_STYLE_PREFIX)
+        .map_or((LeaderboardStyle::Text, custom_id), |id| {
+            (LeaderboardStyle::Image, id)
+        })
+}
+
+fn filtered_id(role: Option<Id<RoleMarker>>, id: &str) -> String {
+    role.map_or_else(
+        || id.to_string(),
+        |role| format!("{ROLE_FILTER_PREFIX}{role}:{id}"),
+    )
+}
+
+fn split_filter(custom_id: &str) -> (Option<Id<RoleMarker>>, &str) {
+    custom_id
+        .strip_prefix(ROLE_FILTER_PREFIX)
+        .and_then(|rest| rest.split_once(':'))
+        .and_then(|(role, id)| Some((Some(role.parse().ok()?), id)))
+        .unwrap_or((None, custom_id))
+}
+
+fn component_id(style: LeaderboardStyle, role: Option<Id<RoleMarker>>, id: &str) -> String {
+    styled_id(style, &filtered_id(role, id))
+}
+
+fn split_component_id(custom_id: &str) -> (LeaderboardStyle, Option<Id<RoleMarker>>, &str) {
+    let (style, rest) = split_style(custom_id);
+    let (role, id) = split_filter(rest);
+    (style, role, id)
+}
+
+/// Members the cache knows to have `role`. Discord doesn't send every member of big
+/// servers up front, so members the bot hasn't seen since it started may be missing.
+fn role_members(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    role: Id<RoleMarker>,
+) -> Vec<Id<UserMarker>> {
+    let Some(members) = state.cache.guild_members(guild_id) else {
+        return Vec::new();
+    };
+    members
+        .iter()
+        .copied()
+        .filter(|user_id| {
+            state
+                .cache
+                .member(guild_id, *user_id)
+                .is_some_and(|member| member.roles().contains(&role))
+        })
+        .collect()
+}
+
 const USERS_PER_PAGE_USIZE: usize = 10;
 #[allow(clippy::cast_possible_wrap)]
 const USERS_PER_PAGE: i64 = USERS_PER_PAGE_USIZE as i64;
@@ -52,43 +157,44 @@ async fn gen_leaderboard(
     guild_id: Id<GuildMarker>,
     zpage: i64,
     show_off: Option<bool>,
+    style: LeaderboardStyle,
+    role: Option<Id<RoleMarker>>,
 ) -> Result<XpdInteractionData, Error> {
     if zpage.is_negative() {
         return Err(Error::PageDoesNotExist);
     }
     let is_ephemeral = !(show_off.unwrap_or(true));
-    let users = xpd_database::get_leaderboard_page(
-        &state.db,
-        guild_id,
-        USERS_PER_PAGE + 1,
-        zpage * USERS_PER_PAGE,
-    )
-    .await?;
+    let users = if let Some(role) = role {
+        xpd_database::get_leaderboard_page_among(
+            &state.db,
+            guild_id,
+            &role_members(state, guild_id, role),
+            USERS_PER_PAGE + 1,
+            zpage * USERS_PER_PAGE,
+        )
+        .await?
+    } else {
+        xpd_database::get_leaderboard_page(
+            &state.db,
+            guild_id,
+            USERS_PER_PAGE + 1,
+            zpage * USERS_PER_PAGE,
+        )
+        .await?
+    };
 
     if users.is_empty() {
-        return Err(if zpage == 0 {
-            Error::NoUsersForPage
-        } else {
-            Error::NoRanksYet
+        return Err(match (zpage, role) {
+            (0, Some(_)) => Error::NoRankedRoleMembers,
+            (0, None) => Error::NoUsersForPage,
+            _ => Error::NoRanksYet,
         });
     }
 
     let one_more_page_bro = users.len() >= (USERS_PER_PAGE_USIZE + 1);
     let last_user_idx = users.len().clamp(0, USERS_PER_PAGE_USIZE);
     let users = &users[0..last_user_idx];
-    // this is kinda the only way to do this
-    // It's designed to only allocate once, at the start here
-    let mut description = String::with_capacity(256 + users.len() * 128);
-    writeln!(description, "### Leaderboard")?;
-    for (i, user) in users.iter().enumerate() {
-        let level = mee6::LevelInfo::new(user.xp.try_into().unwrap_or(0)).level();
-        let rank: i64 = i
-            .try_into()
-            .map_or(-1, |v: i64| v + (zpage * USERS_PER_PAGE) + 1);
-        writeln!(description, "**#{rank}.** <@{}> - Level {level}", user.id)?;
-    }
-
-    let control_options = control_options(zpage, one_more_page_bro);
+    let control_options = control_options(zpage, one_more_page_bro, style, role);
 
     let (components, flags) = if is_ephemeral {
         let second_last_idx = control_options.len() - 2;
@@ -104,17 +210,184 @@ async fn gen_leaderboard(
         components: components.to_vec(),
     });
 
-    Ok(XpdInteractionData::new()
+    let response = XpdInteractionData::new()
         .allowed_mentions(AllowedMentions::default())
         .components([components])
-        .content(description)
-        .flags(flags))
+        .flags(flags);
+    Ok(match style {
+        LeaderboardStyle::Text => response.content(text_leaderboard(users, zpage, role)?),
+        LeaderboardStyle::Image => response
+            .content(String::new())
+            .attachments([image_leaderboard(state, guild_id, users, zpage).await?]),
+    })
 }
 
-fn control_options(zpage: i64, next_page_exists: bool) -> [Component; 5] {
+fn leaderboard_rank(zpage: i64, index: usize) -> i64 {
+    index
+        .try_into()
+        .map_or(-1, |v: i64| v + (zpage * USERS_PER_PAGE) + 1)
+}
+
+fn text_leaderboard(
+    users: &[UserStatus],
+    zpage: i64,
+    role: Option<Id<RoleMarker>>,
+) -> Result<String, Error> {
+    // this is kinda the only way to do this
+    // It's designed to only allocate once, at the start here
+    let mut description = String::with_capacity(256 + users.len() * 128);
+    match role {
+        Some(role) => writeln!(description, "### Leaderboard for <@&{role}>")?,
+        None => writeln!(description, "### Leaderboard")?,
+    }
+    for (i, user) in users.iter().enumerate() {
+        let level = mee6::LevelInfo::new(user.xp.try_into().unwrap_or(0)).level();
+        let rank = leaderboard_rank(zpage, i);
+        writeln!(description, "**#{rank}.** <@{}> - Level {level}", user.id)?;
+    }
+    Ok(description)
+}
+
+async fn image_leaderboard(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    users: &[UserStatus],
+    zpage: i64,
+) -> Result<Attachment, Error> {
+    let customizations = get_customizations(state, &[guild_id.cast()]).await?;
+
+    // Names and avatars may need a request each, so fetch the whole page at once
+    let mut lookups = JoinSet::new();
+    for (i, user) in users.iter().enumerate() {
+        let state = state.clone();
+        let user_id = user.id;
+        lookups.spawn(async move { (i, leaderboard_member(&state, guild_id, user_id).await) });
+    }
+    let mut members = vec![(String::new(), String::new()); users.len()];
+    while let Some(lookup) = lookups.join_next().await {
+        let (i, member) = lookup?;
+        members[i] = member;
+    }
+
+    let rows = users
+        .iter()
+        .zip(members)
+        .enumerate()
+        .map(|(i, (user, (name, avatar)))| {
+            let level_info = mee6::LevelInfo::new(user.xp.try_into().unwrap_or(0));
+            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+            let percentage = (level_info.percentage() * 100.0).round() as u64;
+            LeaderboardRow {
+                rank: leaderboard_rank(zpage, i),
+                name,
+                level: level_info.level(),
+                percentage,
+                current: level_info.xp(),
+                needed: mee6::xp_needed_for_level(level_info.level() + 1),
+                avatar,
+            }
+        })
+        .collect();
+    let image = state
+        .svg
+        .render_leaderboard(
+            LeaderboardContext {
+                rows,
+                customizations,
+            },
+            state.card_formats.text,
+        )
+        .await?;
+    Ok(Attachment {
+        description: Some(format!("Leaderboard page {}", zpage + 1)),
+        file: image,
+        filename: format!("leaderboard.{}", state.card_formats.text.extension()),
+        id: 0,
+    })
+}
+
+/// Display name and avatar data URL for one leaderboard row.
+/// Users who have left the server are still listed, with a placeholder name and default avatar.
+async fn leaderboard_member(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> (String, String) {
+    let member = match cached_member(state, guild_id, user_id) {
+        Some(member) => Some(member),
+        None => fetch_member(state, guild_id, user_id)
+            .await
+            .inspect_err(|source| debug!(?source, %user_id, "Could not fetch leaderboard member"))
+            .ok(),
+    };
+    let (name, avatar_ref) = member.map_or_else(
+        || {
+            (
+                "Unknown user".to_string(),
+                AvatarReference::new(user_id, None, None, None),
+            )
+        },
+        |member| {
+            (
+                member.display_name().to_string(),
+                AvatarReference::new(
+                    member.id,
+                    member.avatar,
+                    Some(guild_id),
+                    member.local_avatar,
+                ),
+            )
+        },
+    );
+    // A missing avatar shouldn't stop the rest of the leaderboard from rendering
+    let avatar = get_avatar(state, avatar_ref)
+        .await
+        .inspect_err(|source| debug!(?source, %user_id, "Could not fetch leaderboard avatar"))
+        .unwrap_or_default();
+    (name, avatar)
+}
+
+fn cached_member(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> Option<MemberDisplayInfo> {
+    let member = state.cache.member(guild_id, user_id)?;
+    let user = state.cache.user(user_id)?;
+    Some(MemberDisplayInfo {
+        id: user_id,
+        name: user.name.clone(),
+        global_name: user.global_name.clone(),
+        nick: member.nick().map(ToOwned::to_owned),
+        avatar: user.avatar,
+        local_avatar: member.avatar(),
+        bot: user.bot,
+    })
+}
+
+async fn fetch_member(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> Result<MemberDisplayInfo, Error> {
+    let member = state
+        .client
+        .guild_member(guild_id, user_id)
+        .await?
+        .model()
+        .await?;
+    Ok(member.into())
+}
+
+fn control_options(
+    zpage: i64,
+    next_page_exists: bool,
+    style: LeaderboardStyle,
+    role: Option<Id<RoleMarker>>,
+) -> [Component; 5] {
     [
         Button {
-            custom_id: Some("page_indicator".to_string()),
+            custom_id: Some(component_id(style, role, "page_indicator")),
             disabled: true,
             emoji: None,
             label: Some(format!("Page {}", zpage + 1)),
@@ -123,7 +396,7 @@ fn control_options(zpage: i64, next_page_exists: bool) -> [Component; 5] {
             sku_id: None,
         },
         Button {
-            custom_id: Some((zpage - 1).to_string()),
+            custom_id: Some(component_id(style, role, &(zpage - 1).to_string())),
             disabled: zpage == 0,
             emoji: Some(EmojiReactionType::Unicode {
                 name: "⬅".to_string(),
@@ -134,7 +407,7 @@ fn control_options(zpage: i64, next_page_exists: bool) -> [Component; 5] {
             sku_id: None,
         },
         Button {
-            custom_id: Some("jump_modal".to_string()),
+            custom_id: Some(component_id(style, role, "jump_modal")),
             disabled: !next_page_exists && zpage == 0,
             emoji: None,
             label: Some("Go to page".to_string()),
@@ -143,7 +416,7 @@ fn control_options(zpage: i64, next_page_exists: bool) -> [Component; 5] {
             sku_id: None,
         },
         Button {
-            custom_id: Some((zpage + 1).to_string()),
+            custom_id: Some(component_id(style, role, &(zpage + 1).to_string())),
             disabled: !next_page_exists,
             emoji: Some(EmojiReactionType::Unicode {
                 name: "➡️".to_string(),
@@ -154,7 +427,7 @@ fn control_options(zpage: i64, next_page_exists: bool) -> [Component; 5] {
             sku_id: None,
         },
         Button {
-            custom_id: Some("delete_leaderboard".to_string()),
+            custom_id: Some(component_id(style, role, "delete_leaderboard")),
             disabled: false,
             emoji: Some(EmojiReactionType::Unicode {
                 name: "🗑️".to_string(),
@@ -174,6 +447,7 @@ pub async fn process_modal_submit(
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
     // You can't get this modal unless you are the triggering user
+    let (style, role, _) = split_component_id(&data.custom_id);
     let actions = data.components.first().ok_or(Error::NoModalActionRow)?;
     let field = actions.components.first().ok_or(Error::NoFormField)?;
     let choice: i64 = field
@@ -184,7 +458,7 @@ pub async fn process_modal_submit(
     let zpage = choice - 1;
     Ok(XpdInteractionResponse::new(
         InteractionResponseType::UpdateMessage,
-        gen_leaderboard(&state, guild_id, zpage, Some(true)).await?,
+        gen_leaderboard(&state, guild_id, zpage, Some(true), style, role).await?,
     ))
 }
 
@@ -205,7 +479,8 @@ pub async fn process_message_component(
     {
         return Err(Error::NotYourLeaderboard);
     }
-    match data.custom_id.as_str() {
+    let (style, role, action) = split_component_id(&data.custom_id);
+    match action {
         "jump_modal" => {
             let input = TextInput {
                 custom_id: "jump_modal_input".to_string(),
@@ -223,7 +498,7 @@ pub async fn process_message_component(
                     .components([Component::ActionRow(ActionRow {
                         components: vec![Component::TextInput(input)],
                     })])
-                    .custom_id("jump_modal".to_string())
+                    .custom_id(component_id(style, role, "jump_modal"))
                     .title("Go to page..".to_string()),
             ))
         }
@@ -254,8 +529,54 @@ pub async fn process_message_component(
                 .is_none_or(|f| !f.contains(MessageFlags::EPHEMERAL));
             Ok(XpdInteractionResponse::new(
                 InteractionResponseType::UpdateMessage,
-                gen_leaderboard(&state, guild_id, offset, Some(show_delete_btn)).await?,
+                gen_leaderboard(&state, guild_id, offset, Some(show_delete_btn), style, role)
+                    .await?,
             ))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use twilight_model::id::Id;
+    use xpd_slash_defs::levels::LeaderboardStyle;
+
+    use super::{component_id, split_component_id, split_style, styled_id};
+
+    #[test]
+    fn style_round_trips() {
+        for style in [LeaderboardStyle::Text, LeaderboardStyle::Image] {
+            for id in ["jump_modal", "delete_leaderboard", "3"] {
+                assert_eq!(split_style(&styled_id(style, id)), (style, id));
+            }
+        }
+    }
+
+    #[test]
+    fn unstyled_ids_are_text() {
+        assert_eq!(split_style("4"), (LeaderboardStyle::Text, "4"));
+        assert_eq!(
+            split_style("jump_modal"),
+            (LeaderboardStyle::Text, "jump_modal")
+        );
+    }
+
+    #[test]
+    fn role_filter_round_trips() {
+        let role = Some(Id::new(1234));
+        for style in [LeaderboardStyle::Text, LeaderboardStyle::Image] {
+            for filter in [None, role] {
+                for id in ["jump_modal", "delete_leaderboard", "3"] {
+                    assert_eq!(
+                        split_component_id(&component_id(style, filter, id)),
+                        (style, filter, id)
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            split_component_id("image:role:1234:-1"),
+            (LeaderboardStyle::Image, role, "-1")
+        );
+    }
+}
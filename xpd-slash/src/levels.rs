@@ -1,17 +1,23 @@
+use std::{sync::Arc, time::Duration};
+
 use base64::Engine;
 use tokio::try_join;
 use twilight_model::{
     channel::message::MessageFlags,
     http::{attachment::Attachment, interaction::InteractionResponseType},
     id::{
-        marker::{GenericMarker, GuildMarker, UserMarker},
+        marker::{GenericMarker, GuildMarker, RoleMarker, UserMarker},
         Id,
     },
     util::ImageHash,
 };
 use twilight_util::builder::embed::EmbedBuilder;
 use xpd_common::{DisplayName, MemberDisplayInfo};
-use xpd_rank_card::customizations::{Color, Customizations};
+use xpd_rank_card::{
+    cache::ByteCache,
+    customizations::{Color, Customizations},
+    ImageFormat, NextReward, RewardRole, CUSTOM_LAYOUT, DEFAULT_WEBP_QUALITY, MAX_AVATAR_DIMENSION,
+};
 
 use crate::{response::XpdInteractionResponse, Error, SlashState, XpdInteractionData};
 
@@ -84,7 +90,7 @@ async fn generate_level_response(
         .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
 }
 
-async fn get_customizations_fields(
+pub async fn get_customizations_fields(
     state: SlashState,
     user_id: Id<UserMarker>,
     guild_id: Option<Id<GuildMarker>>,
@@ -96,6 +102,101 @@ async fn get_customizations_fields(
     }
 }
 
+async fn get_background(
+    state: SlashState,
+    user_id: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+) -> Result<Option<Arc<Vec<u8>>>, Error> {
+    let background = if let Some(guild_id) = guild_id {
+        xpd_database::card_background(&state.db, &[user_id.cast(), guild_id.cast()]).await
+    } else {
+        xpd_database::card_background(&state.db, &[user_id.cast()]).await
+    }?;
+    Ok(background.map(Arc::new))
+}
+
+/// Find the highest reward role the user has earned, and the next one they can earn.
+/// Rewards whose role is no longer in the cache are skipped.
+async fn get_rewards(
+    state: &SlashState,
+    guild_id: Option<Id<GuildMarker>>,
+    level_info: mee6::LevelInfo,
+) -> Result<(Option<RewardRole>, Option<NextReward>), Error> {
+    let Some(guild_id) = guild_id else {
+        return Ok((None, None));
+    };
+    let mut rewards = xpd_database::guild_rewards(&state.db, guild_id).await?;
+    rewards.sort_by_key(|reward| reward.requirement);
+    let level = level_info.level();
+    let (earned, upcoming): (Vec<_>, Vec<_>) = rewards
+        .into_iter()
+        .filter(|reward| state.cache.role(reward.id).is_some())
+        .partition(|reward| u64::try_from(reward.requirement).is_ok_and(|req| req <= level));
+
+    let reward = match earned.last() {
+        Some(reward) => get_reward_role(state, reward.id).await?,
+        None => None,
+    };
+    let next_reward = match upcoming.first() {
+        Some(next) => get_reward_role(state, next.id).await?.map(|role| {
+            let next_level = u64::try_from(next.requirement).unwrap_or(0);
+            NextReward {
+                role,
+                level: next_level,
+                xp_needed: mee6::xp_needed_for_level(next_level).saturating_sub(level_info.xp()),
+            }
+        }),
+        None => None,
+    };
+    Ok((reward, next_reward))
+}
+
+/// Long role names would run into the rest of the card
+const MAX_REWARD_NAME_CHARS: usize = 20;
+
+async fn get_reward_role(
+    state: &SlashState,
+    role_id: Id<RoleMarker>,
+) -> Result<Option<RewardRole>, Error> {
+    // Don't hold the cache reference across the icon download
+    let Some((name, color, emoji, icon)) = state.cache.role(role_id).map(|role| {
+        (
+            role.name.clone(),
+            role.color,
+            role.unicode_emoji.clone(),
+            role.icon,
+        )
+    }) else {
+        return Ok(None);
+    };
+    let name = if name.chars().count() > MAX_REWARD_NAME_CHARS {
+        name.chars()
+            .take(MAX_REWARD_NAME_CHARS - 1)
+            .chain(['…'])
+            .collect()
+    } else {
+        name
+    };
+    // Roles without a color have a color of 0
+    let color = (color != 0).then(|| {
+        let [_, red, green, blue] = color.to_be_bytes();
+        Color::new(red, green, blue)
+    });
+    let icon = match icon {
+        Some(hash) => get_role_icon(state, role_id, hash)
+            .await
+            .inspect_err(|source| warn!(?source, "Failed to download role icon"))
+            .ok(),
+        None => None,
+    };
+    Ok(Some(RewardRole {
+        name,
+        color,
+        emoji,
+        icon,
+    }))
+}
+
 pub async fn gen_card(
     state: SlashState,
     user: MemberDisplayInfo,
@@ -103,25 +204,74 @@ pub async fn gen_card(
     level_info: mee6::LevelInfo,
     rank: i64,
 ) -> Result<Attachment, Error> {
-    let customizations_future = get_customizations_fields(state.clone(), user.id, guild_id);
+    gen_card_with(state, user, guild_id, level_info, rank, None).await
+}
+
+/// Like [`gen_card`], but `customizations` replace the user's saved customizations
+/// when given, for previews.
+pub async fn gen_card_with(
+    state: SlashState,
+    user: MemberDisplayInfo,
+    guild_id: Option<Id<GuildMarker>>,
+    level_info: mee6::LevelInfo,
+    rank: i64,
+    customizations: Option<Customizations>,
+) -> Result<Attachment, Error> {
+    let customizations_future = async {
+        match customizations {
+            Some(customizations) => Ok(customizations),
+            None => get_customizations_fields(state.clone(), user.id, guild_id).await,
+        }
+    };
     let avatar_ref = AvatarReference::new(user.id, user.avatar, guild_id, user.local_avatar);
-    let avatar_future = get_avatar(&state.http, avatar_ref);
-    let (customizations, avatar) = try_join!(customizations_future, avatar_future)?;
+    let avatar_future = get_avatar(&state, avatar_ref);
+    let background_future = get_background(state.clone(), user.id, guild_id);
+    let rewards_future = get_rewards(&state, guild_id, level_info);
+    let (customizations, avatar, background_image, (reward, next_reward)) = try_join!(
+        customizations_future,
+        avatar_future,
+        background_future,
+        rewards_future
+    )?;
     #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
     let percentage = (level_info.percentage() * 100.0).round() as u64;
-    let png = state
-        .svg
-        .render(xpd_rank_card::Context {
-            level: level_info.level(),
-            rank,
-            name: user.display_name().to_string(),
-            percentage,
-            current: level_info.xp(),
-            needed: mee6::xp_needed_for_level(level_info.level() + 1),
-            customizations,
-            avatar,
-        })
-        .await?;
+    let animated = customizations.animated_avatar && avatar_ref.is_animated();
+    let layout_owner = match guild_id {
+        Some(guild_id) if customizations.internal_name == CUSTOM_LAYOUT => {
+            load_layout(&state, guild_id).await?;
+            Some(guild_id.get())
+        }
+        _ => None,
+    };
+    let context = xpd_rank_card::Context {
+        level: level_info.level(),
+        rank,
+        name: user.display_name().to_string(),
+        percentage,
+        current: level_info.xp(),
+        needed: mee6::xp_needed_for_level(level_info.level() + 1),
+        customizations,
+        avatar,
+        reward,
+        next_reward,
+        background_image,
+        layout_owner,
+    };
+    let animated_card = if animated {
+        render_animated_card(&state, avatar_ref, context.clone()).await
+    } else {
+        None
+    };
+    let (file, extension) = if let Some(gif) = animated_card {
+        (gif, "gif")
+    } else {
+        let format = if context.background_image.is_some() {
+            state.card_formats.photo
+        } else {
+            state.card_formats.text
+        };
+        (state.svg.render(context, format).await?, format.extension())
+    };
     Ok(Attachment {
         description: Some(format!(
             "{} is level {} (rank #{}), and is {}% of the way to level {}.",
@@ -131,21 +281,57 @@ pub async fn gen_card(
             (level_info.percentage() * 100.0).round(),
             level_info.level() + 1
         )),
-        file: png,
-        filename: "card.png".to_string(),
+        file,
+        filename: format!("card.{extension}"),
         id: 0,
     })
 }
 
+/// Uploaded layouts are compiled the first time a card needs them. A layout which no longer
+/// passes validation is only checked once, and cards are drawn with the default layout until
+/// the guild uploads or restores another.
+async fn load_layout(state: &SlashState, guild_id: Id<GuildMarker>) -> Result<(), Error> {
+    if state.svg.has_layout(guild_id.get()) {
+        return Ok(());
+    }
+    let Some(template) = xpd_database::guild_card_layout(&state.db, guild_id).await? else {
+        return Ok(());
+    };
+    if let Err(source) = state.svg.register_layout(guild_id.get(), template).await {
+        warn!(?source, %guild_id, "Stored card layout failed validation");
+    }
+    Ok(())
+}
+
+/// Animated cards are a nice-to-have, so any failure here just means the caller
+/// renders a still card instead.
+async fn render_animated_card(
+    state: &SlashState,
+    avatar: AvatarReference,
+    context: xpd_rank_card::Context,
+) -> Option<Vec<u8>> {
+    let gif = get_animated_avatar(state, avatar)
+        .await
+        .inspect_err(|source| warn!(?source, "Failed to download animated avatar"))
+        .ok()?;
+    state
+        .svg
+        .render_animated(context, gif.to_vec())
+        .await
+        .inspect_err(|source| debug!(?source, "Falling back to still card"))
+        .ok()
+}
+
 pub async fn get_customizations(
     state: &SlashState,
     ids: &[Id<GenericMarker>],
 ) -> Result<Customizations, Error> {
-    let Some(customizations) = xpd_database::card_customizations(&state.db, ids).await? else {
-        return Ok(state.svg.default_customizations().clone());
+    let customizations = xpd_database::card_customizations(&state.db, ids).await?;
+    let resources = state.svg.resources();
+    let Some(customizations) = customizations else {
+        return Ok(resources.default_customizations().clone());
     };
-    let defaults = state
-        .svg
+    let defaults = resources
         .customizations_for(&customizations.card_layout)
         .ok_or(Error::UnknownCard)?;
 
@@ -174,19 +360,22 @@ pub async fn get_customizations(
         font: customizations.font.unwrap_or_else(|| defaults.font.clone()),
         toy: customizations.toy_image,
         internal_name: customizations.card_layout,
+        animated_avatar: customizations
+            .animated_avatar
+            .unwrap_or(defaults.animated_avatar),
     })
 }
 
 fn color_or_default(color: Option<&str>, default: Color) -> Result<Color, Error> {
     if let Some(color) = &color {
-        Ok(Color::from_hex(color)?)
+        Ok(Color::parse(color)?)
     } else {
         Ok(default)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct AvatarReference {
+pub struct AvatarReference {
     id: Id<UserMarker>,
     kind: Option<AvatarReferenceKind>,
 }
@@ -210,13 +399,31 @@ impl AvatarReference {
         Self { id, kind }
     }
 
+    /// Animated avatars have an `a_` prefix on their hash. Default avatars are never animated.
+    pub const fn is_animated(self) -> bool {
+        match self.kind {
+            Some(AvatarReferenceKind::Guild(_, hash) | AvatarReferenceKind::User(hash)) => {
+                hash.is_animated()
+            }
+            None => false,
+        }
+    }
+
     pub fn to_url(self) -> String {
+        self.to_url_with_extension("png")
+    }
+
+    pub fn to_gif_url(self) -> String {
+        self.to_url_with_extension("gif") + &format!("?size={MAX_AVATAR_DIMENSION}")
+    }
+
+    fn to_url_with_extension(self, extension: &str) -> String {
         let user_id = self.id;
         match self.kind {
             Some(AvatarReferenceKind::Guild(guild_id, avatar_hash)) =>format!(
-                "https://cdn.discordapp.com/guilds/{guild_id}/users/{user_id}/avatars/{avatar_hash}.png",
+                "https://cdn.discordapp.com/guilds/{guild_id}/users/{user_id}/avatars/{avatar_hash}.{extension}",
             ),
-            Some(AvatarReferenceKind::User(avatar_hash)) => format!("https://cdn.discordapp.com/avatars/{user_id}/{avatar_hash}.png",),
+            Some(AvatarReferenceKind::User(avatar_hash)) => format!("https://cdn.discordapp.com/avatars/{user_id}/{avatar_hash}.{extension}"),
             None => format!(
                 "https://cdn.discordapp.com/embed/avatars/{}.png",
                 (user_id.get() >> 22) % 6
@@ -225,17 +432,122 @@ impl AvatarReference {
     }
 }
 
-#[tracing::instrument(skip(client))]
-async fn get_avatar(client: &reqwest::Client, avatar: AvatarReference) -> Result<String, Error> {
-    let url = avatar.to_url();
-    debug!(url, "Downloading avatar");
-    let png = client.get(url).send().await?.bytes().await?;
+/// How still cards are encoded. Each response picks one of these by what it draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardFormats {
+    /// For leaderboards, comparisons and plain cards, which are flat colors and text
+    pub text: ImageFormat,
+    /// For cards with a background image, which is usually a photo
+    pub photo: ImageFormat,
+}
+
+impl Default for CardFormats {
+    /// Lossless WebP keeps text sharp and is still several times smaller than PNG, and
+    /// photos come out far smaller as lossy WebP.
+    fn default() -> Self {
+        Self {
+            text: ImageFormat::WebpLossless,
+            photo: ImageFormat::WebpLossy {
+                quality: DEFAULT_WEBP_QUALITY,
+            },
+        }
+    }
+}
+
+/// Avatar downloads are cached by their image hash, so a changed avatar is a new entry.
+pub type AvatarCache = ByteCache<(AvatarReference, AvatarFormat)>;
+
+pub const AVATAR_CACHE_BYTES: usize = 32 * 1024 * 1024;
+pub const AVATAR_CACHE_TTL: Duration = Duration::from_mins(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AvatarFormat {
+    Png,
+    Gif,
+}
+
+/// Role icons are tiny, and change about as often as the roles do.
+pub type RoleIconCache = ByteCache<(Id<RoleMarker>, ImageHash)>;
+
+pub const ROLE_ICON_CACHE_BYTES: usize = 4 * 1024 * 1024;
+pub const ROLE_ICON_CACHE_TTL: Duration = Duration::from_mins(30);
+
+#[tracing::instrument(skip(state))]
+async fn get_role_icon(
+    state: &SlashState,
+    role_id: Id<RoleMarker>,
+    hash: ImageHash,
+) -> Result<String, Error> {
+    let key = (role_id, hash);
+    let png = if let Some(png) = state.role_icons.get(&key) {
+        png
+    } else {
+        let url = format!("https://cdn.discordapp.com/role-icons/{role_id}/{hash}.png?size=64");
+        debug!(url, "Downloading role icon");
+        let response = state.http.get(url).send().await?.error_for_status()?;
+        let png = Arc::new(response.bytes().await?.to_vec());
+        state.role_icons.insert(key, png.clone());
+        png
+    };
+    Ok("data:image/png;base64,".to_string() + &BASE64_ENGINE.encode(png.as_slice()))
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn get_avatar(state: &SlashState, avatar: AvatarReference) -> Result<String, Error> {
+    let key = (avatar, AvatarFormat::Png);
+    let png = if let Some(png) = state.avatars.get(&key) {
+        png
+    } else {
+        let url = avatar.to_url();
+        debug!(url, "Downloading avatar");
+        let response = state.http.get(url).send().await?;
+        let cacheable = response.status().is_success();
+        let png = Arc::new(response.bytes().await?.to_vec());
+        if cacheable {
+            state.avatars.insert(key, png.clone());
+        }
+        png
+    };
     debug!("Encoding avatar");
-    let data = "data:image/png;base64,".to_string() + &BASE64_ENGINE.encode(png);
+    let data = "data:image/png;base64,".to_string() + &BASE64_ENGINE.encode(png.as_slice());
     debug!("Encoded avatar");
     Ok(data)
 }
 
+/// Download an animated avatar as raw GIF data, refusing anything over [`MAX_ANIMATED_AVATAR_BYTES`].
+#[tracing::instrument(skip(state))]
+async fn get_animated_avatar(
+    state: &SlashState,
+    avatar: AvatarReference,
+) -> Result<Arc<Vec<u8>>, Error> {
+    let key = (avatar, AvatarFormat::Gif);
+    if let Some(gif) = state.avatars.get(&key) {
+        return Ok(gif);
+    }
+    let url = avatar.to_gif_url();
+    debug!(url, "Downloading animated avatar");
+    let mut response = state.http.get(url).send().await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_ANIMATED_AVATAR_BYTES as u64)
+    {
+        return Err(Error::AnimatedAvatarTooBig);
+    }
+    let mut gif = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if gif.len() + chunk.len() > MAX_ANIMATED_AVATAR_BYTES {
+            return Err(Error::AnimatedAvatarTooBig);
+        }
+        gif.extend_from_slice(&chunk);
+    }
+    let gif = Arc::new(gif);
+    state.avatars.insert(key, gif.clone());
+    Ok(gif)
+}
+
+/// Discord serves animated avatars at 512px well under this size
+const MAX_ANIMATED_AVATAR_BYTES: usize = 8 * 1024 * 1024;
+
 const BASE64_ENGINE: base64::engine::GeneralPurpose = base64::engine::GeneralPurpose::new(
     &base64::alphabet::STANDARD,
     base64::engine::general_purpose::NO_PAD,
@@ -1,9 +1,12 @@
 #![deny(clippy::all, clippy::pedantic, clippy::nursery)]
-#![allow(clippy::module_name_repetitions)]
+#![allow(clippy::module_name_repetitions, clippy::result_large_err)]
 
 mod admin;
 mod audit;
 mod autocomplete;
+mod backup;
+mod calculate;
+mod compare;
 mod config;
 mod dispatch;
 mod error;
@@ -12,14 +15,22 @@ mod gdpr;
 mod help;
 mod leaderboard;
 mod levels;
+mod log_channel;
 mod manage_card;
 mod manager;
+mod pending;
 mod response;
 mod rewards;
 
-use std::{future::Future, sync::Arc, time::Instant};
+use std::{future::Future, path::Path, sync::Arc, time::Instant};
 
 pub use error::Error;
+pub use levels::CardFormats;
+use levels::{
+    AvatarCache, RoleIconCache, AVATAR_CACHE_BYTES, AVATAR_CACHE_TTL, ROLE_ICON_CACHE_BYTES,
+    ROLE_ICON_CACHE_TTL,
+};
+use pending::PendingActions;
 pub use response::XpdInteractionData;
 use response::XpdInteractionResponse;
 use sqlx::PgPool;
@@ -69,8 +80,11 @@ impl XpdSlash {
         control_guild: Id<GuildMarker>,
         owners: Vec<Id<UserMarker>>,
         event_bus: EventBus,
+        max_import_size: usize,
+        card_resources: &Path,
+        card_formats: CardFormats,
     ) -> Self {
-        let svg = SvgState::new("xpd-card-resources").expect("Failed to initialize card renderer");
+        let svg = SvgState::new(card_resources).expect("Failed to initialize card renderer");
         let rt = Handle::current();
         let state = SlashState {
             db,
@@ -85,6 +99,14 @@ impl XpdSlash {
             control_guild,
             owners: owners.into(),
             event_bus,
+            pending: Arc::new(PendingActions::default()),
+            avatars: Arc::new(AvatarCache::new(AVATAR_CACHE_BYTES, AVATAR_CACHE_TTL)),
+            role_icons: Arc::new(RoleIconCache::new(
+                ROLE_ICON_CACHE_BYTES,
+                ROLE_ICON_CACHE_TTL,
+            )),
+            max_import_size,
+            card_formats,
         };
         Self { state }
     }
@@ -156,6 +178,13 @@ pub struct SlashState {
     pub owners: Arc<[Id<UserMarker>]>,
     pub control_guild: Id<GuildMarker>,
     pub event_bus: EventBus,
+    pub pending: Arc<PendingActions>,
+    pub avatars: Arc<AvatarCache>,
+    pub role_icons: Arc<RoleIconCache>,
+    /// The largest file `/manage import` will read, in bytes
+    pub max_import_size: usize,
+    /// How still cards and leaderboards are encoded before upload
+    pub card_formats: CardFormats,
 }
 
 impl SlashState {
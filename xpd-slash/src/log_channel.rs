@@ -0,0 +1,171 @@
+use serde_json::Value;
+use twilight_model::{
+    channel::message::{AllowedMentions, Embed},
+    id::{
+        marker::{ChannelMarker, GuildMarker},
+        Id,
+    },
+    util::Timestamp,
+};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFooterBuilder};
+use xpd_common::{
+    AuditLogAction, AuditLogEvent, GuildAuditEvent, GuildAuditKind, DISCORD_EPOCH_SECS,
+};
+use xpd_util::LogError;
+
+use crate::SlashState;
+
+/// An audit log event which has been committed to the database, along with its ID.
+#[derive(Clone, Debug)]
+pub enum LoggedEvent {
+    Xp(i64, AuditLogEvent),
+    Guild(i64, GuildAuditEvent),
+}
+
+/// Post audit events to the guild's log channel, if it has one.
+/// This runs in the background after the events have been committed, so a missing channel or
+/// missing permissions only get logged, and never fail the command which caused the events.
+pub fn mirror_audit_events(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    events: Vec<LoggedEvent>,
+) {
+    if events.is_empty() {
+        return;
+    }
+    let task_state = state.clone();
+    state.spawn(async move {
+        let config = match xpd_database::guild_config(&task_state.db, guild_id).await {
+            Ok(config) => config,
+            Err(source) => {
+                warn!(?source, %guild_id, "Failed to fetch log channel");
+                return;
+            }
+        };
+        let Some(channel_id) = config.and_then(|config| config.log_channel) else {
+            return;
+        };
+        let embeds: Vec<Embed> = events.iter().map(LoggedEvent::embed).collect();
+        send_log_embeds(&task_state, channel_id, &embeds).await;
+    });
+}
+
+async fn send_log_embeds(state: &SlashState, channel_id: Id<ChannelMarker>, embeds: &[Embed]) {
+    match xpd_util::can_create_message(&state.cache, state.bot_id, channel_id) {
+        Ok(true) => {}
+        Ok(false) => {
+            debug!(%channel_id, "Missing permission to post in log channel");
+            return;
+        }
+        Err(source) => {
+            debug!(?source, %channel_id, "Could not check permissions for log channel");
+            return;
+        }
+    }
+    // discord allows at most 10 embeds per message
+    for chunk in embeds.chunks(10) {
+        state
+            .client
+            .create_message(channel_id)
+            .allowed_mentions(Some(&AllowedMentions::default()))
+            .embeds(chunk)
+            .await
+            .log_error("Failed to post to log channel");
+    }
+}
+
+impl LoggedEvent {
+    fn embed(&self) -> Embed {
+        let (id, timestamp, title, description) = match self {
+            Self::Xp(id, event) => (*id, event.timestamp, xp_title(event), xp_description(event)),
+            Self::Guild(id, event) => (
+                *id,
+                event.timestamp,
+                guild_title(event.kind),
+                guild_description(event),
+            ),
+        };
+        let footer = match self {
+            Self::Xp(..) => format!("Audit log entry #{id}"),
+            Self::Guild(..) => format!("Settings audit log entry #{id}"),
+        };
+        let mut embed = EmbedBuilder::new()
+            .title(title)
+            .description(description)
+            .footer(EmbedFooterBuilder::new(footer));
+        if let Ok(timestamp) = Timestamp::from_secs(timestamp + DISCORD_EPOCH_SECS) {
+            embed = embed.timestamp(timestamp);
+        }
+        embed.build()
+    }
+}
+
+const fn xp_title(event: &AuditLogEvent) -> &'static str {
+    match event.action() {
+        AuditLogAction::Add => "XP added",
+        AuditLogAction::Remove => "XP removed",
+        AuditLogAction::Set => "XP set",
+        AuditLogAction::Reset => "XP reset",
+        AuditLogAction::Import => "XP imported",
+        AuditLogAction::Revert => "Action reverted",
+    }
+}
+
+fn xp_description(event: &AuditLogEvent) -> String {
+    let change = format!(
+        "<@{}> changed <@{}>'s XP from {} to {} ({:+})",
+        event.moderator,
+        event.user_id,
+        event.previous,
+        event.previous + event.delta,
+        event.delta
+    );
+    match event.reverts {
+        Some(reverts) => format!("{change}\nReverts audit log entry #{reverts}"),
+        None => change,
+    }
+}
+
+const fn guild_title(kind: GuildAuditKind) -> &'static str {
+    match kind {
+        GuildAuditKind::ConfigUpdate => "Config updated",
+        GuildAuditKind::ConfigReset => "Config reset",
+        GuildAuditKind::RewardAdd => "Reward added",
+        GuildAuditKind::RewardRemove => "Reward removed",
+        GuildAuditKind::GuildCardUpdate => "Server card updated",
+        GuildAuditKind::GuildCardReset => "Server card reset",
+        GuildAuditKind::Import => "Levels imported",
+        GuildAuditKind::GuildReset => "Server XP reset",
+        GuildAuditKind::Restore => "Backup restored",
+    }
+}
+
+fn guild_description(event: &GuildAuditEvent) -> String {
+    let field = |value: Option<&Value>, key: &str| {
+        value
+            .and_then(|value| value.get(key))
+            .map_or_else(|| "?".to_string(), ToString::to_string)
+    };
+    let details = match event.kind {
+        GuildAuditKind::Import => format!(
+            "Imported XP for {} users from {}",
+            field(event.after.as_ref(), "users"),
+            field(event.after.as_ref(), "filename")
+        ),
+        GuildAuditKind::GuildReset => format!(
+            "Deleted XP for {} users",
+            field(event.before.as_ref(), "users")
+        ),
+        GuildAuditKind::Restore => format!(
+            "Restored XP for {} users from {}",
+            field(event.after.as_ref(), "users"),
+            field(event.after.as_ref(), "filename")
+        ),
+        _ => crate::audit::describe_change(event.before.as_ref(), event.after.as_ref()),
+    };
+    if details.is_empty() {
+        format!("By <@{}>", event.actor)
+    } else {
+        format!("By <@{}>: {details}", event.actor)
+    }
+}
@@ -1,18 +1,33 @@
+use std::fmt::Write;
+
 use mee6::LevelInfo;
 use twilight_model::{
-    http::interaction::InteractionResponseType,
+    channel::Attachment,
+    http::{attachment::Attachment as HttpAttachment, interaction::InteractionResponseType},
     id::{
         marker::{GenericMarker, GuildMarker},
         Id,
     },
 };
 use twilight_util::builder::embed::{EmbedBuilder, ImageSource};
-use xpd_common::MemberDisplayInfo;
-use xpd_database::CardUpdate;
-use xpd_rank_card::NameableItem;
-use xpd_slash_defs::card::{CardCommand, CardCommandEdit, ColorOption, GuildCardCommand};
+use xpd_common::{GuildAuditKind, MemberDisplayInfo};
+use xpd_database::{CardTheme, CardUpdate};
+use xpd_rank_card::{
+    customizations::{Color, Customizations, Palette},
+    NameableItem, CUSTOM_LAYOUT, MAX_LAYOUT_BYTES,
+};
+use xpd_slash_defs::card::{
+    CardCommand, CardCommandDeleteTheme, CardCommandEdit, CardCommandSaveTheme, CardCommandTheme,
+    ColorOption, GuildCardCommand, GuildCardCommandLayout, GuildCardCommandLayoutUpload,
+};
 
-use crate::{response::XpdInteractionResponse, Error, SlashState, UserStats, XpdInteractionData};
+use crate::{
+    audit::AuditData,
+    log_channel::{mirror_audit_events, LoggedEvent},
+    manager::fetch_attachment,
+    response::XpdInteractionResponse,
+    Error, SlashState, UserStats, XpdInteractionData,
+};
 
 pub async fn user_card_update(
     command: CardCommand,
@@ -20,8 +35,17 @@ pub async fn user_card_update(
     state: &SlashState,
     guild_id: Option<Id<GuildMarker>>,
 ) -> Result<XpdInteractionResponse, Error> {
-    let (contents, target) = match command {
-        CardCommand::Reset(_reset) => (process_reset(state, invoker.id.cast()).await?, invoker),
+    let invoker_ids: Vec<Id<GenericMarker>> = if let Some(guild_id) = guild_id {
+        vec![invoker.id.cast(), guild_id.cast()]
+    } else {
+        vec![invoker.id.cast()]
+    };
+    let (contents, target, preview) = match command {
+        CardCommand::Reset(_reset) => (
+            process_reset(state, invoker.id.cast()).await?,
+            invoker,
+            None,
+        ),
         CardCommand::Fetch(fetch) => {
             let target = fetch
                 .user
@@ -31,9 +55,28 @@ pub async fn user_card_update(
             } else {
                 process_fetch(state, &[target.id.cast()]).await
             }?;
-            (contents, target)
+            (contents, target, None)
         }
-        CardCommand::Edit(edit) => (process_edit(edit, state, invoker.id.cast()).await?, invoker),
+        CardCommand::Edit(edit) => (
+            process_edit(edit, state, invoker.id.cast()).await?,
+            invoker,
+            None,
+        ),
+        CardCommand::Theme(theme) => {
+            let (contents, preview) =
+                process_theme(theme, state, invoker.id.cast(), &invoker_ids).await?;
+            (contents, invoker, preview)
+        }
+        CardCommand::SaveTheme(save) => (
+            process_save_theme(save, state, invoker.id.cast(), &invoker_ids).await?,
+            invoker,
+            None,
+        ),
+        CardCommand::DeleteTheme(delete) => (
+            process_delete_theme(delete, state, invoker.id.cast()).await?,
+            invoker,
+            None,
+        ),
     };
     let user_stats = if let Some(id) = guild_id {
         state.get_user_stats(target.id, id).await?
@@ -42,12 +85,18 @@ pub async fn user_card_update(
         UserStats { xp: 420, rank: 69 }
     };
     let level_info = LevelInfo::new(u64::try_from(user_stats.xp).unwrap_or(0));
-    let card =
-        crate::levels::gen_card(state.clone(), target, guild_id, level_info, user_stats.rank)
-            .await?;
+    let card = crate::levels::gen_card_with(
+        state.clone(),
+        target,
+        guild_id,
+        level_info,
+        user_stats.rank,
+        preview,
+    )
+    .await?;
     let embed = EmbedBuilder::new()
         .description(contents)
-        .image(ImageSource::attachment("card.png")?)
+        .image(ImageSource::attachment(&card.filename)?)
         .build();
     Ok(XpdInteractionData::new()
         .attachments([card])
@@ -60,25 +109,74 @@ pub async fn guild_card_update(
     command: GuildCardCommand,
     state: &SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
 ) -> Result<XpdInteractionResponse, Error> {
-    let contents = match command {
-        GuildCardCommand::Reset(_reset) => process_reset(state, guild_id.cast()).await?,
-        GuildCardCommand::Fetch(_fetch) => process_fetch(state, &[guild_id.cast()]).await?,
-        GuildCardCommand::Edit(edit) => process_edit(edit, state, guild_id.cast()).await?,
+    let before = xpd_database::card_customizations(&state.db, &[guild_id.cast()]).await?;
+    let guild_ids = [guild_id.cast()];
+    let (contents, kind, preview) = match command {
+        GuildCardCommand::Reset(_reset) => (
+            process_reset(state, guild_id.cast()).await?,
+            Some(GuildAuditKind::GuildCardReset),
+            None,
+        ),
+        GuildCardCommand::Fetch(_fetch) => (process_fetch(state, &guild_ids).await?, None, None),
+        GuildCardCommand::Edit(edit) => (
+            process_edit(edit, state, guild_id.cast()).await?,
+            Some(GuildAuditKind::GuildCardUpdate),
+            None,
+        ),
+        GuildCardCommand::Theme(theme) => {
+            let (contents, preview) =
+                process_theme(theme, state, guild_id.cast(), &guild_ids).await?;
+            let kind = preview.is_none().then_some(GuildAuditKind::GuildCardUpdate);
+            (contents, kind, preview)
+        }
+        GuildCardCommand::SaveTheme(save) => (
+            process_save_theme(save, state, guild_id.cast(), &guild_ids).await?,
+            None,
+            None,
+        ),
+        GuildCardCommand::DeleteTheme(delete) => (
+            process_delete_theme(delete, state, guild_id.cast()).await?,
+            None,
+            None,
+        ),
+        GuildCardCommand::Layout(GuildCardCommandLayout::Upload(upload)) => (
+            process_layout_upload(upload, state, guild_id).await?,
+            Some(GuildAuditKind::GuildCardUpdate),
+            None,
+        ),
+        GuildCardCommand::Layout(GuildCardCommandLayout::Remove(_remove)) => (
+            process_layout_remove(state, guild_id).await?,
+            Some(GuildAuditKind::GuildCardUpdate),
+            None,
+        ),
+        GuildCardCommand::Layout(GuildCardCommandLayout::Download(_download)) => {
+            return download_layout(state, guild_id).await;
+        }
     };
+    if let Some(kind) = kind {
+        let after = xpd_database::card_customizations(&state.db, &[guild_id.cast()]).await?;
+        let before = before.map(serde_json::to_value).transpose()?;
+        let after = after.map(serde_json::to_value).transpose()?;
+        let event = audit.guild_event(guild_id, kind, before, after);
+        let id = xpd_database::add_guild_audit_event(&state.db, event.clone()).await?;
+        mirror_audit_events(state, guild_id, vec![LoggedEvent::Guild(id, event)]);
+    }
     let referenced_user = fake_user(guild_id.cast());
     let level_info = LevelInfo::new(40);
-    let card = crate::levels::gen_card(
+    let card = crate::levels::gen_card_with(
         state.clone(),
         referenced_user,
         Some(guild_id),
         level_info,
         127,
+        preview,
     )
     .await?;
     let embed = EmbedBuilder::new()
         .description(contents)
-        .image(ImageSource::attachment("card.png")?)
+        .image(ImageSource::attachment(&card.filename)?)
         .build();
     Ok(XpdInteractionData::new()
         .ephemeral(true)
@@ -107,13 +205,19 @@ fn process_edit_helper<I: NameableItem>(
 }
 
 pub const CUSTOM_CARD_NULL_SENTINEL: &str = "NULL";
+/// Largest background image upload we will download
+pub const MAX_BACKGROUND_BYTES: usize = 8 * 1024 * 1024;
+const DEFAULT_CARD_LAYOUT: &str = "classic.svg";
+/// Each user and server can save this many themes, which is as many as autocomplete can show
+pub const MAX_SAVED_THEMES: usize = 25;
 
 async fn process_edit(
     edit: CardCommandEdit,
     state: &SlashState,
     id: Id<GenericMarker>,
 ) -> Result<String, Error> {
-    let items = state.svg.config();
+    let resources = state.svg.resources();
+    let items = resources.config();
     let toy_image = process_edit_helper(&items.toys, edit.toy_image, Error::UnknownToy)?;
     let card_layout = process_edit_helper(&items.cards, edit.card_layout, Error::UnknownCard)?;
     let font = process_edit_helper(&items.fonts, edit.font, Error::UnknownFont)?;
@@ -131,14 +235,281 @@ async fn process_edit(
         font,
         toy_image,
         card_layout,
-        card_layout_default: "classic.svg".to_string(),
+        card_layout_default: DEFAULT_CARD_LAYOUT.to_string(),
+        animated_avatar: edit.animated_avatar,
+    };
+
+    // Backgrounds are cropped for the layout the card will have after this edit
+    let background = if let Some(attachment) = edit.background_image {
+        let layout = match &update.card_layout {
+            Some(layout) => layout.clone(),
+            None => xpd_database::card_customizations(&state.db, &[id])
+                .await?
+                .map_or_else(|| DEFAULT_CARD_LAYOUT.to_string(), |card| card.card_layout),
+        };
+        Some(prepare_background(state, attachment, layout).await?)
+    } else {
+        None
     };
 
     xpd_database::update_card(&state.db, id, &update).await?;
+    if let Some(background) = background {
+        xpd_database::set_card_background(&state.db, id, &background).await?;
+    } else if edit.remove_background_image == Some(true) {
+        xpd_database::delete_card_background(&state.db, id).await?;
+    }
 
     Ok("Updated card!".to_string())
 }
 
+async fn prepare_background(
+    state: &SlashState,
+    attachment: Attachment,
+    layout: String,
+) -> Result<Vec<u8>, Error> {
+    if attachment.size > MAX_BACKGROUND_BYTES as u64 {
+        return Err(Error::BackgroundImageTooBig);
+    }
+    let image = fetch_attachment(state, &attachment.url, MAX_BACKGROUND_BYTES).await?;
+    match state.svg.prepare_background(layout, image).await {
+        Ok(background) => Ok(background),
+        Err(
+            e @ (xpd_rank_card::Error::UnsupportedBackground
+            | xpd_rank_card::Error::BackgroundSize { .. }
+            | xpd_rank_card::Error::NoBackgroundSupport),
+        ) => Err(Error::InvalidBackgroundImage(e.to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn process_layout_upload(
+    upload: GuildCardCommandLayoutUpload,
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    if upload.layout.size > MAX_LAYOUT_BYTES as u64 {
+        return Err(Error::LayoutTooBig);
+    }
+    let template = fetch_attachment(state, &upload.layout.url, MAX_LAYOUT_BYTES).await?;
+    let template = String::from_utf8(template)
+        .map_err(|_| Error::InvalidLayout("Layouts must be UTF-8 text".to_string()))?;
+    match state.svg.validate_layout(template.clone()).await {
+        Ok(()) => {}
+        Err(xpd_rank_card::Error::Layout(e)) => return Err(Error::InvalidLayout(e.to_string())),
+        Err(e) => return Err(e.into()),
+    }
+    let mut txn = state.db.begin().await?;
+    xpd_database::set_guild_card_layout(txn.as_mut(), guild_id, &template).await?;
+    xpd_database::update_card(txn.as_mut(), guild_id.cast(), &layout_update(CUSTOM_LAYOUT)).await?;
+    txn.commit().await?;
+    // The new layout is compiled from the database the next time a card needs it
+    state.svg.remove_layout(guild_id.get());
+    Ok("Uploaded layout! Your server's card now uses it.".to_string())
+}
+
+async fn process_layout_remove(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    let mut txn = state.db.begin().await?;
+    if !xpd_database::delete_guild_card_layout(txn.as_mut(), guild_id).await? {
+        return Err(Error::NoCustomLayout);
+    }
+    let uses_layout = xpd_database::card_customizations(txn.as_mut(), &[guild_id.cast()])
+        .await?
+        .is_some_and(|card| card.card_layout == CUSTOM_LAYOUT);
+    if uses_layout {
+        xpd_database::update_card(
+            txn.as_mut(),
+            guild_id.cast(),
+            &layout_update(DEFAULT_CARD_LAYOUT),
+        )
+        .await?;
+    }
+    txn.commit().await?;
+    state.svg.remove_layout(guild_id.get());
+    Ok("Removed layout! Your server's card uses the default layout again.".to_string())
+}
+
+async fn download_layout(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<XpdInteractionResponse, Error> {
+    let template = xpd_database::guild_card_layout(&state.db, guild_id)
+        .await?
+        .ok_or(Error::NoCustomLayout)?;
+    let file =
+        HttpAttachment::from_bytes(format!("layout-{guild_id}.svg"), template.into_bytes(), 0);
+    Ok(XpdInteractionData::new()
+        .content("Here is your server's card layout.".to_string())
+        .attachments([file])
+        .ephemeral(true)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+/// An update which only changes the card layout
+fn layout_update(layout: &str) -> CardUpdate {
+    CardUpdate {
+        username: None,
+        rank: None,
+        level: None,
+        border: None,
+        background: None,
+        progress_background: None,
+        progress_foreground: None,
+        foreground_xp_count: None,
+        background_xp_count: None,
+        font: None,
+        toy_image: None,
+        card_layout: Some(layout.to_string()),
+        card_layout_default: DEFAULT_CARD_LAYOUT.to_string(),
+        animated_avatar: None,
+    }
+}
+
+/// A theme from the manifest, or one someone saved
+struct Theme {
+    display_name: String,
+    palette: Palette,
+    font: String,
+    card: String,
+}
+
+/// Look up a preset theme by internal name, or a saved theme by name or share code.
+async fn find_theme(
+    state: &SlashState,
+    owner: Id<GenericMarker>,
+    name: &str,
+) -> Result<Theme, Error> {
+    if let Some(theme) = state.svg.resources().theme(name) {
+        return Ok(Theme {
+            display_name: theme.display_name.clone(),
+            palette: theme.palette,
+            font: theme.font.clone(),
+            card: theme.card.clone(),
+        });
+    }
+    let saved = xpd_database::find_card_theme(&state.db, owner, name)
+        .await?
+        .ok_or(Error::UnknownTheme)?
+        .theme;
+    // Saved themes can outlive the fonts and layouts they use
+    let resources = state.svg.resources();
+    if resources.customizations_for(&saved.card_layout).is_none() {
+        return Err(Error::UnknownCard);
+    }
+    if !resources
+        .config()
+        .fonts
+        .iter()
+        .any(|font| font.internal_name == saved.font)
+    {
+        return Err(Error::UnknownFont);
+    }
+    let palette = Palette {
+        username: Color::parse(&saved.username)?,
+        rank: Color::parse(&saved.rank)?,
+        level: Color::parse(&saved.level)?,
+        border: Color::parse(&saved.border)?,
+        background: Color::parse(&saved.background)?,
+        progress_foreground: Color::parse(&saved.progress_foreground)?,
+        progress_background: Color::parse(&saved.progress_background)?,
+        background_xp_count: Color::parse(&saved.background_xp_count)?,
+        foreground_xp_count: Color::parse(&saved.foreground_xp_count)?,
+    };
+    Ok(Theme {
+        display_name: saved.name,
+        palette,
+        font: saved.font,
+        card: saved.card_layout,
+    })
+}
+
+/// Apply a theme to `id`'s card, or return the customizations it would give for a preview.
+/// `current` are the ids the card's current customizations come from.
+async fn process_theme(
+    command: CardCommandTheme,
+    state: &SlashState,
+    id: Id<GenericMarker>,
+    current: &[Id<GenericMarker>],
+) -> Result<(String, Option<Customizations>), Error> {
+    let theme = find_theme(state, id, &command.theme).await?;
+    if command.preview == Some(true) {
+        let customizations = crate::levels::get_customizations(state, current).await?;
+        let preview = customizations.with_theme(&theme.palette, &theme.font, &theme.card);
+        let contents = format!(
+            "Previewing theme `{}`. Run this again without `preview` to apply it.",
+            theme.display_name
+        );
+        return Ok((contents, Some(preview)));
+    }
+    let palette = theme.palette;
+    let update = CardUpdate {
+        username: Some(palette.username.to_string()),
+        rank: Some(palette.rank.to_string()),
+        level: Some(palette.level.to_string()),
+        border: Some(palette.border.to_string()),
+        background: Some(palette.background.to_string()),
+        progress_background: Some(palette.progress_background.to_string()),
+        progress_foreground: Some(palette.progress_foreground.to_string()),
+        foreground_xp_count: Some(palette.foreground_xp_count.to_string()),
+        background_xp_count: Some(palette.background_xp_count.to_string()),
+        font: Some(theme.font),
+        toy_image: None,
+        card_layout: Some(theme.card),
+        card_layout_default: DEFAULT_CARD_LAYOUT.to_string(),
+        animated_avatar: None,
+    };
+    xpd_database::update_card(&state.db, id, &update).await?;
+    Ok((format!("Applied theme `{}`!", theme.display_name), None))
+}
+
+async fn process_save_theme(
+    command: CardCommandSaveTheme,
+    state: &SlashState,
+    id: Id<GenericMarker>,
+    current: &[Id<GenericMarker>],
+) -> Result<String, Error> {
+    let saved = xpd_database::card_themes(&state.db, id).await?;
+    let replaces = saved.iter().any(|theme| theme.theme.name == command.name);
+    if !replaces && saved.len() >= MAX_SAVED_THEMES {
+        return Err(Error::TooManyThemes);
+    }
+    let customizations = crate::levels::get_customizations(state, current).await?;
+    let palette = customizations.palette();
+    let theme = CardTheme {
+        name: command.name,
+        username: palette.username.to_string(),
+        rank: palette.rank.to_string(),
+        level: palette.level.to_string(),
+        border: palette.border.to_string(),
+        background: palette.background.to_string(),
+        progress_foreground: palette.progress_foreground.to_string(),
+        progress_background: palette.progress_background.to_string(),
+        foreground_xp_count: palette.foreground_xp_count.to_string(),
+        background_xp_count: palette.background_xp_count.to_string(),
+        font: customizations.font,
+        card_layout: customizations.internal_name,
+    };
+    let code = xpd_database::save_card_theme(&state.db, id, &theme).await?;
+    Ok(format!(
+        "Saved theme `{}`. Anyone can use it with the code `{code}` in `/card theme`.",
+        theme.name
+    ))
+}
+
+async fn process_delete_theme(
+    command: CardCommandDeleteTheme,
+    state: &SlashState,
+    id: Id<GenericMarker>,
+) -> Result<String, Error> {
+    if xpd_database::delete_card_theme(&state.db, id, &command.name).await? {
+        Ok(format!("Deleted theme `{}`.", command.name))
+    } else {
+        Err(Error::UnknownTheme)
+    }
+}
+
 fn matches_config_item<I: NameableItem>(ci: &I, choice: &str) -> Option<String> {
     if ci.internal_name() == choice {
         Some(ci.internal_name().to_owned())
@@ -154,11 +525,19 @@ async fn process_reset(state: &SlashState, id: Id<GenericMarker>) -> Result<Stri
 
 async fn process_fetch(state: &SlashState, ids: &[Id<GenericMarker>]) -> Result<String, Error> {
     let card = crate::levels::get_customizations(state, ids).await?;
-    let defaults = state
-        .svg
+    let resources = state.svg.resources();
+    let defaults = resources
         .customizations_for(&card.internal_name)
         .ok_or(Error::UnknownCard)?;
-    Ok(card.display(defaults)?)
+    let mut contents = card.display(defaults)?;
+    let background = xpd_database::card_background(&state.db, ids).await?;
+    let background = if background.is_some() {
+        "Custom"
+    } else {
+        "None"
+    };
+    writeln!(contents, "Background image: `{background}`")?;
+    Ok(contents)
 }
 
 fn fake_user(id: Id<GenericMarker>) -> MemberDisplayInfo {
@@ -1,39 +1,82 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Write},
+    io::{BufRead, BufReader, Read, Write as _},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use flate2::{write::GzEncoder, Compression};
 use http_body_util::{BodyExt, Limited};
-use serde::{Deserialize, Serialize};
-use tokio::time::Instant;
+use mee6::LevelInfo;
+use serde::{
+    de::{DeserializeSeed, Error as _, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::{json, Value};
+use sqlx::{PgConnection, Postgres, Transaction};
+use tokio::{sync::mpsc, time::Instant};
+use twilight_cache_inmemory::InMemoryCache;
 use twilight_model::{
     channel::{message::AllowedMentions, Attachment},
     http::{attachment::Attachment as HttpAttachment, interaction::InteractionResponseType},
     id::{
-        marker::{GuildMarker, UserMarker},
+        marker::{GuildMarker, RoleMarker, UserMarker},
         Id,
     },
 };
-use twilight_util::builder::embed::EmbedBuilder;
-use xpd_slash_defs::manage::{ManageCommand, CONFIRMATION_STRING};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use xpd_common::{AuditLogEvent, GuildAuditKind, RoleReward};
+use xpd_slash_defs::manage::{ExportFormat, ManageCommand, CONFIRMATION_STRING};
+use xpd_util::snowflake_to_timestamp;
 
 use crate::{
-    dispatch::Respondable, response::XpdInteractionResponse, Error, SlashState, XpdInteractionData,
+    audit::AuditData,
+    dispatch::Respondable,
+    log_channel::{mirror_audit_events, LoggedEvent},
+    pending::{confirm_buttons, PendingAction},
+    response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData,
 };
 
 pub async fn process_manage(
     data: ManageCommand,
     guild_id: Id<GuildMarker>,
     respondable: Respondable,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
     let contents = match data {
         ManageCommand::ResetGuild(rg) => {
-            reset_guild_xp(state, guild_id, rg.confirm_message).await?
+            reset_guild_xp(state, guild_id, rg.confirm_message, audit).await?
         }
-        ManageCommand::Import(import) => import_level_data(
+        ManageCommand::Import(import) => {
+            let options = ImportOptions {
+                overwrite: import.overwrite.unwrap_or(false),
+                convert_levels: import.convert_levels.unwrap_or(false),
+                dry_run: import.dry_run.unwrap_or(false),
+            };
+            import_level_data(state, respondable, guild_id, audit, import.levels, options)?
+        }
+        ManageCommand::Export(export) => {
+            let options = ExportOptions {
+                format: export.format.unwrap_or_default(),
+                level_and_rank: export.level_and_rank.unwrap_or(false),
+                names: export.names.unwrap_or(false),
+                min_level: export.min_level.map(|v| u64::try_from(v).unwrap_or(0)),
+                role: export.role,
+                gzip: export.gzip.unwrap_or(false),
+            };
+            export_level_data(state, respondable, guild_id, options)?
+        }
+        ManageCommand::Backup(_) => crate::backup::backup_guild(state, respondable, guild_id),
+        ManageCommand::Restore(restore) => crate::backup::restore_guild(
             state,
             respondable,
             guild_id,
-            import.levels,
-            import.overwrite.unwrap_or(false),
-        )?,
-        ManageCommand::Export(_) => export_level_data(state, respondable, guild_id)?,
+            audit.invoker,
+            restore.backup,
+        ),
     };
     Ok(XpdInteractionData::new()
         .allowed_mentions(AllowedMentions::default())
@@ -44,15 +87,43 @@ pub async fn process_manage(
 
 #[derive(Deserialize, Serialize)]
 pub struct ImportUser {
+    pub id: Id<UserMarker>,
+    pub xp: i64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ExportOptions {
+    format: ExportFormat,
+    level_and_rank: bool,
+    names: bool,
+    min_level: Option<u64>,
+    role: Option<Id<RoleMarker>>,
+    gzip: bool,
+}
+
+/// One user in an export. The optional fields are the same for every user in a file,
+/// so CSV columns always line up.
+#[derive(Serialize)]
+struct ExportUser {
     id: Id<UserMarker>,
     xp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    level: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rank: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
 }
 
+/// Discord's attachment size limit for bots. Larger exports are compressed.
+const MAX_EXPORT_SIZE: usize = 10 * 1024 * 1024;
+
 #[allow(clippy::unnecessary_wraps)]
 fn export_level_data(
     state: SlashState,
     respondable: Respondable,
     guild_id: Id<GuildMarker>,
+    options: ExportOptions,
 ) -> Result<String, Error> {
     state
         .task_tracker
@@ -61,8 +132,7 @@ fn export_level_data(
             state,
             respondable,
             guild_id,
-            None,
-            false,
+            DataOperation::Export(options),
         ));
     Ok("Exporting level data, check back soon!".to_string())
 }
@@ -70,122 +140,1477 @@ fn export_level_data(
 async fn background_data_export(
     state: &SlashState,
     guild_id: Id<GuildMarker>,
+    options: ExportOptions,
 ) -> Result<XpdInteractionData, Error> {
-    let levels: Vec<ImportUser> = xpd_database::export_bulk_users(&state.db, guild_id)
-        .await?
-        .iter()
-        .map(|us| ImportUser {
-            id: us.id,
-            xp: us.xp,
-        })
-        .collect();
-    let file = serde_json::to_vec_pretty(&levels)?;
-    let attachment = HttpAttachment::from_bytes(format!("export-{guild_id}.json"), file, 0);
+    let mut levels = xpd_database::export_bulk_users(&state.db, guild_id).await?;
+    levels.sort_unstable_by(|a, b| b.xp.cmp(&a.xp).then(a.id.cmp(&b.id)));
+
+    let mut users = Vec::with_capacity(levels.len());
+    let mut rank = 0;
+    let mut last_xp = None;
+    for (index, user) in levels.iter().enumerate() {
+        // users with the same XP share a rank, like on the leaderboard
+        if last_xp != Some(user.xp) {
+            rank = index + 1;
+            last_xp = Some(user.xp);
+        }
+        let level = LevelInfo::new(u64::try_from(user.xp).unwrap_or(0)).level();
+        if options.min_level.is_some_and(|min_level| level < min_level) {
+            // everyone after this has less XP
+            break;
+        }
+        if let Some(role) = options.role {
+            if !has_role(&state.cache, guild_id, user.id, role) {
+                continue;
+            }
+        }
+        users.push(ExportUser {
+            id: user.id,
+            xp: user.xp,
+            level: options.level_and_rank.then_some(level),
+            rank: options.level_and_rank.then_some(rank),
+            display_name: options
+                .names
+                .then(|| display_name(&state.cache, guild_id, user.id).unwrap_or_default()),
+        });
+    }
+    let user_count = users.len();
+
+    let (file, compressed) =
+        tokio::task::spawn_blocking(move || encode_export(&users, options)).await??;
+    let mut filename = format!("export-{guild_id}.{}", options.format.extension());
+    let mut message = format!("Exported {user_count} users!");
+    if compressed {
+        filename.push_str(".gz");
+        message.push_str(" The file is compressed with gzip.");
+    }
+    let attachment = HttpAttachment::from_bytes(filename, file, 0);
     Ok(XpdInteractionData::new()
-        .content("Exported your level data!".to_string())
+        .content(message)
         .attachments([attachment]))
 }
 
+/// Serialize an export, and compress it if it was asked for or is too big to upload.
+/// Returns the file, and whether it was compressed.
+fn encode_export(users: &[ExportUser], options: ExportOptions) -> Result<(Vec<u8>, bool), Error> {
+    let file = match options.format {
+        ExportFormat::Json => serde_json::to_vec_pretty(users)?,
+        ExportFormat::Ndjson => {
+            let mut file = Vec::with_capacity(users.len() * 64);
+            for user in users {
+                serde_json::to_writer(&mut file, user)?;
+                file.push(b'\n');
+            }
+            file
+        }
+        ExportFormat::Csv => {
+            let mut file = Vec::with_capacity(users.len() * 64);
+            {
+                let mut csv_writer = csv::Writer::from_writer(&mut file);
+                for user in users {
+                    csv_writer.serialize(user)?;
+                }
+                csv_writer.flush()?;
+            }
+            file
+        }
+    };
+    if !options.gzip && file.len() <= MAX_EXPORT_SIZE {
+        return Ok((file, false));
+    }
+    let mut encoder = GzEncoder::new(Vec::with_capacity(file.len() / 4), Compression::best());
+    encoder.write_all(&file)?;
+    let file = encoder.finish()?;
+    if file.len() > MAX_EXPORT_SIZE {
+        return Err(Error::ExportFileTooBig);
+    }
+    Ok((file, true))
+}
+
+fn has_role(
+    cache: &InMemoryCache,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    role: Id<RoleMarker>,
+) -> bool {
+    cache
+        .member(guild_id, user_id)
+        .is_some_and(|member| member.roles().contains(&role))
+}
+
+/// A user's nickname in this server, falling back to their global display name and username
+fn display_name(
+    cache: &InMemoryCache,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> Option<String> {
+    let nick = cache
+        .member(guild_id, user_id)
+        .and_then(|member| member.nick().map(ToOwned::to_owned));
+    nick.or_else(|| {
+        let user = cache.user(user_id)?;
+        Some(
+            user.global_name
+                .clone()
+                .unwrap_or_else(|| user.name.clone()),
+        )
+    })
+}
+
 #[allow(clippy::unnecessary_wraps)]
 fn import_level_data(
     state: SlashState,
     respondable: Respondable,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     attachment: Attachment,
-    overwrite: bool,
+    options: ImportOptions,
 ) -> Result<String, Error> {
     state.clone().spawn(background_data_operation_wrapper(
         state,
         respondable,
         guild_id,
-        Some(attachment),
-        overwrite,
+        DataOperation::Import(attachment, audit, options),
     ));
-    Ok("Importing level data, check back soon!".to_string())
+    Ok("Checking level data, check back soon!".to_string())
 }
 
-const MAX_IMPORT_SIZE: usize = 1024 * 1024 * 10;
-
-async fn background_data_import(
+/// Download a user-provided attachment, refusing to read more than `max_size` bytes of it.
+pub async fn fetch_attachment(
     state: &SlashState,
-    guild_id: Id<GuildMarker>,
-    attachment: Attachment,
-    overwrite: bool,
-) -> Result<XpdInteractionData, Error> {
-    let start = Instant::now();
-
-    let request = state.http.get(attachment.url).send().await?;
+    url: &str,
+    max_size: usize,
+) -> Result<Vec<u8>, Error> {
+    let request = state.http.get(url).send().await?;
     request.error_for_status_ref()?;
 
     let raw_body = reqwest::Body::from(request);
-    let body = Limited::new(raw_body, MAX_IMPORT_SIZE)
+    let body = Limited::new(raw_body, max_size)
         .collect()
         .await
         .map_err(|_| Error::RawHttpBody)?
         .to_bytes();
+    Ok(body.to_vec())
+}
 
-    let data: Vec<ImportUser> = serde_json::from_slice(&body)?;
-    let user_count = data.len();
-    let mut txn = state.db.begin().await?;
-    for user in data {
-        if overwrite {
-            xpd_database::set_xp(txn.as_mut(), user.id, guild_id, user.xp).await?;
+/// Imports are checked and written this many entries at a time
+const IMPORT_BATCH_SIZE: usize = 5000;
+/// How often a running import tells the user how far it has got
+const IMPORT_PROGRESS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Blocking reader over a file which is still being downloaded
+struct ChunkReader {
+    chunks: mpsc::Receiver<Bytes>,
+    current: Bytes,
+}
+
+impl ChunkReader {
+    const fn new(chunks: mpsc::Receiver<Bytes>) -> Self {
+        Self {
+            chunks,
+            current: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.current = chunk,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Download an attachment into `chunks` as it arrives, refusing to read more than `max_size` bytes.
+/// Stops quietly if the reader goes away.
+async fn stream_attachment(
+    state: &SlashState,
+    url: &str,
+    max_size: usize,
+    chunks: mpsc::Sender<Bytes>,
+) -> Result<(), Error> {
+    let mut response = state.http.get(url).send().await?.error_for_status()?;
+    let mut size = 0;
+    while let Some(chunk) = response.chunk().await? {
+        size += chunk.len();
+        if size > max_size {
+            return Err(Error::ImportFileTooBig);
+        }
+        if chunks.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read an import file as it downloads, and show what it would change. Nothing is
+/// written until the invoker confirms, see [`confirm_import`].
+async fn background_data_import(
+    state: &SlashState,
+    respondable: &Respondable,
+    guild_id: Id<GuildMarker>,
+    attachment: Attachment,
+    options: ImportOptions,
+    audit: AuditData,
+) -> Result<XpdInteractionData, Error> {
+    let (chunk_tx, chunk_rx) = mpsc::channel(16);
+    let download = stream_attachment(state, &attachment.url, state.max_import_size, chunk_tx);
+    let reader = ChunkReader::new(chunk_rx);
+
+    let convert_levels = options.convert_levels;
+    let parse = tokio::task::spawn_blocking(move || parse_import(reader, convert_levels));
+    let (downloaded, parsed) = tokio::join!(download, parse);
+    // a failed download cuts the file short, which can look like a valid file
+    downloaded?;
+    let parsed = parsed??;
+    preview_import(
+        state,
+        respondable,
+        guild_id,
+        audit.invoker,
+        attachment.filename,
+        options,
+        parsed,
+    )
+    .await
+}
+
+/// Writes an import to the database a batch at a time, along with its audit log entries.
+/// [`parse_import`] already keeps only the last entry for each user, so no user is written twice.
+struct ImportWriter<'a> {
+    guild_id: Id<GuildMarker>,
+    audit: &'a AuditData,
+    overwrite: bool,
+    timestamp: i64,
+}
+
+impl<'a> ImportWriter<'a> {
+    fn new(guild_id: Id<GuildMarker>, audit: &'a AuditData, overwrite: bool) -> Self {
+        Self {
+            guild_id,
+            audit,
+            overwrite,
+            timestamp: snowflake_to_timestamp(audit.interaction),
+        }
+    }
+
+    async fn write(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[(Id<UserMarker>, i64)],
+    ) -> Result<(), Error> {
+        let events: Vec<AuditLogEvent> = if self.overwrite {
+            let ids: Vec<Id<UserMarker>> = rows.iter().map(|(id, _)| *id).collect();
+            let previous: HashMap<Id<UserMarker>, i64> =
+                xpd_database::bulk_user_xp(&mut *conn, self.guild_id, &ids)
+                    .await?
+                    .into_iter()
+                    .collect();
+            let changed: Vec<(Id<UserMarker>, i64)> = rows
+                .iter()
+                .filter(|(id, xp)| *xp > 0 || previous.get(id).is_some_and(|v| *v > 0))
+                .copied()
+                .collect();
+            xpd_database::bulk_set_xp(&mut *conn, self.guild_id, &changed).await?;
+            rows.iter()
+                .map(|(id, xp)| {
+                    let previous = previous.get(id).copied().unwrap_or(0);
+                    self.event(*id, previous, xp - previous)
+                })
+                .collect()
         } else {
-            xpd_database::add_xp(txn.as_mut(), user.id, guild_id, user.xp).await?;
+            let deltas: HashMap<Id<UserMarker>, i64> = rows.iter().copied().collect();
+            xpd_database::bulk_add_xp(&mut *conn, self.guild_id, rows)
+                .await?
+                .into_iter()
+                .map(|(id, new)| {
+                    let delta = deltas.get(&id).copied().unwrap_or(0);
+                    self.event(id, new - delta, delta)
+                })
+                .collect()
+        };
+        xpd_database::bulk_add_audit_log_events(&mut *conn, &events).await?;
+        Ok(())
+    }
+
+    const fn event(&self, user_id: Id<UserMarker>, previous: i64, delta: i64) -> AuditLogEvent {
+        AuditLogEvent {
+            guild_id: self.guild_id,
+            user_id,
+            moderator: self.audit.invoker,
+            timestamp: self.timestamp,
+            previous,
+            delta,
+            reset: false,
+            set: self.overwrite,
+            reverts: None,
+            import: true,
         }
     }
+}
+
+/// What is being imported, for the summary in the guild audit log
+struct ImportDetails {
+    filename: String,
+    source: ImportSource,
+    options: ImportOptions,
+}
+
+/// Write a confirmed import a batch at a time, all in one transaction.
+async fn apply_import(
+    state: &SlashState,
+    respondable: &Respondable,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    plan: ImportPlan,
+) -> Result<XpdInteractionData, Error> {
+    let start = Instant::now();
+    let ImportPlan { details, users } = plan;
+    let mut txn = state.db.begin().await?;
+    let writer = ImportWriter::new(guild_id, &audit, details.options.overwrite);
+    let mut last_progress = Instant::now();
+    let mut written = 0;
+    for batch in users.chunks(IMPORT_BATCH_SIZE) {
+        let rows: Vec<(Id<UserMarker>, i64)> =
+            batch.iter().map(|user| (user.id, user.xp)).collect();
+        writer.write(txn.as_mut(), &rows).await?;
+        written += batch.len();
+        if last_progress.elapsed() >= IMPORT_PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            let progress = XpdInteractionData::with_embed_text(format!(
+                "Imported {written} of {} users so far...",
+                users.len()
+            ))
+            .ephemeral(true);
+            state.send_followup(progress, respondable.token()).await;
+        }
+    }
+    finish_import(state, txn, guild_id, &audit, details, users.len(), start).await
+}
+
+/// Record an import in the guild audit log, and commit it
+async fn finish_import(
+    state: &SlashState,
+    mut txn: Transaction<'_, Postgres>,
+    guild_id: Id<GuildMarker>,
+    audit: &AuditData,
+    details: ImportDetails,
+    user_count: usize,
+    start: Instant,
+) -> Result<XpdInteractionData, Error> {
+    let summary = json!({
+        "users": user_count,
+        "overwrite": details.options.overwrite,
+        "filename": details.filename,
+        "source": details.source.name(),
+        "convert_levels": details.options.convert_levels,
+    });
+    let guild_event = audit.guild_event(guild_id, GuildAuditKind::Import, None, Some(summary));
+    let guild_event_id =
+        xpd_database::add_guild_audit_event(txn.as_mut(), guild_event.clone()).await?;
 
     txn.commit().await?;
+    // per-user import events would flood the log channel, so only the summary is posted
+    let logged = LoggedEvent::Guild(guild_event_id, guild_event);
+    mirror_audit_events(state, guild_id, vec![logged]);
 
     let seconds = start.elapsed().as_secs_f64();
     Ok(XpdInteractionData::with_embed_text(format!(
-        "Imported XP data for {user_count} users in {seconds:.2} seconds!"
+        "Imported XP data for {user_count} users from {} in {seconds:.2} seconds!",
+        details.source.name()
     )))
 }
 
+#[allow(clippy::large_enum_variant)]
+enum DataOperation {
+    Import(Attachment, AuditData, ImportOptions),
+    Export(ExportOptions),
+}
+
 async fn background_data_operation_wrapper(
     state: SlashState,
     respondable: Respondable,
     guild_id: Id<GuildMarker>,
-    attachment: Option<Attachment>,
-    overwrite: bool,
+    operation: DataOperation,
 ) {
-    let xsr = if let Some(attachment) = attachment {
-        background_data_import(&state, guild_id, attachment, overwrite)
+    let xsr = match operation {
+        DataOperation::Import(attachment, audit, options) => {
+            background_data_import(&state, &respondable, guild_id, attachment, options, audit)
+                .await
+                .unwrap_or_else(|source| {
+                    error!(?source, "Failed to import level data");
+                    XpdInteractionData::with_embed_text(format!(
+                        "Failed to import level data: {source}"
+                    ))
+                })
+        }
+        DataOperation::Export(options) => background_data_export(&state, guild_id, options)
             .await
             .unwrap_or_else(|source| {
-                error!(?source, "Failed to import level data");
+                error!(?source, "Failed to export level data");
                 XpdInteractionData::with_embed_text(format!(
-                    "Failed to import level data: {source}"
+                    "Failed to export level data: {source}"
                 ))
-            })
+            }),
+    }
+    .ephemeral(true);
+    state.send_followup(xsr, respondable.token()).await;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ImportOptions {
+    overwrite: bool,
+    /// Only report what the import would do, without offering to run it
+    dry_run: bool,
+    /// Ignore the XP in the file, and give users the XP needed for their level on our curve.
+    convert_levels: bool,
+}
+
+/// Which bot an import file came from, as detected from its shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ImportSource {
+    Experienced,
+    Mee6,
+    Lurkr,
+    Arcane,
+    Tatsu,
+    Amari,
+    Json,
+    Csv,
+}
+
+impl ImportSource {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Experienced => "an Experienced export",
+            Self::Mee6 => "a MEE6 leaderboard",
+            Self::Lurkr => "a Lurkr export",
+            Self::Arcane => "an Arcane leaderboard",
+            Self::Tatsu => "a Tatsu leaderboard",
+            Self::Amari => "an AmariBot leaderboard",
+            Self::Json => "a JSON leaderboard",
+            Self::Csv => "a CSV file",
+        }
+    }
+}
+
+// Keys are compared after lowercasing and removing everything but letters and numbers,
+// so `user_id`, `userId` and `User ID` all match `userid`.
+const ID_KEYS: &[&str] = &["id", "userid", "user", "uid", "discordid", "memberid"];
+const XP_KEYS: &[&str] = &["xp", "exp", "experience", "totalxp", "score", "points"];
+const LEVEL_KEYS: &[&str] = &["level", "lvl"];
+const MESSAGE_COUNT_KEYS: &[&str] = &["messagecount", "messages", "totalmessages"];
+
+fn normalize_key(key: &str) -> String {
+    key.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// One user's row from an import file, before we decide how much XP it is worth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ImportRecord {
+    id: Id<UserMarker>,
+    xp: Option<i64>,
+    level: Option<i64>,
+    /// Not stored, but MEE6 and Lurkr both export it, so it shouldn't be mistaken for XP
+    message_count: Option<i64>,
+}
+
+impl ImportRecord {
+    fn xp(&self, convert_levels: bool) -> Result<i64, RowProblem> {
+        let from_level = || self.level.map(level_to_xp);
+        let xp = if convert_levels {
+            from_level()
+        } else {
+            self.xp.or_else(from_level)
+        };
+        match xp {
+            Some(xp) if xp.is_negative() => Err(RowProblem::NegativeXp(self.id)),
+            Some(xp) => Ok(xp),
+            None if convert_levels => Err(RowProblem::NoLevel(self.id)),
+            None => Err(RowProblem::NoXp(self.id)),
+        }
+    }
+}
+
+/// Why one entry of an import file can't be imported
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RowProblem {
+    NotAUser,
+    NoUserId,
+    InvalidUserId(String),
+    InvalidNumber(String),
+    NegativeXp(Id<UserMarker>),
+    NoXp(Id<UserMarker>),
+    NoLevel(Id<UserMarker>),
+}
+
+impl RowProblem {
+    /// Values are cut short so that one huge cell can't fill the whole report
+    fn snippet(value: &str) -> String {
+        value.chars().take(32).collect()
+    }
+}
+
+impl Display for RowProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotAUser => f.write_str("is not a user"),
+            Self::NoUserId => f.write_str("has no user ID"),
+            Self::InvalidUserId(id) => write!(f, "`{id}` is not a user ID"),
+            Self::InvalidNumber(value) => write!(f, "`{value}` is not a whole number"),
+            Self::NegativeXp(id) => write!(f, "<@{id}> has negative XP"),
+            Self::NoXp(id) => write!(f, "<@{id}> has no XP or level"),
+            Self::NoLevel(id) => write!(f, "<@{id}> has no level to convert"),
+        }
+    }
+}
+
+/// The total XP needed to reach `level` on our (and MEE6's) curve
+fn level_to_xp(level: i64) -> i64 {
+    let level = u64::try_from(level).unwrap_or(0);
+    i64::try_from(mee6::xp_needed_for_level(level)).unwrap_or(i64::MAX)
+}
+
+/// Everything read from an import file. Entries with problems are left out of `users`.
+struct ParsedImport {
+    source: ImportSource,
+    users: Vec<ImportUser>,
+    /// 1-based entry numbers, and what was wrong with them
+    problems: Vec<(usize, RowProblem)>,
+    /// Users listed more than once, once for each extra entry. Their last entry is used.
+    duplicates: Vec<Id<UserMarker>>,
+}
+
+/// An entry of an import file, with its 1-based position in the file
+type ImportRow = (usize, Result<ImportRecord, RowProblem>);
+
+/// Read a whole import file, keeping every problem with it rather than stopping at the first.
+/// If a user is listed more than once, the last entry wins.
+fn parse_import(reader: impl Read, convert_levels: bool) -> Result<ParsedImport, Error> {
+    let mut users: HashMap<Id<UserMarker>, i64> = HashMap::new();
+    let mut problems = Vec::new();
+    let mut duplicates = Vec::new();
+    let source = read_import(reader, |(entry, row)| {
+        match row.and_then(|record| Ok((record.id, record.xp(convert_levels)?))) {
+            Ok((id, xp)) => {
+                if users.insert(id, xp).is_some() {
+                    duplicates.push(id);
+                }
+            }
+            Err(problem) => problems.push((entry, problem)),
+        }
+        true
+    })?;
+    let users = users
+        .into_iter()
+        .map(|(id, xp)| ImportUser { id, xp })
+        .collect();
+    Ok(ParsedImport {
+        source,
+        users,
+        problems,
+        duplicates,
+    })
+}
+
+/// Detect the format of an import file, and pass its entries to `sink` one at a time,
+/// without holding the whole file in memory. Reading stops early if `sink` returns false.
+fn read_import(
+    reader: impl Read,
+    mut sink: impl FnMut(ImportRow) -> bool,
+) -> Result<ImportSource, Error> {
+    let mut reader = BufReader::new(reader);
+    let mut entry = 0;
+    let mut numbered = |row| {
+        entry += 1;
+        sink((entry, row))
+    };
+    if matches!(first_byte(&mut reader)?, Some(b'[' | b'{')) {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let source = JsonImportSeed {
+            sink: &mut numbered,
+        }
+        .deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(source)
+    } else {
+        read_csv_import(reader, &mut numbered)?;
+        Ok(ImportSource::Csv)
+    }
+}
+
+/// Skip leading whitespace, and peek at the first byte after it
+fn first_byte(reader: &mut impl BufRead) -> Result<Option<u8>, std::io::Error> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        if let Some(start) = buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            let byte = buf[start];
+            reader.consume(start);
+            return Ok(Some(byte));
+        }
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+type RowSink<'a> = &'a mut dyn FnMut(Result<ImportRecord, RowProblem>) -> bool;
+
+const IMPORT_STOPPED: &str = "the import was stopped";
+
+/// The list keys used by bots which wrap their leaderboard in an object.
+/// Arcane also uses `levels`, see [`levels_source`].
+const KNOWN_LIST_KEYS: [(ImportSource, &str); 4] = [
+    (ImportSource::Mee6, "players"),
+    (ImportSource::Lurkr, "levels"),
+    (ImportSource::Tatsu, "rankings"),
+    (ImportSource::Amari, "data"),
+];
+
+/// Lurkr and Arcane both list their users under `levels`. Lurkr calls the user ID
+/// `userId`, where Arcane calls it `id`.
+fn levels_source(first_row: Option<&Value>) -> ImportSource {
+    if first_row.is_some_and(|row| row.get("userId").is_none() && row.get("id").is_some()) {
+        ImportSource::Arcane
     } else {
-        background_data_export(&state, guild_id)
+        ImportSource::Lurkr
+    }
+}
+
+/// Whether an entry of a list we don't know looks like a user, rather than
+/// something like a role reward
+fn looks_like_user(row: &Value) -> bool {
+    row.as_object().is_some_and(|fields| {
+        fields
+            .keys()
+            .any(|key| ID_KEYS.contains(&normalize_key(key).as_str()))
+    })
+}
+
+fn send_rows<'a, E: serde::de::Error>(
+    sink: RowSink<'_>,
+    rows: impl IntoIterator<Item = &'a Value>,
+) -> Result<(), E> {
+    for row in rows {
+        if !sink(json_import_record(row)) {
+            return Err(E::custom(IMPORT_STOPPED));
+        }
+    }
+    Ok(())
+}
+
+/// Streams the users out of any of the JSON layouts we understand.
+/// Each user is buffered on its own, but the list they're in never is.
+struct JsonImportSeed<'a> {
+    sink: RowSink<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for JsonImportSeed<'_> {
+    type Value = ImportSource;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<ImportSource, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for JsonImportSeed<'_> {
+    type Value = ImportSource;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("a list of users")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<ImportSource, S::Error> {
+        let mut source = None;
+        while let Some(value) = seq.next_element::<Value>()? {
+            // scrape6.py-style dumps of several MEE6 leaderboard pages
+            let players = value.get("players").and_then(Value::as_array);
+            let source = *source.get_or_insert_with(|| {
+                if players.is_some() {
+                    ImportSource::Mee6
+                } else {
+                    ImportSource::Experienced
+                }
+            });
+            match players {
+                Some(players) if source == ImportSource::Mee6 => {
+                    send_rows(&mut *self.sink, players)?;
+                }
+                _ => send_rows(&mut *self.sink, [&value])?,
+            }
+        }
+        Ok(source.unwrap_or(ImportSource::Experienced))
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<ImportSource, M::Error> {
+        let mut source = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if source.is_some() {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            let known = KNOWN_LIST_KEYS
+                .iter()
+                .find(|(_, name)| *name == key)
+                .map(|(source, _)| *source);
+            source = if let Some(known) = known {
+                let first_row = map.next_value_seed(JsonRowsSeed {
+                    sink: &mut *self.sink,
+                })?;
+                if known == ImportSource::Lurkr {
+                    Some(levels_source(first_row.as_ref()))
+                } else {
+                    Some(known)
+                }
+            } else {
+                // The first list of users wins, even if a known list comes after it,
+                // because its users have already been streamed out
+                map.next_value_seed(OtherListSeed {
+                    sink: &mut *self.sink,
+                })?
+                .then_some(ImportSource::Json)
+            };
+        }
+        source.ok_or_else(|| M::Error::custom("no list of users was found"))
+    }
+}
+
+/// Streams a known list of users out, and returns its first row,
+/// which some formats are told apart by.
+struct JsonRowsSeed<'a> {
+    sink: RowSink<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for JsonRowsSeed<'_> {
+    type Value = Option<Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<Value>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for JsonRowsSeed<'_> {
+    type Value = Option<Value>;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("a list of users")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Option<Value>, S::Error> {
+        let mut first_row = None;
+        while let Some(value) = seq.next_element::<Value>()? {
+            send_rows(&mut *self.sink, [&value])?;
+            first_row.get_or_insert(value);
+        }
+        Ok(first_row)
+    }
+}
+
+/// A value under a key we don't know. If it is a list whose first entry looks like a user,
+/// all of it is streamed out as users, and otherwise it is skipped.
+struct OtherListSeed<'a> {
+    sink: RowSink<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for OtherListSeed<'_> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<bool, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OtherListSeed<'_> {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<bool, S::Error> {
+        let Some(first_row) = seq.next_element::<Value>()? else {
+            return Ok(false);
+        };
+        if !looks_like_user(&first_row) {
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            return Ok(false);
+        }
+        send_rows(&mut *self.sink, [&first_row])?;
+        while let Some(value) = seq.next_element::<Value>()? {
+            send_rows(&mut *self.sink, [&value])?;
+        }
+        Ok(true)
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, map: M) -> Result<bool, M::Error> {
+        IgnoredAny.visit_map(map).map(|_| false)
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_unit<E>(self) -> Result<bool, E> {
+        Ok(false)
+    }
+}
+
+fn json_import_record(row: &Value) -> Result<ImportRecord, RowProblem> {
+    fn int(value: &Value) -> Option<i64> {
+        value
+            .as_i64()
+            .or_else(|| value.as_str().and_then(|v| v.trim().parse().ok()))
+    }
+    fn user_id(value: &Value) -> Option<Id<UserMarker>> {
+        let id = match value {
+            Value::Object(user) => return user.get("id").and_then(user_id),
+            Value::String(id) => id.trim().parse().ok()?,
+            value => value.as_u64()?,
+        };
+        Id::new_checked(id)
+    }
+
+    let Value::Object(fields) = row else {
+        return Err(RowProblem::NotAUser);
+    };
+    let mut ids = Vec::new();
+    let (mut xp, mut level, mut message_count) = (None, None, None);
+    for (key, value) in fields {
+        let key = normalize_key(key);
+        let key = key.as_str();
+        if ID_KEYS.contains(&key) {
+            ids.push(value);
+        } else if XP_KEYS.contains(&key) {
+            xp = xp.or_else(|| int(value));
+        } else if LEVEL_KEYS.contains(&key) {
+            level = level.or_else(|| int(value));
+        } else if MESSAGE_COUNT_KEYS.contains(&key) {
+            message_count = message_count.or_else(|| int(value));
+        }
+    }
+    // some exports have both a `user` name and a `user_id`, so use whichever is an ID
+    let id = match (ids.iter().find_map(|id| user_id(id)), ids.first()) {
+        (Some(id), _) => id,
+        (None, Some(Value::String(id))) => {
+            return Err(RowProblem::InvalidUserId(RowProblem::snippet(id)))
+        }
+        (None, Some(id)) => {
+            return Err(RowProblem::InvalidUserId(RowProblem::snippet(
+                &id.to_string(),
+            )))
+        }
+        (None, None) => return Err(RowProblem::NoUserId),
+    };
+    Ok(ImportRecord {
+        id,
+        xp,
+        level,
+        message_count,
+    })
+}
+
+/// Which column holds what in an import CSV
+struct CsvColumns {
+    id: usize,
+    xp: Option<usize>,
+    level: Option<usize>,
+    message_count: Option<usize>,
+}
+
+impl CsvColumns {
+    /// Plain `user_id,xp` files don't need a header
+    const HEADERLESS: Self = Self {
+        id: 0,
+        xp: Some(1),
+        level: None,
+        message_count: None,
+    };
+
+    fn from_header(header: &csv::StringRecord) -> Result<Self, Error> {
+        let find = |keys: &[&str]| {
+            header
+                .iter()
+                .position(|name| keys.contains(&normalize_key(name).as_str()))
+        };
+        let id = find(ID_KEYS)
+            .ok_or_else(|| Error::InvalidImport("the CSV has no user ID column".to_string()))?;
+        let columns = Self {
+            id,
+            xp: find(XP_KEYS),
+            level: find(LEVEL_KEYS),
+            message_count: find(MESSAGE_COUNT_KEYS),
+        };
+        if columns.xp.is_none() && columns.level.is_none() {
+            return Err(Error::InvalidImport(
+                "the CSV has no XP or level column".to_string(),
+            ));
+        }
+        Ok(columns)
+    }
+
+    fn record(&self, row: &csv::StringRecord) -> Result<ImportRecord, RowProblem> {
+        let int = |column: Option<usize>| -> Result<Option<i64>, RowProblem> {
+            match column.and_then(|column| row.get(column)) {
+                None | Some("") => Ok(None),
+                Some(value) => value
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| RowProblem::InvalidNumber(RowProblem::snippet(value))),
+            }
+        };
+        let raw_id = row.get(self.id).unwrap_or_default();
+        if raw_id.is_empty() {
+            return Err(RowProblem::NoUserId);
+        }
+        let id = raw_id
+            .parse()
+            .ok()
+            .and_then(Id::new_checked)
+            .ok_or_else(|| RowProblem::InvalidUserId(RowProblem::snippet(raw_id)))?;
+        Ok(ImportRecord {
+            id,
+            xp: int(self.xp)?,
+            level: int(self.level)?,
+            message_count: int(self.message_count)?,
+        })
+    }
+}
+
+fn read_csv_import(
+    reader: impl Read,
+    sink: &mut impl FnMut(Result<ImportRecord, RowProblem>) -> bool,
+) -> Result<(), Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut rows = reader.records();
+    let Some(first) = rows.next().transpose()? else {
+        return Ok(());
+    };
+    let is_header = first.get(0).is_some_and(|v| v.parse::<u64>().is_err());
+    let (columns, first) = if is_header {
+        (CsvColumns::from_header(&first)?, None)
+    } else {
+        (CsvColumns::HEADERLESS, Some(first))
+    };
+    for row in first.into_iter().map(Ok).chain(rows) {
+        if !sink(columns.record(&row?)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// An import which has been checked, and is waiting to be confirmed.
+pub struct ImportPlan {
+    details: ImportDetails,
+    users: Vec<ImportUser>,
+}
+
+/// How many leaderboard entries a preview shows before and after the import
+const PREVIEW_LEADERBOARD_SIZE: usize = 10;
+/// How many invalid entries a preview lists
+const PREVIEW_PROBLEM_COUNT: usize = 10;
+
+/// Report everything an import would do, without changing anything. If the file has no
+/// invalid entries and this isn't a dry run, the report comes with a button to run the import.
+async fn preview_import(
+    state: &SlashState,
+    respondable: &Respondable,
+    guild_id: Id<GuildMarker>,
+    invoker: Id<UserMarker>,
+    filename: String,
+    options: ImportOptions,
+    parsed: ParsedImport,
+) -> Result<XpdInteractionData, Error> {
+    let current: HashMap<Id<UserMarker>, i64> =
+        xpd_database::export_bulk_users(&state.db, guild_id)
+            .await?
+            .into_iter()
+            .map(|user| (user.id, user.xp))
+            .collect();
+    let rewards = xpd_database::guild_rewards(&state.db, guild_id).await?;
+
+    let mut after = current.clone();
+    let mut reward_changes = 0;
+    for user in &parsed.users {
+        let before = current.get(&user.id).copied().unwrap_or(0);
+        let new = if options.overwrite {
+            user.xp
+        } else {
+            before.saturating_add(user.xp)
+        };
+        if rewards_reached(&rewards, before) != rewards_reached(&rewards, new) {
+            reward_changes += 1;
+        }
+        after.insert(user.id, new);
+    }
+    let members_cached = state
+        .cache
+        .guild_members(guild_id)
+        .is_some_and(|members| !members.is_empty());
+    let not_in_guild = parsed
+        .users
+        .iter()
+        .filter(|user| state.cache.member(guild_id, user.id).is_none())
+        .count();
+
+    let mode = if options.overwrite {
+        "replacing their current XP"
+    } else {
+        "added to their current XP"
+    };
+    let mut description = format!(
+        "**File:** {filename} ({})\n**Users:** {}, {mode}\n",
+        parsed.source.name(),
+        parsed.users.len()
+    );
+    describe_import_problems(&mut description, &parsed)?;
+    if members_cached {
+        writeln!(description, "**Not in this server:** {not_in_guild} users")?;
+    } else {
+        writeln!(
+            description,
+            "**Not in this server:** unknown, this server's members aren't cached yet"
+        )?;
+    }
+    writeln!(
+        description,
+        "**Reward roles:** {reward_changes} users would reach a different reward"
+    )?;
+
+    let mut embed = EmbedBuilder::new()
+        .field(EmbedFieldBuilder::new("Top now", leaderboard_preview(current)).inline())
+        .field(EmbedFieldBuilder::new("Top after import", leaderboard_preview(after)).inline());
+    let data = if parsed.problems.is_empty() && options.dry_run {
+        writeln!(
+            description,
+            "\nThis was a dry run, so nothing has been changed. Import the file again without \
+            `dry_run` to run it."
+        )?;
+        embed = embed
+            .title("This file can be imported")
+            .description(description);
+        XpdInteractionData::new()
+    } else if parsed.problems.is_empty() {
+        writeln!(description, "\nNothing has been changed yet.")?;
+        let plan = ImportPlan {
+            details: ImportDetails {
+                filename,
+                source: parsed.source,
+                options,
+            },
+            users: parsed.users,
+        };
+        state.pending.insert(
+            respondable.id(),
+            guild_id,
+            invoker,
+            PendingAction::Import(Box::new(plan)),
+        );
+        embed = embed.title("Import this file?").description(description);
+        XpdInteractionData::new().components([confirm_buttons(respondable.id())])
+    } else {
+        writeln!(
+            description,
+            "\nFix the invalid entries and try again. Nothing has been changed."
+        )?;
+        embed = embed
+            .title("This file can't be imported")
+            .description(description);
+        XpdInteractionData::new()
+    };
+    Ok(data
+        .allowed_mentions(AllowedMentions::default())
+        .embeds([embed.build()]))
+}
+
+/// List a sample of the invalid and duplicate entries of an import file
+fn describe_import_problems(description: &mut String, parsed: &ParsedImport) -> std::fmt::Result {
+    if !parsed.problems.is_empty() {
+        writeln!(
+            description,
+            "**Invalid entries:** {}",
+            parsed.problems.len()
+        )?;
+        for (entry, problem) in parsed.problems.iter().take(PREVIEW_PROBLEM_COUNT) {
+            writeln!(description, "- Entry {entry} {problem}")?;
+        }
+        let more = parsed.problems.len().saturating_sub(PREVIEW_PROBLEM_COUNT);
+        if more > 0 {
+            writeln!(description, "- ...and {more} more")?;
+        }
+    }
+    if !parsed.duplicates.is_empty() {
+        writeln!(
+            description,
+            "**Duplicate IDs:** {} extra entries, only the last entry for each user is used",
+            parsed.duplicates.len()
+        )?;
+    }
+    Ok(())
+}
+
+/// How many reward roles a user with this much XP has reached
+fn rewards_reached(rewards: &[RoleReward], xp: i64) -> usize {
+    let level = LevelInfo::new(u64::try_from(xp).unwrap_or(0)).level();
+    let level = i64::try_from(level).unwrap_or(i64::MAX);
+    rewards
+        .iter()
+        .filter(|reward| reward.requirement <= level)
+        .count()
+}
+
+fn leaderboard_preview(levels: HashMap<Id<UserMarker>, i64>) -> String {
+    let mut levels: Vec<(Id<UserMarker>, i64)> = levels.into_iter().collect();
+    levels.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let lines: Vec<String> = levels
+        .iter()
+        .take(PREVIEW_LEADERBOARD_SIZE)
+        .enumerate()
+        .map(|(rank, (id, xp))| {
+            let level = LevelInfo::new(u64::try_from(*xp).unwrap_or(0)).level();
+            format!("{}. <@{id}> level {level}", rank + 1)
+        })
+        .collect();
+    if lines.is_empty() {
+        "Nobody".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+pub fn confirm_import(
+    state: SlashState,
+    respondable: Respondable,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    plan: ImportPlan,
+) -> String {
+    state.clone().spawn(async move {
+        let response = apply_import(&state, &respondable, guild_id, audit, plan)
             .await
             .unwrap_or_else(|source| {
-                error!(?source, "Failed to export level data");
+                error!(?source, "Failed to import level data");
                 XpdInteractionData::with_embed_text(format!(
-                    "Failed to export level data: {source}"
+                    "Failed to import level data: {source}"
                 ))
             })
-    }
-    .ephemeral(true);
-    state.send_followup(xsr, respondable.token()).await;
+            .ephemeral(true);
+        state.send_followup(response, respondable.token()).await;
+    });
+    "Importing level data, check back soon!".to_string()
 }
 
 async fn reset_guild_xp(
     state: SlashState,
     guild_id: Id<GuildMarker>,
     confirmation: String,
+    audit: AuditData,
 ) -> Result<String, Error> {
     if confirmation != CONFIRMATION_STRING {
         return Ok("Confirmation string did not match.".to_string());
     }
 
     let mut txn = state.db.begin().await?;
-    xpd_database::delete_levels_guild(&mut txn, guild_id).await?;
+    let deleted = xpd_database::delete_levels_guild(&mut txn, guild_id).await?;
     xpd_database::delete_audit_log_events_guild(&mut txn, guild_id).await?;
+    let summary = json!({ "users": deleted });
+    let guild_event = audit.guild_event(guild_id, GuildAuditKind::GuildReset, Some(summary), None);
+    let guild_event_id = xpd_database::add_guild_audit_event(&mut txn, guild_event.clone()).await?;
     txn.commit().await?;
+    let logged = LoggedEvent::Guild(guild_event_id, guild_event);
+    mirror_audit_events(&state, guild_id, vec![logged]);
 
     Ok("Done. Thank you for using Experienced.".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use bytes::Bytes;
+    use flate2::read::GzDecoder;
+    use tokio::sync::mpsc;
+    use twilight_model::id::Id;
+
+    use super::{
+        encode_export, parse_import, read_import, ChunkReader, ExportFormat, ExportOptions,
+        ExportUser, ImportSource, ImportUser, RowProblem,
+    };
+
+    fn parse(body: &str, convert_levels: bool) -> (ImportSource, Vec<(u64, i64)>) {
+        let parsed = parse_import(body.as_bytes(), convert_levels).unwrap();
+        assert_eq!(parsed.problems, []);
+        let source = parsed.source;
+        let mut users: Vec<(u64, i64)> = parsed
+            .users
+            .into_iter()
+            .map(|ImportUser { id, xp }| (id.get(), xp))
+            .collect();
+        users.sort_unstable();
+        (source, users)
+    }
+
+    #[test]
+    fn experienced() {
+        let body = r#"[{"id": "1", "xp": 50}, {"id": "2", "xp": 70}]"#;
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Experienced, vec![(1, 50), (2, 70)])
+        );
+    }
+
+    #[test]
+    fn mee6_pages() {
+        let body = r#"[
+            {"page": 0, "players": [{"id": "1", "xp": 120, "level": 1, "message_count": 8}]},
+            {"page": 1, "players": [{"id": "2", "xp": 10, "level": 0, "message_count": 1}]}
+        ]"#;
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Mee6, vec![(1, 120), (2, 10)])
+        );
+    }
+
+    #[test]
+    fn convert_levels() {
+        let body = r#"{"levels": [{"userId": "1", "xp": 9999, "level": 2}]}"#;
+        assert_eq!(parse(body, true), (ImportSource::Lurkr, vec![(1, 255)]));
+    }
+
+    #[test]
+    fn nested_user_id() {
+        let body = r#"{"rankings": [{"user": {"id": "5"}, "score": 40}]}"#;
+        assert_eq!(parse(body, false), (ImportSource::Tatsu, vec![(5, 40)]));
+    }
+
+    #[test]
+    fn lurkr() {
+        let body = r#"{
+            "guild": {"icon": null, "id": "9", "name": "A server"},
+            "isManager": false,
+            "levels": [
+                {"level": 4, "messageCount": 52, "userId": "1", "xp": 1020,
+                    "user": {"avatar": null, "id": "1", "username": "someone"}},
+                {"level": 1, "messageCount": 9, "userId": "2", "xp": 180,
+                    "user": {"avatar": null, "id": "2", "username": "other"}}
+            ],
+            "multipliers": [],
+            "roles": []
+        }"#;
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Lurkr, vec![(1, 1020), (2, 180)])
+        );
+    }
+
+    #[test]
+    fn arcane() {
+        let body = r#"{
+            "levels": [
+                {"id": "1", "username": "someone", "discriminator": "0", "avatar": null,
+                    "xp": 5120, "level": 9, "rank": 1},
+                {"id": "2", "username": "other", "discriminator": "0", "avatar": null,
+                    "xp": 640, "level": 3, "rank": 2}
+            ],
+            "roleRewards": [{"level": 5, "roleId": "3"}],
+            "bannerURL": null,
+            "totalPages": 1
+        }"#;
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Arcane, vec![(1, 5120), (2, 640)])
+        );
+    }
+
+    #[test]
+    fn amari() {
+        let body = r#"{
+            "count": 2,
+            "data": [
+                {"id": "1", "username": "someone", "exp": "2500", "level": 6, "weeklyExp": "300"},
+                {"id": "2", "username": "other", "exp": "40", "level": 0, "weeklyExp": "40"}
+            ],
+            "total_count": 2
+        }"#;
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Amari, vec![(1, 2500), (2, 40)])
+        );
+    }
+
+    #[test]
+    fn unknown_list_key() {
+        let body = r#"{"settings": {"rate": 2}, "rewards": [{"level": 5, "role": "3"}],
+            "members": [{"user_id": "1", "xp": 5}, {"user_id": "2", "xp": 6}]}"#;
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Json, vec![(1, 5), (2, 6)])
+        );
+    }
+
+    #[test]
+    fn csv_header() {
+        let body = "Rank,User ID,Username,Level,Exp\n1,7,someone,3,900\n2,8,other,1,150\n";
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Csv, vec![(7, 900), (8, 150)])
+        );
+    }
+
+    #[test]
+    fn csv_headerless_last_wins() {
+        let body = "3,10\n4,20\n3,30\n";
+        assert_eq!(
+            parse(body, false),
+            (ImportSource::Csv, vec![(3, 30), (4, 20)])
+        );
+    }
+
+    #[test]
+    fn invalid_entries() {
+        let parsed = parse_import(b"3,-10\nabc,5\n4,\n5,7\n5,8\n".as_slice(), false).unwrap();
+        assert_eq!(
+            parsed.problems,
+            vec![
+                (1, RowProblem::NegativeXp(Id::new(3))),
+                (2, RowProblem::InvalidUserId("abc".to_string())),
+                (3, RowProblem::NoXp(Id::new(4))),
+            ]
+        );
+        assert_eq!(parsed.duplicates, vec![Id::new(5)]);
+        assert_eq!(parsed.users.len(), 1);
+
+        let parsed = parse_import(br#"{"players": [{"xp": 5}, 7]}"#.as_slice(), false).unwrap();
+        assert_eq!(
+            parsed.problems,
+            vec![(1, RowProblem::NoUserId), (2, RowProblem::NotAUser)]
+        );
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(parse_import(b"name,score\nsomeone,5".as_slice(), false).is_err());
+        assert!(parse_import(b"user_id,name\n1,someone".as_slice(), false).is_err());
+        assert!(parse_import(br#""users""#.as_slice(), false).is_err());
+        assert!(parse_import(br#"{"players": 5}"#.as_slice(), false).is_err());
+    }
+
+    #[test]
+    fn streamed_in_chunks() {
+        let body = r#"{"guild": {"id": "3"}, "role_rewards": [{"rank": 1}],
+            "players": [{"id": "1", "xp": 5}, {"id": "2", "xp": 6}]}"#;
+        let (chunk_tx, chunk_rx) = mpsc::channel(body.len());
+        for chunk in body.as_bytes().chunks(3) {
+            chunk_tx.try_send(Bytes::copy_from_slice(chunk)).unwrap();
+        }
+        drop(chunk_tx);
+        let parsed = parse_import(ChunkReader::new(chunk_rx), false).unwrap();
+        assert_eq!(parsed.source, ImportSource::Mee6);
+        assert_eq!(parsed.problems, []);
+        assert_eq!(parsed.users.len(), 2);
+    }
+
+    #[test]
+    fn stops_early() {
+        for body in [
+            "1,1\n2,2\n3,3\n",
+            r#"[{"id": "1", "xp": 1}, {"id": "2", "xp": 2}, 3]"#,
+            r#"{"members": [{"id": "1", "xp": 1}, {"id": "2", "xp": 2}, 3]}"#,
+        ] {
+            let mut seen = 0;
+            let _ = read_import(body.as_bytes(), |_| {
+                seen += 1;
+                seen < 2
+            });
+            assert_eq!(seen, 2);
+        }
+    }
+
+    fn export_options(format: ExportFormat, gzip: bool) -> ExportOptions {
+        ExportOptions {
+            format,
+            level_and_rank: true,
+            names: false,
+            min_level: None,
+            role: None,
+            gzip,
+        }
+    }
+
+    fn export_users() -> [ExportUser; 2] {
+        [
+            ExportUser {
+                id: Id::new(1),
+                xp: 300,
+                level: Some(2),
+                rank: Some(1),
+                display_name: None,
+            },
+            ExportUser {
+                id: Id::new(2),
+                xp: 10,
+                level: Some(0),
+                rank: Some(2),
+                display_name: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn export_csv() {
+        let options = export_options(ExportFormat::Csv, false);
+        let (file, compressed) = encode_export(&export_users(), options).unwrap();
+        assert!(!compressed);
+        assert_eq!(
+            String::from_utf8(file).unwrap(),
+            "id,xp,level,rank\n1,300,2,1\n2,10,0,2\n"
+        );
+    }
+
+    #[test]
+    fn export_gzip_ndjson() {
+        let options = export_options(ExportFormat::Ndjson, true);
+        let (file, compressed) = encode_export(&export_users(), options).unwrap();
+        assert!(compressed);
+        let mut ndjson = String::new();
+        GzDecoder::new(file.as_slice())
+            .read_to_string(&mut ndjson)
+            .unwrap();
+        assert_eq!(
+            ndjson,
+            "{\"id\":\"1\",\"xp\":300,\"level\":2,\"rank\":1}\n\
+             {\"id\":\"2\",\"xp\":10,\"level\":0,\"rank\":2}\n"
+        );
+    }
+}
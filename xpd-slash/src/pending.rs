@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use twilight_model::{
+    channel::message::{
+        component::{ActionRow, Button, ButtonStyle},
+        Component,
+    },
+    http::interaction::InteractionResponseType,
+    id::{
+        marker::{GuildMarker, InteractionMarker, UserMarker},
+        Id,
+    },
+};
+
+use crate::{
+    audit::AuditData, backup::RestorePlan, dispatch::Respondable, manager::ImportPlan,
+    response::XpdInteractionResponse, Error, SlashState, XpdInteractionData,
+};
+
+pub const PENDING_ACTION_PREFIX: &str = "pending:";
+
+/// Interaction tokens expire after 15 minutes, after which we can no longer tell
+/// the user how their confirmed action went.
+const PENDING_ACTION_LIFETIME: Duration = Duration::from_mins(14);
+
+/// A destructive action which has been checked, and is waiting for its invoker to confirm it.
+pub enum PendingAction {
+    Restore(Box<RestorePlan>),
+    Import(Box<ImportPlan>),
+}
+
+struct Pending {
+    guild_id: Id<GuildMarker>,
+    invoker: Id<UserMarker>,
+    created: Instant,
+    action: PendingAction,
+}
+
+/// Pending actions are keyed by the ID of the interaction which created them.
+/// They only live in memory, so a restart cancels all of them.
+#[derive(Default)]
+pub struct PendingActions(Mutex<HashMap<Id<InteractionMarker>, Pending>>);
+
+impl PendingActions {
+    pub fn insert(
+        &self,
+        id: Id<InteractionMarker>,
+        guild_id: Id<GuildMarker>,
+        invoker: Id<UserMarker>,
+        action: PendingAction,
+    ) {
+        let mut pending = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        pending.retain(|_, v| v.created.elapsed() < PENDING_ACTION_LIFETIME);
+        pending.insert(
+            id,
+            Pending {
+                guild_id,
+                invoker,
+                created: Instant::now(),
+                action,
+            },
+        );
+    }
+
+    fn take(
+        &self,
+        id: Id<InteractionMarker>,
+        guild_id: Id<GuildMarker>,
+        invoker: Id<UserMarker>,
+    ) -> Result<PendingAction, Error> {
+        let mut pending = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let owned = pending
+            .get(&id)
+            .map(|entry| entry.guild_id == guild_id && entry.invoker == invoker)
+            .ok_or(Error::PendingActionExpired)?;
+        if !owned {
+            return Err(Error::NotYourPendingAction);
+        }
+        let entry = pending.remove(&id);
+        drop(pending);
+        let entry = entry.ok_or(Error::PendingActionExpired)?;
+        if entry.created.elapsed() >= PENDING_ACTION_LIFETIME {
+            return Err(Error::PendingActionExpired);
+        }
+        Ok(entry.action)
+    }
+}
+
+pub fn confirm_buttons(id: Id<InteractionMarker>) -> Component {
+    Component::ActionRow(ActionRow {
+        components: vec![
+            Component::Button(Button {
+                custom_id: Some(format!("{PENDING_ACTION_PREFIX}confirm:{id}")),
+                disabled: false,
+                emoji: None,
+                label: Some("Confirm".to_string()),
+                style: ButtonStyle::Danger,
+                url: None,
+                sku_id: None,
+            }),
+            Component::Button(Button {
+                custom_id: Some(format!("{PENDING_ACTION_PREFIX}cancel:{id}")),
+                disabled: false,
+                emoji: None,
+                label: Some("Cancel".to_string()),
+                style: ButtonStyle::Secondary,
+                url: None,
+                sku_id: None,
+            }),
+        ],
+    })
+}
+
+pub fn process_pending_component(
+    custom_id: &str,
+    guild_id: Id<GuildMarker>,
+    respondable: Respondable,
+    audit: AuditData,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let (choice, id) = custom_id
+        .strip_prefix(PENDING_ACTION_PREFIX)
+        .and_then(|v| v.split_once(':'))
+        .ok_or(Error::PendingActionExpired)?;
+    let id: Id<InteractionMarker> = id.parse().map_err(|_| Error::PendingActionExpired)?;
+    let action = state.pending.take(id, guild_id, audit.invoker)?;
+
+    let contents = if choice == "confirm" {
+        match action {
+            PendingAction::Restore(plan) => {
+                crate::backup::confirm_restore(state, respondable, guild_id, audit, *plan)
+            }
+            PendingAction::Import(plan) => {
+                crate::manager::confirm_import(state, respondable, guild_id, audit, *plan)
+            }
+        }
+    } else {
+        "Cancelled, nothing was changed.".to_string()
+    };
+    Ok(XpdInteractionData::with_embed_text(contents)
+        .components([])
+        .into_interaction_response(InteractionResponseType::UpdateMessage))
+}
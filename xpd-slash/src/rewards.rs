@@ -1,23 +1,31 @@
 use std::fmt::Write;
 
+use sqlx::PgConnection;
 use twilight_model::{
     channel::message::AllowedMentions,
     http::interaction::InteractionResponseType,
     id::{marker::GuildMarker, Id},
 };
 use twilight_util::builder::embed::EmbedBuilder;
+use xpd_common::{GuildAuditKind, RoleReward};
 use xpd_slash_defs::rewards::{RewardsCommand, RewardsCommandAdd, RewardsCommandRemove};
 
-use crate::{response::XpdInteractionResponse, Error, SlashState, XpdInteractionData};
+use crate::{
+    audit::AuditData,
+    log_channel::{mirror_audit_events, LoggedEvent},
+    response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData,
+};
 
 pub async fn process_rewards(
     cmd: RewardsCommand,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
     let contents = match cmd {
-        RewardsCommand::Add(add) => process_rewards_add(add, state, guild_id).await,
-        RewardsCommand::Remove(remove) => process_rewards_rm(remove, state, guild_id).await,
+        RewardsCommand::Add(add) => process_rewards_add(add, state, guild_id, audit).await,
+        RewardsCommand::Remove(remove) => process_rewards_rm(remove, state, guild_id, audit).await,
         RewardsCommand::List(_list) => process_rewards_list(state, guild_id).await,
     }?;
     Ok(XpdInteractionData::new()
@@ -31,8 +39,15 @@ async fn process_rewards_add(
     options: RewardsCommandAdd,
     state: SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
 ) -> Result<String, Error> {
-    xpd_database::add_reward_role(&state.db, guild_id, options.level, options.role.id).await?;
+    let mut txn = state.db.begin().await?;
+    let before = xpd_database::guild_rewards(txn.as_mut(), guild_id).await?;
+    xpd_database::add_reward_role(txn.as_mut(), guild_id, options.level, options.role.id).await?;
+    let logged =
+        log_rewards_change(&mut txn, guild_id, audit, GuildAuditKind::RewardAdd, before).await?;
+    txn.commit().await?;
+    mirror_audit_events(&state, guild_id, vec![logged]);
     state.invalidate_rewards(guild_id).await;
     Ok(format!(
         "Added role reward <@&{}> at level {}!",
@@ -44,9 +59,21 @@ async fn process_rewards_rm(
     options: RewardsCommandRemove,
     state: SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
 ) -> Result<String, Error> {
-    match xpd_database::delete_reward_role(&state.db, guild_id, options.level, options.role).await {
+    let mut txn = state.db.begin().await?;
+    let before = xpd_database::guild_rewards(txn.as_mut(), guild_id).await?;
+    match xpd_database::delete_reward_role(txn.as_mut(), guild_id, options.level, options.role)
+        .await
+    {
         Ok(count) => {
+            let mut logged = Vec::with_capacity(1);
+            if count > 0 {
+                let kind = GuildAuditKind::RewardRemove;
+                logged.push(log_rewards_change(&mut txn, guild_id, audit, kind, before).await?);
+            }
+            txn.commit().await?;
+            mirror_audit_events(&state, guild_id, logged);
             state.invalidate_rewards(guild_id).await;
             let pluralizer = if count == 1 { "" } else { "s" };
             Ok(format!("Deleted {count} role reward{pluralizer}."))
@@ -58,6 +85,23 @@ async fn process_rewards_rm(
     }
 }
 
+async fn log_rewards_change(
+    conn: &mut PgConnection,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    kind: GuildAuditKind,
+    mut before: Vec<RoleReward>,
+) -> Result<LoggedEvent, Error> {
+    let mut after = xpd_database::guild_rewards(&mut *conn, guild_id).await?;
+    before.sort_by_key(|a| a.requirement);
+    after.sort_by_key(|a| a.requirement);
+    let before = Some(serde_json::to_value(before)?);
+    let after = Some(serde_json::to_value(after)?);
+    let event = audit.guild_event(guild_id, kind, before, after);
+    let id = xpd_database::add_guild_audit_event(conn, event.clone()).await?;
+    Ok(LoggedEvent::Guild(id, event))
+}
+
 async fn process_rewards_list(
     state: SlashState,
     guild_id: Id<GuildMarker>,
@@ -68,7 +112,7 @@ async fn process_rewards_list(
     }
     let mut data = String::new();
 
-    roles.sort_by(|a, b| a.requirement.cmp(&b.requirement));
+    roles.sort_by_key(|a| a.requirement);
 
     for role in roles {
         writeln!(